@@ -1,24 +1,58 @@
 use lib::buffer::{Buffer, Dimensions};
+use lib::camera::Camera;
+use lib::integrator::PathTracing;
+use lib::sampling::{Filter, SamplePattern};
+use lib::scene::Scene;
+use lib::tone_mapping::{PostProcessing, ToneMapping};
 use lib::vector_math::Vec3;
+use lib::RenderSettings;
 use png::Encoder;
-use std::{fs::File, io};
+use std::error::Error;
+use std::fs::File;
+use std::sync::Arc;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1024;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let scene = Arc::new(Scene::load("scene.toml")?);
 
-fn main() -> io::Result<()> {
     let output = File::create("test.png")?;
-    let mut encoder = Encoder::new(output, lib::WIDTH, lib::HEIGHT);
+    let mut encoder = Encoder::new(output, WIDTH, HEIGHT);
 
     encoder.set_color(png::ColorType::Rgb);
     encoder.set_depth(png::BitDepth::Eight);
 
-    let mut buffer = Buffer::new(Dimensions(lib::WIDTH, lib::HEIGHT), lib::CHANNELS);
+    let mut buffer = Buffer::new(Dimensions(WIDTH, HEIGHT), lib::CHANNELS);
     buffer.clear();
-    lib::draw(
-        &mut buffer,
+    let camera = Camera::new(
         Vec3::new(0.0, 1.2, 2.0),
-        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.2, 1.0),
+        WIDTH,
+        HEIGHT,
     );
+    lib::draw(&mut buffer, &scene, &camera, &render_settings());
 
     let mut writer = encoder.write_header()?;
-    writer.write_image_data(buffer.get_data_ref())?;
+    writer.write_image_data(&post_processing().encode(&buffer))?;
     Ok(())
 }
+
+fn render_settings() -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 16,
+        sample_pattern: SamplePattern::Sobol,
+        filter: Filter::Gaussian {
+            radius: 1.5,
+            alpha: 2.0,
+        },
+        integrator: Arc::new(PathTracing { max_bounces: 6 }),
+    }
+}
+
+fn post_processing() -> PostProcessing {
+    PostProcessing {
+        exposure: 0.0,
+        tone_mapping: ToneMapping::Aces,
+    }
+}
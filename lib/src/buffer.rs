@@ -1,82 +1,22 @@
-use std::ops::{Add, Mul};
+use crate::color::Color;
+
 pub struct Point(pub u32, pub u32);
 
 pub struct Dimensions(pub u32, pub u32);
 
+/// Frame buffer of linear light colors, only turned into bytes when written out.
 pub struct Buffer {
-    data: Vec<u8>,
+    data: Vec<f32>,
     width: u32,
     height: u32,
 }
 
-#[derive(PartialEq, Debug, Clone)]
-pub struct Rgb {
-    values: [u8; 3],
-}
-
-impl Rgb {
-    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
-        Rgb { values: [r, g, b] }
-    }
-
-    pub fn as_bites(&self) -> (u8, u8, u8) {
-        match self.values {
-            [r, g, b] => (r, g, b),
-        }
-    }
-}
-
-impl Mul<f32> for Rgb {
-    type Output = Self;
-    fn mul(mut self, rhs: f32) -> Self::Output {
-        for i in &mut self.values {
-            *i = (*i as f32 * rhs) as u8;
-        }
-        self
-    }
-}
-
-impl Add for Rgb {
-    type Output = Self;
-    fn add(self, v2: Self) -> Self {
-        let mut new_values = [0_u8; 3];
-        for (idx, (v1_i, v2_i)) in self.values.iter().zip(v2.values).enumerate() {
-            let value_with_overflow = *v1_i as u16 + v2_i as u16;
-            new_values[idx] = value_with_overflow.min(255) as u8;
-        }
-        Self { values: new_values }
-    }
-}
-
-#[cfg(test)]
-mod rgb_test {
-    use super::*;
-
-    #[test]
-    fn rgb_multiplication() {
-        assert_eq!(Rgb::new(100, 10, 200) * 0.8, Rgb::new(80, 8, 160));
-    }
-
-    #[test]
-    fn rgb_multiplication_out_of_bound() {
-        assert_eq!(Rgb::new(100, 10, 200) * 2.0, Rgb::new(200, 20, 255));
-    }
-
-    #[test]
-    fn rgb_addition() {
-        assert_eq!(
-            Rgb::new(100, 10, 200) + Rgb::new(100, 10, 200),
-            Rgb::new(200, 20, 255)
-        );
-    }
-}
-
 impl Buffer {
     pub fn new(dimensions: Dimensions, depth: u8) -> Buffer {
-        let mut data: Vec<u8> =
+        let mut data: Vec<f32> =
             Vec::with_capacity((dimensions.0 * dimensions.1 * depth as u32) as usize);
         for _i in 0..data.capacity() {
-            data.push(0);
+            data.push(0.0);
         }
         Buffer {
             data,
@@ -87,34 +27,47 @@ impl Buffer {
 
     pub fn clear(&mut self) {
         for i in 0..self.data.len() {
-            self.data[i] = 0;
+            self.data[i] = 0.0;
         }
     }
 
-    pub fn set(&mut self, point: &Point, rgb: &Rgb) {
-        let first_byte = ((point.1 * self.width + point.0) * 3) as usize;
-        self.data[first_byte] = rgb.values[0];
-        self.data[first_byte + 1] = rgb.values[1];
-        self.data[first_byte + 2] = rgb.values[2];
+    pub fn set(&mut self, point: &Point, color: &Color) {
+        let first_value = ((point.1 * self.width + point.0) * 3) as usize;
+        let (r, g, b) = color.as_channels();
+        self.data[first_value] = r;
+        self.data[first_value + 1] = g;
+        self.data[first_value + 2] = b;
     }
 
-    pub fn get(&self, point: &Point) -> Rgb {
-        let first_byte = ((point.1 * self.width + point.0) * 3) as usize;
-        Rgb::new(
-            self.data[first_byte],
-            self.data[first_byte + 1],
-            self.data[first_byte + 2],
+    pub fn get(&self, point: &Point) -> Color {
+        let first_value = ((point.1 * self.width + point.0) * 3) as usize;
+        Color::new(
+            self.data[first_value],
+            self.data[first_value + 1],
+            self.data[first_value + 2],
         )
     }
 
-    pub fn set_raw_value(&mut self, idx: usize, value: u8) {
+    pub fn set_raw_value(&mut self, idx: usize, value: f32) {
         self.data[idx] = value;
     }
 
-    pub fn get_data_ref(&self) -> &[u8] {
+    pub fn get_data_ref(&self) -> &[f32] {
         self.data.as_slice()
     }
 
+    /// 8-bit RGB rows ready to be handed to an image encoder.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b) = self.get(&Point(x, y)).to_bytes();
+                bytes.extend([r, g, b]);
+            }
+        }
+        bytes
+    }
+
     pub fn width(&self) -> &u32 {
         &self.width
     }
@@ -123,3 +76,18 @@ impl Buffer {
         &self.height
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_values_above_one() {
+        let mut buffer = Buffer::new(Dimensions(2, 1), 3);
+
+        buffer.set(&Point(1, 0), &Color::new(4.0, 0.5, 0.0));
+
+        assert_eq!(buffer.get(&Point(1, 0)), Color::new(4.0, 0.5, 0.0));
+        assert_eq!(buffer.to_bytes(), vec![0, 0, 0, 255, 128, 0]);
+    }
+}
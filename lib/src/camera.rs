@@ -0,0 +1,219 @@
+use crate::sampling::concentric_disk;
+use crate::vector_math::Vec3;
+
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub horizontal_fov: f32,
+    pub width: u32,
+    pub height: u32,
+    // thin lens model, zero aperture is a pinhole camera with everything in focus
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, target: Vec3, width: u32, height: u32) -> Camera {
+        Camera {
+            position,
+            target,
+            up: Vec3::new(0.0, 1.0, 0.0),
+            horizontal_fov: 90.0,
+            width,
+            height,
+            aperture_radius: 0.0,
+            focus_distance: (target - position).magnitude(),
+        }
+    }
+
+    // orthonormal basis of the camera: x points right, y up and z backwards from the target
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let camera_z = (self.position - self.target).normalize();
+
+        let mut camera_x = (-camera_z).cross_product(&self.up);
+        if camera_x.magnitude() < 1e-6 {
+            // looking along the up vector, any horizontal direction will do as long as it
+            // stays consistent, so borrow the axis the view direction is least aligned with
+            let (x, y, z) = camera_z.as_coords();
+            let fallback_up = if x.abs() <= y.abs() && x.abs() <= z.abs() {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else if y.abs() <= z.abs() {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(0.0, 0.0, -1.0)
+            };
+            camera_x = (-camera_z).cross_product(&fallback_up);
+        }
+        let camera_x = camera_x.normalize();
+        let camera_y = camera_z.cross_product(&camera_x);
+
+        (camera_x, camera_y, camera_z)
+    }
+
+    pub(crate) fn viewport(&self) -> Viewport {
+        let vertical_fov = (self.height as f32 * (self.horizontal_fov / 2.0).to_radians().tan()
+            / self.width as f32)
+            .atan()
+            .to_degrees()
+            * 2.0;
+
+        // virtual screen size in world coordinates
+        let in_world_screen_width = 2.0 * (self.horizontal_fov / 2.0).to_radians().tan();
+        let in_world_screen_height = 2.0 * (vertical_fov / 2.0).to_radians().tan();
+
+        let (camera_x, camera_y, camera_z) = self.basis();
+
+        // size of a pixel in the output translated to world coordinates
+        let pixel_x_offset = camera_x * (in_world_screen_width / (self.width as f32));
+        let pixel_y_offset = camera_y * (-in_world_screen_height / (self.height as f32));
+
+        // top left of the virtual screen
+        let top_left = self.position - camera_x * (in_world_screen_width / 2.0)
+            + camera_y * (in_world_screen_height / 2.0)
+            - camera_z;
+
+        Viewport {
+            origin: self.position,
+            top_left,
+            pixel_x_offset,
+            pixel_y_offset,
+            lens_x: camera_x * self.aperture_radius,
+            lens_y: camera_y * self.aperture_radius,
+            focus_distance: self.focus_distance,
+        }
+    }
+}
+
+// virtual screen one unit in front of the camera through which the primary rays are shot
+#[derive(Clone, Copy)]
+pub(crate) struct Viewport {
+    origin: Vec3,
+    top_left: Vec3,
+    pixel_x_offset: Vec3,
+    pixel_y_offset: Vec3,
+    lens_x: Vec3,
+    lens_y: Vec3,
+    focus_distance: f32,
+}
+
+impl Viewport {
+    // mapping between the pixel on the png and the vector looking at its representation
+    // on the virtual screen from the pov of the camera
+    pub fn direction_through(&self, x: f32, y: f32) -> Vec3 {
+        self.pixel_x_offset * x + self.pixel_y_offset * y + self.top_left - self.origin
+    }
+
+    // angle a pixel covers as seen from the camera, exact in the middle of the screen
+    pub fn pixel_spread(&self) -> f32 {
+        self.pixel_x_offset.magnitude()
+    }
+
+    // primary ray through the pixel starting from a point on the lens, `lens_sample` is
+    // uniformly distributed in 0..1 on both axes
+    pub fn ray_through(&self, x: f32, y: f32, lens_sample: (f32, f32)) -> (Vec3, Vec3) {
+        // the screen is one unit away, so this lands on the plane that is in focus
+        let point_in_focus = self.origin + self.direction_through(x, y) * self.focus_distance;
+
+        let (u, v) = concentric_disk(lens_sample);
+        let ray_origin = self.origin + self.lens_x * u + self.lens_y * v;
+
+        (ray_origin, point_in_focus - ray_origin)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    fn capped(vector: Vec3) -> (f32, f32, f32) {
+        let (x, y, z) = vector.as_coords();
+        (cap_float(x), cap_float(y), cap_float(z))
+    }
+
+    #[test]
+    fn center_of_the_screen_looks_at_target() {
+        let camera = Camera::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(1.0, 2.0, -7.0),
+            200,
+            100,
+        );
+
+        let direction = camera.viewport().direction_through(100.0, 50.0);
+
+        assert_eq!(capped(direction.normalize()), (0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn top_left_pixel_is_up_and_to_the_left() {
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            200,
+            100,
+        );
+
+        let direction = camera.viewport().direction_through(0.0, 0.0);
+
+        // 90 degrees horizontal fov puts the screen edges at 45 degrees
+        assert_eq!(capped(direction), (-1.0, 0.5, -1.0));
+    }
+
+    #[test]
+    fn lens_rays_converge_on_the_focus_plane() {
+        let mut camera = Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            100,
+            100,
+        );
+        camera.aperture_radius = 0.5;
+        camera.focus_distance = 4.0;
+        let viewport = camera.viewport();
+
+        for lens_sample in [(0.0, 0.0), (0.9, 0.1), (0.5, 0.5), (0.25, 1.0)] {
+            let (origin, direction) = viewport.ray_through(30.0, 60.0, lens_sample);
+            let point_in_focus = origin + direction;
+
+            assert!((origin - camera.position).magnitude() <= 0.5 + 1e-6);
+            assert_eq!(capped(point_in_focus), (-1.6, -0.8, -4.0));
+        }
+    }
+
+    #[test]
+    fn looking_straight_down_keeps_a_valid_basis() {
+        let camera = Camera::new(
+            Vec3::new(0.0, 10.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            100,
+            100,
+        );
+
+        let (x, y, z) = camera.basis();
+
+        assert_eq!(capped(z), (0.0, 1.0, 0.0));
+        assert_eq!(cap_float(x.magnitude()), 1.0);
+        assert_eq!(cap_float(y.magnitude()), 1.0);
+        assert_eq!(cap_float(x * y), 0.0);
+        assert_eq!(cap_float(x * z), 0.0);
+        assert_eq!(cap_float(y * z), 0.0);
+    }
+
+    #[test]
+    fn up_vector_rolls_the_camera() {
+        let mut camera = Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            100,
+            100,
+        );
+        camera.up = Vec3::new(1.0, 0.0, 0.0);
+
+        let (x, y, _) = camera.basis();
+
+        assert_eq!(capped(y), (1.0, 0.0, 0.0));
+        assert_eq!(capped(x), (0.0, -1.0, 0.0));
+    }
+}
@@ -0,0 +1,151 @@
+use std::ops::{Add, AddAssign, Mul};
+
+/// Linear light color, channels are not clamped so they can go over 1.0 for bright highlights.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Color {
+    values: [f32; 3],
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Color {
+        Color { values: [r, g, b] }
+    }
+
+    pub const fn from_bytes(r: u8, g: u8, b: u8) -> Color {
+        Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    /// Colors picked in an image editor or a color picker are sRGB encoded, this undoes the
+    /// encoding so they can be used for shading.
+    pub fn from_srgb_bytes(r: u8, g: u8, b: u8) -> Color {
+        Color::from_bytes(r, g, b).map(srgb_to_linear)
+    }
+
+    pub fn as_channels(&self) -> (f32, f32, f32) {
+        match self.values {
+            [r, g, b] => (r, g, b),
+        }
+    }
+
+    /// 8-bit representation for output, anything out of the 0..1 range is clipped.
+    pub fn to_bytes(&self) -> (u8, u8, u8) {
+        match self
+            .values
+            .map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8)
+        {
+            [r, g, b] => (r, g, b),
+        }
+    }
+
+    pub fn luminance(&self) -> f32 {
+        let [r, g, b] = self.values;
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    pub fn max_channel(&self) -> f32 {
+        let [r, g, b] = self.values;
+        r.max(g).max(b)
+    }
+
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Color {
+        Color {
+            values: self.values.map(f),
+        }
+    }
+}
+
+/// sRGB transfer function, `value` is in 0..1.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Inverse of `srgb_to_linear`, `value` is in 0..1.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Self;
+    fn mul(self, rhs: f32) -> Self::Output {
+        self.map(|channel| channel * rhs)
+    }
+}
+
+impl Mul<Color> for Color {
+    type Output = Self;
+    fn mul(self, rhs: Color) -> Self::Output {
+        let [r1, g1, b1] = self.values;
+        let [r2, g2, b2] = rhs.values;
+        Color::new(r1 * r2, g1 * g2, b1 * b2)
+    }
+}
+
+impl Add for Color {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let [r1, g1, b1] = self.values;
+        let [r2, g2, b2] = rhs.values;
+        Color::new(r1 + r2, g1 + g2, b1 + b2)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiplication_keeps_energy_above_one() {
+        assert_eq!(Color::new(0.4, 0.1, 0.8) * 2.0, Color::new(0.8, 0.2, 1.6));
+    }
+
+    #[test]
+    fn addition_does_not_saturate() {
+        assert_eq!(
+            Color::new(0.5, 0.1, 0.8) + Color::new(0.5, 0.1, 0.8),
+            Color::new(1.0, 0.2, 1.6)
+        );
+    }
+
+    #[test]
+    fn component_wise_multiplication() {
+        assert_eq!(
+            Color::new(0.5, 1.0, 2.0) * Color::new(0.5, 0.0, 2.0),
+            Color::new(0.25, 0.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        assert_eq!(Color::from_bytes(100, 10, 200).to_bytes(), (100, 10, 200));
+    }
+
+    #[test]
+    fn srgb_transfer_function_round_trips() {
+        for value in [0.0, 0.002, 0.2, 0.5, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn conversion_to_bytes_clips_out_of_range_values() {
+        assert_eq!(Color::new(2.0, -1.0, 0.5).to_bytes(), (255, 0, 128));
+    }
+}
@@ -1,11 +1,64 @@
-use crate::buffer::Rgb;
+use crate::color::Color;
 
-pub const DEBUG_PINK: Rgb = Rgb::new(200, 50, 200);
+pub const DEBUG_PINK: Color = Color::from_bytes(200, 50, 200);
 
 #[cfg(test)]
 pub mod test {
+    use std::sync::Arc;
+
+    pub use rand::rngs::SmallRng;
+    pub use rand::{Rng, SeedableRng};
+
+    use crate::color::Color;
+    use crate::scene::material::{Albedo, Material, Materials, SolidColor};
+    use crate::scene::rect::Rect;
+    use crate::scene::Object;
+    use crate::vector_math::Vec3;
 
     pub fn cap_float(value: f32) -> f32 {
         (value * 10.0).round() / 10.0
     }
+
+    // Lambertian floor of `color` reflecting all the light it gets, 100 units wide around the
+    // y axis at `height` and facing up
+    pub fn floor(height: f32, color: Color) -> Object {
+        let mut material = Materials::solid_color(color);
+        material.albedo = Albedo {
+            diffuse: 1.0,
+            ..Albedo::default()
+        };
+        Object {
+            surface: Box::new(Rect::new(
+                Vec3::new(-50.0, height, 50.0),
+                Vec3::new(-50.0, height, -50.0),
+                Vec3::new(50.0, height, -50.0),
+                Vec3::new(50.0, height, 50.0),
+            )),
+            material,
+        }
+    }
+
+    // thin glass that lets most of the light through and only faintly reflects it
+    pub fn glass() -> Material {
+        Material {
+            diffuse_color: Arc::new(SolidColor(Color::from_srgb_bytes(10, 10, 10))),
+            shininess: 200.0,
+            albedo: Albedo {
+                specular: 0.6,
+                refraction: 0.9,
+                ..Albedo::default()
+            },
+            refractive_index: 1.8,
+            ..Materials::solid_color(Color::BLACK)
+        }
+    }
+
+    // random point in a cube `scale` wide around the origin
+    pub fn random_vec3(random: &mut SmallRng, scale: f32) -> Vec3 {
+        Vec3::new(
+            (random.gen::<f32>() - 0.5) * scale,
+            (random.gen::<f32>() - 0.5) * scale,
+            (random.gen::<f32>() - 0.5) * scale,
+        )
+    }
 }
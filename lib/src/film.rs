@@ -0,0 +1,107 @@
+use crate::buffer::{Buffer, Point};
+use crate::color::Color;
+use crate::sampling::Filter;
+
+/// Floating point accumulation of filtered samples, written out to a `Buffer` once
+/// all samples are in.
+pub struct Film {
+    width: u32,
+    height: u32,
+    filter: Filter,
+    // weighted sum of the samples' colors and the sum of their weights per pixel
+    pixels: Vec<(Color, f32)>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Film {
+        Film {
+            width,
+            height,
+            filter,
+            pixels: vec![(Color::BLACK, 0.0); (width * height) as usize],
+        }
+    }
+
+    /// Adds the sample to every pixel within the filter radius, `x` and `y` are continuous
+    /// image coordinates where the center of pixel (0, 0) is at (0.5, 0.5).
+    pub fn add_sample(&mut self, x: f32, y: f32, color: Color) {
+        let radius = self.filter.radius();
+        let first_x = (x - radius - 0.5).ceil().max(0.0) as u32;
+        let first_y = (y - radius - 0.5).ceil().max(0.0) as u32;
+        let last_x = ((x + radius - 0.5).floor() as i64).min(self.width as i64 - 1);
+        let last_y = ((y + radius - 0.5).floor() as i64).min(self.height as i64 - 1);
+
+        for pixel_y in first_y as i64..=last_y {
+            for pixel_x in first_x as i64..=last_x {
+                let weight = self
+                    .filter
+                    .weight(x - (pixel_x as f32 + 0.5), y - (pixel_y as f32 + 0.5));
+                if weight <= 0.0 {
+                    continue;
+                }
+                let (sum, total_weight) =
+                    &mut self.pixels[(pixel_y as u32 * self.width + pixel_x as u32) as usize];
+                *sum += color * weight;
+                *total_weight += weight;
+            }
+        }
+    }
+
+    pub fn get(&self, point: &Point) -> Color {
+        let (sum, total_weight) = self.pixels[(point.1 * self.width + point.0) as usize];
+        if total_weight > 0.0 {
+            sum.map(|channel| channel / total_weight)
+        } else {
+            Color::BLACK
+        }
+    }
+
+    pub fn write_to(&self, buffer: &mut Buffer) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let point = Point(x, y);
+                buffer.set(&point, &self.get(&point));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn box_filter_averages_samples_within_pixel() {
+        let mut film = Film::new(2, 2, Filter::Box { radius: 0.5 });
+
+        film.add_sample(0.25, 0.25, Color::new(1.0, 0.0, 0.0));
+        film.add_sample(0.75, 0.75, Color::new(2.0, 0.0, 0.0));
+        film.add_sample(1.5, 1.5, Color::new(0.0, 0.5, 0.0));
+
+        assert_eq!(film.get(&Point(0, 0)), Color::new(1.5, 0.0, 0.0));
+        assert_eq!(film.get(&Point(1, 1)), Color::new(0.0, 0.5, 0.0));
+        assert_eq!(film.get(&Point(1, 0)), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn wide_filter_spreads_sample_to_neighbours() {
+        let mut film = Film::new(3, 3, Filter::Tent { radius: 1.5 });
+
+        film.add_sample(1.5, 1.5, Color::new(0.5, 0.0, 0.0));
+
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(film.get(&Point(x, y)), Color::new(0.5, 0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn samples_outside_the_image_are_clipped() {
+        let mut film = Film::new(2, 2, Filter::Tent { radius: 2.0 });
+
+        film.add_sample(-0.5, 2.5, Color::new(0.1, 0.2, 0.3));
+
+        assert_eq!(film.get(&Point(0, 1)), Color::new(0.1, 0.2, 0.3));
+    }
+}
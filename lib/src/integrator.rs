@@ -0,0 +1,562 @@
+mod ambient_occlusion;
+mod debug;
+mod microfacet;
+mod path_tracing;
+mod whitted;
+
+pub use ambient_occlusion::AmbientOcclusion;
+pub use debug::Debug;
+pub use path_tracing::PathTracing;
+pub use whitted::Whitted;
+
+use std::ptr;
+
+use crate::color::Color;
+use crate::common::DEBUG_PINK;
+use crate::ray_tracing::{self, Intersection};
+use crate::sampling::Sampler;
+use crate::scene::material::{SurfaceParameters, TextureQuery};
+use crate::scene::sphere::Sphere;
+use crate::scene::{Object, Scene, Surface};
+use crate::vector_math::Vec3;
+use microfacet::Microfacet;
+
+/// Ray standing for a narrow cone, as wide as a pixel for camera rays, so texture lookups know
+/// how much of a texture they have to cover.
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// angle in radians the cone widens by
+    pub spread: f32,
+    /// width of the cone at the origin
+    pub width: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            spread: 0.0,
+            width: 0.0,
+        }
+    }
+
+    // width of the cone `distance` away from the origin
+    fn width_at(&self, distance: f32) -> f32 {
+        self.width + self.spread * distance
+    }
+
+    // the cone carrying on from a point `distance` along this one in a new direction
+    fn continued(&self, origin: Vec3, direction: Vec3, distance: f32) -> Ray {
+        Ray {
+            origin,
+            direction,
+            spread: self.spread,
+            width: self.width_at(distance),
+        }
+    }
+}
+
+/// Strategy for computing the light arriving at the camera along a primary ray. `draw` only
+/// deals with pixels and samples, everything about shading happens behind this trait.
+pub trait Integrator: Send + Sync {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Color;
+}
+
+const SPEC_BASE_COLOR: Color = Color::WHITE;
+// shadow rays taken towards every emissive object per shaded point
+const EMITTER_SAMPLES: u32 = 4;
+// surfaces a shadow ray passes through before the light is considered blocked
+const MAX_SHADOW_CROSSINGS: u32 = 16;
+// offset along the normal for rays leaving a surface, keeps them from hitting it again
+const SURFACE_OFFSET: f32 = 1e-4;
+// cosine between the viewer and the shading normal a bent normal is kept at least at
+const MIN_SHADING_COSINE: f32 = 0.01;
+
+// objects the ray is currently inside of, innermost last, so that nested refractive objects such
+// as glass in water bend the ray against the right medium
+#[derive(Clone, Default)]
+struct Media<'a> {
+    objects: Vec<&'a Object>,
+}
+
+impl<'a> Media<'a> {
+    fn refractive_index(&self) -> f32 {
+        self.objects
+            .last()
+            .map_or(1.0, |object| object.material.refractive_index)
+    }
+
+    // media on the other side of the surface of `object`
+    fn crossing(&self, object: &'a Object, entering: bool) -> Media<'a> {
+        let mut objects: Vec<&Object> = self
+            .objects
+            .iter()
+            .copied()
+            .filter(|inside| !ptr::eq(*inside, object))
+            .collect();
+        if entering {
+            objects.push(object);
+        }
+        Media { objects }
+    }
+
+    // Beer–Lambert attenuation over `distance` travelled through the innermost medium
+    fn attenuation(&self, distance: f32) -> Color {
+        match self.objects.last() {
+            Some(object) => object.material.absorption.map(|absorption| {
+                if absorption > 0.0 {
+                    (-absorption * distance).exp()
+                } else {
+                    1.0
+                }
+            }),
+            None => Color::WHITE,
+        }
+    }
+}
+
+// fraction of unpolarized light reflected off a smooth boundary between two media, the rest is
+// let through. `cosine` is between the incoming ray and the normal, 1 on total internal reflection
+fn fresnel_reflectance(cosine: f32, current_index: f32, next_index: f32) -> f32 {
+    let cosine = cosine.clamp(0.0, 1.0);
+    let sine_through = current_index / next_index * (1.0 - cosine * cosine).sqrt();
+    if sine_through >= 1.0 {
+        return 1.0;
+    }
+    let cosine_through = (1.0 - sine_through * sine_through).sqrt();
+    let parallel = (next_index * cosine - current_index * cosine_through)
+        / (next_index * cosine + current_index * cosine_through);
+    let perpendicular = (current_index * cosine - next_index * cosine_through)
+        / (current_index * cosine + next_index * cosine_through);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+// light arriving at a point on a surface straight from the light sources, `diffuse` still has
+// to be scaled by the diffuse color and albedo of the surface and `specular` by its specular
+// albedo. `microfacet` is the light already reflected by the microfacet model, if there is one.
+struct DirectLight {
+    diffuse: Color,
+    specular: Color,
+    microfacet: Color,
+}
+
+// how a surface reflects the light arriving straight from the light sources
+enum Reflection<'a> {
+    // Lambertian plus Phong highlights of the lights
+    Phong { shininess: f32 },
+    // the path goes on in a direction sampled from the BRDF, emitters it runs into there are
+    // weighted against the shadow rays towards them
+    Microfacet(&'a Microfacet),
+    // only the mirror direction is traced on, it brings back the share of the specular lobe
+    // `Microfacet::mirror_share` stands for
+    MirroredMicrofacet(&'a Microfacet),
+}
+
+impl<'a> Reflection<'a> {
+    fn new(microfacet: Option<&'a Microfacet>, parameters: &SurfaceParameters) -> Reflection<'a> {
+        match microfacet {
+            Some(microfacet) => Reflection::Microfacet(microfacet),
+            None => Reflection::Phong {
+                shininess: parameters.shininess,
+            },
+        }
+    }
+
+    // what is reflected towards `outgoing` of the light of an emitter or the sky arriving from
+    // `incoming`
+    // along one of the shadow rays, which together were sampled with `density`. Phong shading
+    // leaves all of it to the diffuse light.
+    fn sampled_light(
+        &self,
+        normal: &Vec3,
+        outgoing: &Vec3,
+        incoming: &Vec3,
+        density: f32,
+    ) -> Color {
+        match self {
+            Reflection::Phong { .. } => Color::BLACK,
+            Reflection::Microfacet(microfacet) => {
+                microfacet.evaluate(normal, outgoing, incoming)
+                    * power_heuristic(density, microfacet.density(normal, outgoing, incoming))
+            }
+            Reflection::MirroredMicrofacet(microfacet) => {
+                let (specular, diffuse) = microfacet.evaluate_lobes(normal, outgoing, incoming);
+                specular * (1.0 - microfacet.mirror_share()) + diffuse
+            }
+        }
+    }
+}
+
+// multiple importance sampling weight of a sample picked with `density` when the other strategy
+// would have picked it with `other_density`, both summed over all the samples taken
+fn power_heuristic(density: f32, other_density: f32) -> f32 {
+    let squared = density * density;
+    let total = squared + other_density * other_density;
+    if total > 0.0 {
+        squared / total
+    } else {
+        0.0
+    }
+}
+
+fn direct_light(
+    object: &Object,
+    point_on_object: &Vec3,
+    normal: &Vec3,
+    ray_direction: &Vec3,
+    reflection: &Reflection,
+    scene: &Scene,
+    sampler: &mut Sampler,
+) -> DirectLight {
+    let mut diffuse = Color::BLACK;
+    let mut specular = Color::BLACK;
+    let mut reflected = Color::BLACK;
+
+    let facing_normal = if *normal * *ray_direction > 0.0 {
+        -normal.normalize()
+    } else {
+        normal.normalize()
+    };
+    let shadow_ray_origin = *point_on_object + facing_normal * SURFACE_OFFSET;
+
+    for light in &scene.lights {
+        let sample_weight = 1.0 / light.samples as f32;
+        for _ in 0..light.samples {
+            let light_sample = light.sample(point_on_object, sampler.next_2d());
+            if light_sample.color.max_channel() <= 0.0 {
+                continue;
+            }
+            // shot from the light so that the object itself is the first thing hit when lit
+            let transmittance = match light_sample.position {
+                Some(light_position) => transmittance(
+                    &light_position,
+                    &(*point_on_object - light_position),
+                    f32::INFINITY,
+                    Some(object),
+                    scene,
+                ),
+                None => transmittance(
+                    &shadow_ray_origin,
+                    &light_sample.direction,
+                    f32::INFINITY,
+                    None,
+                    scene,
+                ),
+            };
+            if transmittance.max_channel() <= 0.0 {
+                continue;
+            }
+            let light_color = light_sample.color * transmittance;
+            let light_direction = light_sample.direction;
+            let irradiance = light_color
+                * ((light_direction * *normal).max(0.0) * light_sample.intensity * sample_weight);
+
+            diffuse += irradiance;
+            match reflection {
+                Reflection::Microfacet(microfacet) | Reflection::MirroredMicrofacet(microfacet) => {
+                    reflected += irradiance
+                        * microfacet.evaluate(&facing_normal, &-*ray_direction, &light_direction);
+                }
+                // highlights only depend on the light's color, not on its intensity
+                Reflection::Phong { shininess } => {
+                    specular += light_color
+                        * ((light_direction.reflection(normal) * -*ray_direction)
+                            .max(0.0)
+                            .powf(*shininess)
+                            * sample_weight);
+                }
+            }
+        }
+    }
+
+    // unlike the lights emissive objects are physically based: the radiance they give off
+    // falls off with the square of the distance and the cosine on both ends
+    for emitter in scene.emitters() {
+        if ptr::eq(emitter, object) {
+            continue;
+        }
+        for _ in 0..EMITTER_SAMPLES {
+            let Some(sample) = emitter
+                .surface
+                .sample_seen_from(&shadow_ray_origin, sampler.next_2d())
+            else {
+                break;
+            };
+            let to_emitter = sample.point - shadow_ray_origin;
+            let distance = to_emitter.magnitude();
+            let emitter_direction = to_emitter * (1.0 / distance);
+            let cosine = emitter_direction * facing_normal;
+            if cosine <= 0.0 || !sample.density.is_finite() || sample.density <= 0.0 {
+                continue;
+            }
+            // the emitter itself sits right at `distance`, leave it a little slack
+            let transmittance = transmittance(
+                &shadow_ray_origin,
+                &emitter_direction,
+                distance * (1.0 - 1e-3),
+                None,
+                scene,
+            );
+
+            let irradiance = emitter.material.emission
+                * transmittance
+                * (cosine / (std::f32::consts::PI * sample.density * EMITTER_SAMPLES as f32));
+
+            reflected += irradiance
+                * reflection.sampled_light(
+                    &facing_normal,
+                    &-*ray_direction,
+                    &emitter_direction,
+                    sample.density * EMITTER_SAMPLES as f32,
+                );
+            diffuse += irradiance;
+        }
+    }
+
+    // the sky gives off radiance like the emitters do, from infinitely far away
+    if let Some(environment_light) = scene.environment_light() {
+        let sample_weight = 1.0 / environment_light.samples as f32;
+        for _ in 0..environment_light.samples {
+            let (sky_direction, density) = environment_light.sample(sampler.next_2d());
+            let cosine = sky_direction * facing_normal;
+            if cosine <= 0.0 || density <= 0.0 {
+                continue;
+            }
+            let transmittance = transmittance(
+                &shadow_ray_origin,
+                &sky_direction,
+                f32::INFINITY,
+                None,
+                scene,
+            );
+            if transmittance.max_channel() <= 0.0 {
+                continue;
+            }
+
+            let irradiance = sky_color(&sky_direction, 0.0, scene)
+                * transmittance
+                * (cosine * sample_weight / (std::f32::consts::PI * density));
+
+            reflected += irradiance
+                * reflection.sampled_light(
+                    &facing_normal,
+                    &-*ray_direction,
+                    &sky_direction,
+                    density * environment_light.samples as f32,
+                );
+            diffuse += irradiance;
+        }
+    }
+
+    DirectLight {
+        diffuse,
+        specular,
+        microfacet: reflected,
+    }
+}
+
+// light let through along the shadow ray until it reaches `target` or has covered `distance`.
+// Surfaces let it through untouched where they are cut out. Refractive objects let some of it
+// through tinted by their color on every surface crossed and absorb some more on the way
+// through, the bending of the light is ignored. Anything else blocks it completely.
+fn transmittance(
+    ray_origin: &Vec3,
+    ray_direction: &Vec3,
+    distance: f32,
+    target: Option<&Object>,
+    scene: &Scene,
+) -> Color {
+    let ray_direction = ray_direction.normalize();
+    let mut ray_origin = *ray_origin;
+    let mut covered = 0.0;
+    let mut transmittance = Color::WHITE;
+    let mut media = Media::default();
+
+    for _ in 0..MAX_SHADOW_CROSSINGS {
+        let Some((object, intersection)) =
+            ray_tracing::scene_intersect(&ray_origin, &ray_direction, scene)
+        else {
+            return transmittance * media.attenuation(f32::INFINITY);
+        };
+        transmittance = transmittance * media.attenuation(intersection.distance);
+        covered += intersection.distance;
+        if target.is_some_and(|target| ptr::eq(object, target)) || covered >= distance {
+            return transmittance;
+        }
+        let query = TextureQuery {
+            position: Some(ray_origin + ray_direction * intersection.distance),
+            normal: Some(intersection.shading_normal.normalize()),
+            ..TextureQuery::at(intersection.texture_coords)
+        };
+        let parameters = object.material.parameters(&query);
+        let (opacity, refraction) = (parameters.opacity, parameters.albedo.refraction);
+        if opacity >= 1.0 && refraction <= 0.0 {
+            return Color::BLACK;
+        }
+
+        transmittance = transmittance
+            * (shadow_tint(object, &query, refraction) * opacity + Color::WHITE * (1.0 - opacity));
+        media = media.crossing(object, intersection.normal * ray_direction < 0.0);
+        ray_origin = ray_origin + ray_direction * (intersection.distance + SURFACE_OFFSET);
+        covered += SURFACE_OFFSET;
+    }
+    Color::BLACK
+}
+
+// only the hue of the color tints the light, how much gets through is up to the refraction
+// albedo, otherwise the nearly black diffuse color of clear glass would block everything
+fn shadow_tint(object: &Object, query: &TextureQuery, refraction: f32) -> Color {
+    let color = object.material.diffuse_color.get(query);
+    let brightest = color.max_channel();
+    let hue = if brightest > 0.0 {
+        color * (1.0 / brightest)
+    } else {
+        Color::WHITE
+    };
+    hue * refraction
+}
+
+// texture lookup covering what the ray cone sees of the surface
+fn texture_query(ray: &Ray, object: &Object, intersection: &Intersection) -> TextureQuery {
+    TextureQuery {
+        texture_coords: intersection.texture_coords,
+        position: Some(ray.origin + ray.direction.normalize() * intersection.distance),
+        normal: Some(intersection.shading_normal.normalize()),
+        footprint: ray.width_at(intersection.distance) * object.surface.texture_scale(),
+    }
+}
+
+// normal the surface is shaded with, bent by the normal map of the material if there is one
+fn shading_normal(ray: &Ray, object: &Object, intersection: &Intersection) -> Vec3 {
+    let normal = match &object.material.normal_map {
+        Some(normal_map) => normal_map.shading_normal(
+            &texture_query(ray, object, intersection),
+            &intersection.shading_normal,
+            &intersection.tangent,
+            &intersection.bitangent,
+            object.surface.texture_scale(),
+        ),
+        None => intersection.shading_normal.normalize(),
+    };
+    // a normal bent away from the viewer would show it the back of the surface at grazing
+    // angles and send reflections below it, it is pulled back until it faces the viewer
+    let towards_viewer = -ray.direction.normalize();
+    let side = if intersection.normal * towards_viewer < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let facing = normal * side;
+    let cosine = facing * towards_viewer;
+    if cosine >= MIN_SHADING_COSINE {
+        return normal;
+    }
+    (facing + towards_viewer * (MIN_SHADING_COSINE - cosine)).normalize() * side
+}
+
+// `spread` is the angle the looked up part of the sky covers
+fn sky_color(ray_direction: &Vec3, spread: f32, scene: &Scene) -> Color {
+    const SKY_SPHERE: Sphere = Sphere {
+        origin: Vec3::new(0.0, 0.0, 0.0),
+        radius: 1.0,
+    };
+    const WORLD_ORIGIN: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    if let Some(Intersection { texture_coords, .. }) =
+        SKY_SPHERE.find_intersection(&WORLD_ORIGIN, ray_direction)
+    {
+        return scene.sky_sphere.diffuse_color.get(&TextureQuery {
+            texture_coords,
+            footprint: spread * SKY_SPHERE.texture_scale(),
+            ..TextureQuery::default()
+        });
+    }
+    DEBUG_PINK
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::material::{Material, Materials, NormalMap, SolidColor};
+    use std::sync::Arc;
+
+    fn sphere(material: Material) -> Object {
+        Object {
+            surface: Box::new(Sphere {
+                origin: Vec3::new(0.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            material,
+        }
+    }
+
+    #[test]
+    fn bent_normals_face_the_viewer_at_grazing_angles() {
+        // normals tilted almost flat along the tangent of the floor
+        let mut object = floor(0.0, Color::WHITE);
+        object.material.normal_map = Some(NormalMap::TangentSpace(Arc::new(SolidColor(
+            Color::new(1.0, 0.5, 0.55),
+        ))));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        for direction in [Vec3::new(1.0, -0.1, 0.0), Vec3::new(-1.0, -0.1, 0.0)] {
+            let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), direction.normalize());
+            let intersection = object
+                .surface
+                .find_intersection(&ray.origin, &ray.direction)
+                .unwrap();
+
+            let normal = shading_normal(&ray, &object, &intersection);
+
+            assert!(normal * -ray.direction > 0.0, "{:?}", normal);
+            assert!(normal * up > 0.0, "{:?}", normal);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn fresnel_reflectance_of_glass() {
+        // ((n - 1) / (n + 1))² head on, everything at grazing angles
+        assert_eq!(cap_float(fresnel_reflectance(1.0, 1.0, 1.5) * 100.0), 4.0);
+        assert_eq!(cap_float(fresnel_reflectance(1.0, 1.5, 1.0) * 100.0), 4.0);
+        assert_eq!(cap_float(fresnel_reflectance(0.0, 1.0, 1.5)), 1.0);
+    }
+
+    #[test]
+    fn fresnel_reflectance_past_critical_angle_is_total() {
+        let at_60_degrees = 0.5;
+
+        assert_eq!(fresnel_reflectance(at_60_degrees, 1.5, 1.0), 1.0);
+        assert!(fresnel_reflectance(at_60_degrees, 1.0, 1.5) < 1.0);
+    }
+
+    #[test]
+    fn nested_media_refract_against_the_enclosing_one() {
+        let water = sphere(Materials::dielectric(1.33, Color::BLACK));
+        let glass = sphere(Materials::dielectric(1.5, Color::BLACK));
+
+        let in_water = Media::default().crossing(&water, true);
+        let in_glass = in_water.crossing(&glass, true);
+        let out_of_glass = in_glass.crossing(&glass, false);
+
+        assert_eq!(in_water.refractive_index(), 1.33);
+        assert_eq!(in_glass.refractive_index(), 1.5);
+        assert_eq!(out_of_glass.refractive_index(), 1.33);
+        assert_eq!(out_of_glass.crossing(&water, false).refractive_index(), 1.0);
+    }
+
+    #[test]
+    fn absorption_falls_off_exponentially_with_distance() {
+        let tinted = sphere(Materials::dielectric(1.5, Color::new(0.0, 1.0, 2.0)));
+        let media = Media::default().crossing(&tinted, true);
+
+        let (r, g, b) = media.attenuation(0.5).as_channels();
+
+        assert_eq!(r, 1.0);
+        assert_eq!(g, (-0.5f32).exp());
+        assert_eq!(b, (-1.0f32).exp());
+        assert_eq!(Media::default().attenuation(f32::INFINITY), Color::WHITE);
+    }
+}
@@ -0,0 +1,96 @@
+use super::{Integrator, Ray};
+use crate::color::Color;
+use crate::ray_tracing;
+use crate::sampling::{cosine_hemisphere, Sampler};
+use crate::scene::Scene;
+
+// keeps the occlusion rays from hitting the surface they start on
+const SURFACE_OFFSET: f32 = 1e-4;
+
+/// Fraction of the hemisphere above the first surface hit that is not blocked by anything
+/// within `radius`, white for fully open surfaces and black for completely enclosed ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOcclusion {
+    pub samples: u32,
+    pub radius: f32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> AmbientOcclusion {
+        AmbientOcclusion {
+            samples: 16,
+            radius: 1.0,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Color {
+        let Some((_, intersection)) =
+            ray_tracing::scene_intersect(&ray.origin, &ray.direction, scene)
+        else {
+            return Color::WHITE;
+        };
+
+        let direction = ray.direction.normalize();
+        let mut normal = intersection.normal.normalize();
+        if normal * direction > 0.0 {
+            normal = -normal;
+        }
+        let point = ray.origin + direction * intersection.distance + normal * SURFACE_OFFSET;
+
+        let samples = self.samples.max(1);
+        let unoccluded = (0..samples)
+            .filter(|_| {
+                let occlusion_direction = cosine_hemisphere(sampler.next_2d(), &normal);
+                ray_tracing::scene_intersect(&point, &occlusion_direction, scene)
+                    .is_none_or(|(_, occluder)| occluder.distance > self.radius)
+            })
+            .count();
+
+        let visibility = unoccluded as f32 / samples as f32;
+        Color::new(visibility, visibility, visibility)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::material::Materials;
+    use crate::vector_math::Vec3;
+
+    fn occlusion_between_planes(ceiling_height: f32) -> f32 {
+        let scene = Scene::new(
+            vec![
+                floor(0.0, Color::WHITE),
+                floor(ceiling_height, Color::WHITE),
+            ],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.1, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let integrator = AmbientOcclusion {
+            samples: 256,
+            radius: 1.0,
+        };
+
+        let (visibility, _, _) = integrator
+            .radiance(&ray, &scene, &mut Sampler::new(1))
+            .as_channels();
+        visibility
+    }
+
+    #[test]
+    fn open_surface_is_white() {
+        assert_eq!(occlusion_between_planes(100.0), 1.0);
+    }
+
+    #[test]
+    fn nearby_ceiling_occludes() {
+        // only rays grazing the floor get further than the radius before hitting the ceiling
+        assert!(occlusion_between_planes(0.2) < 0.1);
+        let partial = occlusion_between_planes(0.8);
+        assert!(partial > 0.0 && partial < 1.0, "{}", partial);
+    }
+}
@@ -0,0 +1,104 @@
+use super::{shading_normal, texture_query, Integrator, Ray};
+use crate::color::Color;
+use crate::ray_tracing;
+use crate::sampling::Sampler;
+use crate::scene::Scene;
+
+/// Visualises a single property of the first surface hit, handy for checking geometry and
+/// texture coordinates without any lighting getting in the way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Debug {
+    /// shading normal, bent by normal maps, mapped from -1..1 to 0..1 on every axis
+    Normals,
+    /// distance to the camera, black up close and white at `max_distance` and beyond
+    Depth { max_distance: f32 },
+    /// unlit diffuse color
+    Albedo,
+    /// texture coordinates as red and green, blue where the surface has none
+    TextureCoords,
+}
+
+impl Integrator for Debug {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _sampler: &mut Sampler) -> Color {
+        let Some((object, intersection)) =
+            ray_tracing::scene_intersect(&ray.origin, &ray.direction, scene)
+        else {
+            return Color::BLACK;
+        };
+
+        match *self {
+            Debug::Normals => {
+                let (x, y, z) = shading_normal(ray, object, &intersection).as_coords();
+                Color::new(x, y, z).map(|channel| channel * 0.5 + 0.5)
+            }
+            Debug::Depth { max_distance } => {
+                let depth = (intersection.distance / max_distance).min(1.0);
+                Color::new(depth, depth, depth)
+            }
+            Debug::Albedo => {
+                object
+                    .material
+                    .diffuse_color
+                    .get(&texture_query(ray, object, &intersection))
+            }
+            Debug::TextureCoords => match intersection.texture_coords {
+                Some((u, v)) => Color::new(u, v, 0.0),
+                None => Color::new(0.0, 0.0, 1.0),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::material::Materials;
+    use crate::scene::sphere::Sphere;
+    use crate::scene::Object;
+    use crate::vector_math::Vec3;
+
+    fn sphere_scene() -> Scene {
+        Scene::new(
+            vec![Object {
+                surface: Box::new(Sphere {
+                    origin: Vec3::new(0.0, 0.0, -5.0),
+                    radius: 1.0,
+                }),
+                material: Materials::solid_color(Color::new(0.2, 0.4, 0.6)),
+            }],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        )
+    }
+
+    #[test]
+    fn shows_surface_properties_of_first_hit() {
+        let scene = sphere_scene();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut sampler = Sampler::new(1);
+
+        assert_eq!(
+            Debug::Normals.radiance(&ray, &scene, &mut sampler),
+            Color::new(0.5, 0.5, 1.0)
+        );
+        assert_eq!(
+            Debug::Depth { max_distance: 8.0 }.radiance(&ray, &scene, &mut sampler),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            Debug::Albedo.radiance(&ray, &scene, &mut sampler),
+            Color::new(0.2, 0.4, 0.6)
+        );
+    }
+
+    #[test]
+    fn misses_are_black() {
+        let scene = sphere_scene();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+
+        assert_eq!(
+            Debug::Normals.radiance(&ray, &scene, &mut Sampler::new(1)),
+            Color::BLACK
+        );
+    }
+}
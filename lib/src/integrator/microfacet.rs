@@ -0,0 +1,311 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::sampling::cosine_hemisphere;
+use crate::scene::material::MetallicRoughness;
+use crate::vector_math::Vec3;
+
+// keeps perfectly smooth surfaces from dividing by zero, they still look like mirrors
+const MIN_ALPHA: f32 = 1e-3;
+
+/// GGX (Trowbridge-Reitz) microfacet reflection with a Lambertian base underneath, as in glTF's
+/// metallic-roughness model. Directions point away from the surface and are unit vectors.
+pub(super) struct Microfacet {
+    base_color: Color,
+    metallic: f32,
+    roughness: f32,
+    alpha: f32,
+    // reflectance seen head on
+    specular_color: Color,
+}
+
+impl Microfacet {
+    pub(super) fn new(parameters: &MetallicRoughness, base_color: Color) -> Microfacet {
+        let metallic = parameters.metallic.clamp(0.0, 1.0);
+        let roughness = parameters.roughness.clamp(0.0, 1.0);
+        let dielectric_specular = 0.08 * parameters.specular.clamp(0.0, 1.0);
+        Microfacet {
+            base_color,
+            metallic,
+            roughness,
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+            specular_color: Color::WHITE * (dielectric_specular * (1.0 - metallic))
+                + base_color * metallic,
+        }
+    }
+
+    /// BRDF times π, so that like the Whitted shading a white Lambertian surface facing a
+    /// light of intensity 1 reflects exactly 1.
+    pub(super) fn evaluate(&self, normal: &Vec3, outgoing: &Vec3, incoming: &Vec3) -> Color {
+        let (specular, diffuse) = self.evaluate_lobes(normal, outgoing, incoming);
+        specular + diffuse
+    }
+
+    /// `evaluate` split into the specular and the diffuse lobe.
+    pub(super) fn evaluate_lobes(
+        &self,
+        normal: &Vec3,
+        outgoing: &Vec3,
+        incoming: &Vec3,
+    ) -> (Color, Color) {
+        let normal_incoming = *normal * *incoming;
+        let normal_outgoing = *normal * *outgoing;
+        if normal_incoming <= 0.0 || normal_outgoing <= 0.0 {
+            return (Color::BLACK, Color::BLACK);
+        }
+        let half = (*incoming + *outgoing).normalize();
+        let fresnel = self.fresnel(half * *outgoing);
+
+        let specular = fresnel
+            * (self.distribution(*normal * half)
+                * self.masking(normal_incoming)
+                * self.masking(normal_outgoing)
+                * PI
+                / (4.0 * normal_incoming * normal_outgoing));
+        (specular, self.diffuse(normal_outgoing))
+    }
+
+    /// Probability density per unit of solid angle `sample` picks `incoming` with.
+    pub(super) fn density(&self, normal: &Vec3, outgoing: &Vec3, incoming: &Vec3) -> f32 {
+        let normal_incoming = *normal * *incoming;
+        let normal_outgoing = *normal * *outgoing;
+        if normal_incoming <= 0.0 || normal_outgoing <= 0.0 {
+            return 0.0;
+        }
+        let specular_probability = self.specular_probability();
+        let half = (*incoming + *outgoing).normalize();
+        let half_outgoing = half * *outgoing;
+        let specular = if half_outgoing > 0.0 {
+            // the density of the half vector changes by that factor when reflected around it
+            self.distribution(*normal * half) * (*normal * half) / (4.0 * half_outgoing)
+        } else {
+            0.0
+        };
+        specular_probability * specular + (1.0 - specular_probability) * normal_incoming / PI
+    }
+
+    /// Picks the direction light arrives from proportionally to the specular or the diffuse
+    /// lobe. Returns it with the BRDF times the cosine over the probability of picking it,
+    /// `None` when the sampled direction ends up below the surface.
+    pub(super) fn sample(
+        &self,
+        normal: &Vec3,
+        outgoing: &Vec3,
+        sample: (f32, f32),
+        lobe: f32,
+    ) -> Option<(Vec3, Color)> {
+        let normal_outgoing = *normal * *outgoing;
+        if normal_outgoing <= 0.0 {
+            return None;
+        }
+        let specular_probability = self.specular_probability();
+
+        if lobe < specular_probability {
+            let half = self.sample_half_vector(normal, sample);
+            let half_outgoing = half * *outgoing;
+            let incoming = half * (2.0 * half_outgoing) - *outgoing;
+            let normal_incoming = *normal * incoming;
+            if normal_incoming <= 0.0 || half_outgoing <= 0.0 {
+                return None;
+            }
+            // the distribution cancels out against the probability of the half vector
+            let weight = self.fresnel(half_outgoing)
+                * (self.masking(normal_incoming) * self.masking(normal_outgoing) * half_outgoing
+                    / (normal_outgoing * (*normal * half) * specular_probability));
+            Some((incoming, weight))
+        } else {
+            let incoming = cosine_hemisphere(sample, normal);
+            let weight = self.diffuse(normal_outgoing) * (1.0 / (1.0 - specular_probability));
+            Some((incoming, weight))
+        }
+    }
+
+    /// How much of the mirror reflection makes it back, for integrators that can only follow
+    /// the mirror direction. Rough surfaces scatter it away so it fades out with roughness.
+    pub(super) fn mirror_reflectance(&self, normal: &Vec3, outgoing: &Vec3) -> Color {
+        self.fresnel(*normal * *outgoing) * self.mirror_share()
+    }
+
+    /// Share of the specular lobe `mirror_reflectance` stands for, the rest of it has to come
+    /// from sampling the lights.
+    pub(super) fn mirror_share(&self) -> f32 {
+        let smoothness = 1.0 - self.roughness;
+        smoothness * smoothness
+    }
+
+    // metals have no diffuse lobe, the rest is split between the lobes by their albedo
+    fn specular_probability(&self) -> f32 {
+        let specular = self.specular_color.luminance();
+        let diffuse = self.base_color.luminance() * (1.0 - self.metallic);
+        if specular + diffuse <= 0.0 {
+            return 1.0;
+        }
+        (specular / (specular + diffuse)).clamp(0.1, 1.0)
+    }
+
+    // what the specular reflection leaves over reaches the base, weighting it by the Fresnel
+    // term of the half vector instead would gain energy at grazing angles
+    fn diffuse(&self, normal_outgoing: f32) -> Color {
+        self.fresnel(normal_outgoing).map(|channel| 1.0 - channel)
+            * self.base_color
+            * (1.0 - self.metallic)
+    }
+
+    // Schlick's approximation
+    fn fresnel(&self, cosine: f32) -> Color {
+        let factor = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+        self.specular_color * (1.0 - factor) + Color::WHITE * factor
+    }
+
+    // share of the microfacets facing along `half`
+    fn distribution(&self, normal_half: f32) -> f32 {
+        let alpha_squared = self.alpha * self.alpha;
+        let denominator = normal_half * normal_half * (alpha_squared - 1.0) + 1.0;
+        alpha_squared / (PI * denominator * denominator)
+    }
+
+    // Smith shadowing and masking of a single direction
+    fn masking(&self, cosine: f32) -> f32 {
+        let alpha_squared = self.alpha * self.alpha;
+        2.0 * cosine / (cosine + (alpha_squared + (1.0 - alpha_squared) * cosine * cosine).sqrt())
+    }
+
+    // microfacet normal distributed proportionally to the distribution times its cosine
+    fn sample_half_vector(&self, normal: &Vec3, (u, v): (f32, f32)) -> Vec3 {
+        let alpha_squared = self.alpha * self.alpha;
+        let cosine_squared = (1.0 - v) / (1.0 + (alpha_squared - 1.0) * v);
+        let cosine = cosine_squared.sqrt();
+        let sine = (1.0 - cosine_squared).max(0.0).sqrt();
+        let angle = 2.0 * PI * u;
+        let (tangent, bitangent) = normal.perpendiculars();
+        (tangent * (sine * angle.cos()) + bitangent * (sine * angle.sin()) + *normal * cosine)
+            .normalize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    fn microfacet(base_color: Color, metallic: f32, roughness: f32) -> Microfacet {
+        Microfacet::new(
+            &MetallicRoughness {
+                metallic,
+                roughness,
+                specular: 0.5,
+            },
+            base_color,
+        )
+    }
+
+    // share of the light arriving from all over the hemisphere reflected towards `outgoing`
+    fn directional_albedo(microfacet: &Microfacet, outgoing: &Vec3) -> Color {
+        let mut random = SmallRng::seed_from_u64(3);
+        let samples = 20000;
+        let mut sum = Color::BLACK;
+        for _ in 0..samples {
+            let sample = (random.gen::<f32>(), random.gen::<f32>());
+            if let Some((_, weight)) = microfacet.sample(&UP, outgoing, sample, random.gen::<f32>())
+            {
+                sum += weight;
+            }
+        }
+        sum * (1.0 / samples as f32)
+    }
+
+    #[test]
+    fn rough_dielectric_is_mostly_lambertian() {
+        let microfacet = microfacet(Color::WHITE, 0.0, 1.0);
+
+        let (r, g, b) = microfacet.evaluate(&UP, &UP, &UP).as_channels();
+
+        // 4% is reflected off the top, the rest diffusely
+        assert!(r > 0.96 && r < 1.1, "{}", r);
+        assert!(r == g && g == b);
+    }
+
+    #[test]
+    fn metals_are_tinted_by_their_base_color_and_have_no_diffuse_lobe() {
+        let gold = microfacet(Color::new(1.0, 0.8, 0.3), 1.0, 0.3);
+        let outgoing = Vec3::new(1.0, 1.0, 0.0).normalize();
+
+        let mirrored = gold.evaluate(&UP, &outgoing, &Vec3::new(-1.0, 1.0, 0.0).normalize());
+        let away_from_highlight =
+            gold.evaluate(&UP, &outgoing, &Vec3::new(1.0, 0.2, 0.0).normalize());
+
+        let (r, g, b) = mirrored.as_channels();
+        assert!(r > g && g > b, "{:?}", mirrored);
+        assert!(away_from_highlight.max_channel() < 0.05 * mirrored.max_channel());
+        assert_eq!(gold.specular_probability(), 1.0);
+    }
+
+    #[test]
+    fn nothing_is_reflected_below_the_surface() {
+        let microfacet = microfacet(Color::WHITE, 0.0, 0.5);
+
+        assert_eq!(
+            microfacet.evaluate(&UP, &UP, &Vec3::new(0.0, -1.0, 0.0)),
+            Color::BLACK
+        );
+    }
+
+    #[test]
+    fn sampling_conserves_energy() {
+        for (metallic, roughness) in [(0.0, 0.2), (0.0, 0.8), (1.0, 0.1), (1.0, 0.6)] {
+            let microfacet = microfacet(Color::WHITE, metallic, roughness);
+            for outgoing in [UP, Vec3::new(1.0, 0.5, 0.0).normalize()] {
+                let (r, _, _) = directional_albedo(&microfacet, &outgoing).as_channels();
+                // single scattering loses some energy on rough surfaces, it never gains any
+                assert!(r <= 1.01 && r > 0.7, "{} {} {}", metallic, roughness, r);
+            }
+        }
+    }
+
+    #[test]
+    fn density_covers_the_hemisphere_once() {
+        for (metallic, roughness) in [(0.0, 0.3), (0.5, 0.5), (1.0, 0.6)] {
+            let microfacet = microfacet(Color::new(0.8, 0.5, 0.2), metallic, roughness);
+            let outgoing = Vec3::new(0.3, 1.0, 0.0).normalize();
+            let mut random = SmallRng::seed_from_u64(6);
+            let samples = 40000;
+            let mut total = 0.0;
+            for _ in 0..samples {
+                // cosine weighted directions have a density of the cosine over π
+                let incoming = cosine_hemisphere((random.gen::<f32>(), random.gen::<f32>()), &UP);
+                total += microfacet.density(&UP, &outgoing, &incoming) * PI / (UP * incoming);
+            }
+
+            // a little of the specular lobe ends up below the surface, more the rougher it gets
+            let total = total / samples as f32;
+            assert!(total > 0.85 && total < 1.02, "{} {}", roughness, total);
+        }
+    }
+
+    #[test]
+    fn sampling_matches_evaluation() {
+        // the sample weights are the BRDF times the cosine over the probability, so averaging
+        // both estimates of the light reflected from a uniform white sky has to agree
+        let microfacet = microfacet(Color::new(0.8, 0.5, 0.2), 0.3, 0.5);
+        let outgoing = Vec3::new(0.3, 1.0, 0.0).normalize();
+        let mut random = SmallRng::seed_from_u64(5);
+        let samples = 40000;
+        let mut uniform = 0.0;
+        for _ in 0..samples {
+            let incoming = cosine_hemisphere((random.gen::<f32>(), random.gen::<f32>()), &UP);
+            // cosine weighted directions, the cosine and the 1 / π cancel out
+            let (r, _, _) = microfacet.evaluate(&UP, &outgoing, &incoming).as_channels();
+            uniform += r;
+        }
+        let (sampled, _, _) = directional_albedo(&microfacet, &outgoing).as_channels();
+
+        assert!(
+            (uniform / samples as f32 - sampled).abs() < 0.02,
+            "{} {}",
+            uniform,
+            sampled
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use std::ptr;
+
+use super::{
+    direct_light, fresnel_reflectance, power_heuristic, shading_normal, sky_color, texture_query,
+    Integrator, Media, Microfacet, Ray, Reflection, EMITTER_SAMPLES, SPEC_BASE_COLOR,
+    SURFACE_OFFSET,
+};
+use crate::color::Color;
+use crate::ray_tracing;
+use crate::sampling::{cosine_hemisphere, Sampler};
+use crate::scene::material::Albedo;
+use crate::scene::{EnvironmentLight, Object, Scene};
+use crate::vector_math::Vec3;
+
+// paths shorter than this are never terminated by russian roulette
+const GUARANTEED_BOUNCES: u8 = 3;
+
+/// Monte Carlo estimate of the light arriving along the ray. Diffuse surfaces scatter into a
+/// cosine weighted hemisphere, mirror and refraction rays are followed as they are, and the
+/// lights, emitters and a sky lighting the scene are sampled directly at every surface the path
+/// hits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathTracing {
+    pub max_bounces: u8,
+}
+
+impl Default for PathTracing {
+    fn default() -> PathTracing {
+        PathTracing { max_bounces: 6 }
+    }
+}
+
+impl Integrator for PathTracing {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Color {
+        trace(ray, scene, self.max_bounces, sampler)
+    }
+}
+
+// how the path got to its current direction, which decides how much of the light of the
+// emitters and the sky it runs into was already accounted for by sampling them directly
+#[derive(Clone, Copy)]
+enum Scattering<'a> {
+    // straight from the camera or off a mirror or refraction, no shadow ray can go that way
+    Specular,
+    // off a diffuse surface, which gets all of the emitters' and the sky's light from its
+    // shadow rays
+    Diffuse,
+    // picked with `density` by the BRDF of `object` at `origin`, shared with its shadow rays
+    Microfacet {
+        object: &'a Object,
+        origin: Vec3,
+        density: f32,
+    },
+}
+
+impl Scattering<'_> {
+    // share of the emission of `emitter` hit in `direction` the path still has to add
+    fn emitter_weight(&self, emitter: &Object, direction: &Vec3) -> f32 {
+        match self {
+            Scattering::Specular => 1.0,
+            Scattering::Diffuse => 0.0,
+            // surfaces don't sample themselves as emitters
+            Scattering::Microfacet { object, .. } if ptr::eq(*object, emitter) => 1.0,
+            Scattering::Microfacet {
+                origin, density, ..
+            } => power_heuristic(
+                *density,
+                emitter.surface.density_seen_from(origin, direction) * EMITTER_SAMPLES as f32,
+            ),
+        }
+    }
+
+    // share of the sky seen in `direction` the path still has to add when `light` samples it
+    fn sky_weight(&self, light: &EnvironmentLight, direction: &Vec3) -> f32 {
+        match self {
+            Scattering::Specular => 1.0,
+            Scattering::Diffuse => 0.0,
+            Scattering::Microfacet { density, .. } => {
+                power_heuristic(*density, light.density(direction) * light.samples as f32)
+            }
+        }
+    }
+}
+
+fn trace(ray: &Ray, scene: &Scene, max_bounces: u8, sampler: &mut Sampler) -> Color {
+    let mut radiance = Color::BLACK;
+    let mut throughput = Color::WHITE;
+    let mut ray_origin = ray.origin;
+    let mut ray_direction = ray.direction.normalize();
+    // textures seen after a bounce are looked up as blurry as the cone has got wide
+    let mut ray_width = ray.width;
+    let mut media = Media::default();
+    let mut scattering = Scattering::Specular;
+
+    for bounce_count in 0..=max_bounces {
+        let Some((object, intersection)) =
+            ray_tracing::scene_intersect(&ray_origin, &ray_direction, scene)
+        else {
+            // a sky lighting the scene is sampled directly just like the emitters
+            let weight = scene
+                .environment_light()
+                .map_or(1.0, |light| scattering.sky_weight(light, &ray_direction));
+            if weight <= 0.0 {
+                return radiance;
+            }
+            return radiance
+                + throughput
+                    * media.attenuation(f32::INFINITY)
+                    * sky_color(&ray_direction, ray.spread, scene)
+                    * weight;
+        };
+        throughput = throughput * media.attenuation(intersection.distance);
+
+        let material = &object.material;
+        let point_on_object = ray_origin + ray_direction * intersection.distance;
+        let cone = Ray {
+            width: ray_width,
+            spread: ray.spread,
+            ..Ray::new(ray_origin, ray_direction)
+        };
+        let entering = intersection.normal * ray_direction < 0.0;
+        let shading_normal = shading_normal(&cone, object, &intersection);
+        let normal = if entering {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+        let query = texture_query(&cone, object, &intersection);
+        let diffuse_color = material.diffuse_color.get(&query);
+        let parameters = material.parameters(&query);
+        ray_width = cone.width_at(intersection.distance);
+        // where the surface is cut out the path carries on as if it wasn't there
+        if parameters.opacity < 1.0 && sampler.next_1d() >= parameters.opacity {
+            ray_origin = point_on_object + ray_direction * SURFACE_OFFSET;
+            continue;
+        }
+        let microfacet = parameters
+            .metallic_roughness
+            .map(|metallic_roughness| Microfacet::new(&metallic_roughness, diffuse_color));
+
+        if scene.emitters().any(|emitter| ptr::eq(emitter, object)) {
+            radiance +=
+                throughput * material.emission * scattering.emitter_weight(object, &ray_direction);
+        } else {
+            radiance += throughput * material.emission;
+        }
+        // next event estimation, the diffuse term matches the Whitted shading so that a white
+        // surface facing a light of intensity 1 reflects exactly 1
+        let direct_light = direct_light(
+            object,
+            &point_on_object,
+            &normal,
+            &ray_direction,
+            &Reflection::new(microfacet.as_ref(), &parameters),
+            scene,
+            sampler,
+        );
+        radiance += throughput
+            * match microfacet {
+                Some(_) => direct_light.microfacet,
+                None => {
+                    diffuse_color * direct_light.diffuse * parameters.albedo.diffuse
+                        + SPEC_BASE_COLOR * direct_light.specular * parameters.albedo.specular
+                }
+            };
+
+        if bounce_count == max_bounces {
+            break;
+        }
+
+        if let Some(microfacet) = microfacet {
+            let Some((incoming, weight)) = microfacet.sample(
+                &normal,
+                &-ray_direction,
+                sampler.next_2d(),
+                sampler.next_1d(),
+            ) else {
+                break;
+            };
+            throughput = throughput * weight;
+            ray_origin = point_on_object + normal * SURFACE_OFFSET;
+            scattering = Scattering::Microfacet {
+                object,
+                origin: ray_origin,
+                density: microfacet.density(&normal, &-ray_direction, &incoming),
+            };
+            ray_direction = incoming;
+        } else {
+            // a single lobe is followed per bounce, picked proportionally to its albedo
+            let Albedo {
+                diffuse: diffuse_weight,
+                reflection: reflection_weight,
+                refraction: refraction_weight,
+                ..
+            } = parameters.albedo;
+            let total_weight = diffuse_weight + reflection_weight + refraction_weight;
+            if total_weight <= 0.0 {
+                break;
+            }
+            let lobe = sampler.next_1d() * total_weight;
+            scattering = if lobe < diffuse_weight {
+                Scattering::Diffuse
+            } else {
+                Scattering::Specular
+            };
+
+            if lobe < diffuse_weight {
+                throughput = throughput * diffuse_color * total_weight;
+                ray_origin = point_on_object + normal * SURFACE_OFFSET;
+                ray_direction = cosine_hemisphere(sampler.next_2d(), &normal);
+            } else if lobe < diffuse_weight + reflection_weight {
+                throughput = throughput * total_weight;
+                ray_origin = object.surface.approximate_outside(point_on_object);
+                ray_direction = -ray_direction.reflection(&normal);
+            } else {
+                // the Fresnel term decides between reflecting off the surface and going through it
+                throughput = throughput * total_weight;
+                let next_media = media.crossing(object, entering);
+                let (current_index, next_index) =
+                    (media.refractive_index(), next_media.refractive_index());
+                let reflectance =
+                    fresnel_reflectance(-ray_direction * normal, current_index, next_index);
+                let refraction_direction = ray_direction
+                    .refraction(&normal, current_index, next_index)
+                    .filter(|_| sampler.next_1d() >= reflectance);
+
+                if let Some(refraction_direction) = refraction_direction {
+                    ray_direction = refraction_direction;
+                    ray_origin = if entering {
+                        object.surface.approximate_inside(point_on_object)
+                    } else {
+                        object.surface.approximate_outside(point_on_object)
+                    };
+                    media = next_media;
+                } else {
+                    ray_direction = -ray_direction.reflection(&normal);
+                    ray_origin = if entering {
+                        object.surface.approximate_outside(point_on_object)
+                    } else {
+                        object.surface.approximate_inside(point_on_object)
+                    };
+                }
+            }
+        }
+
+        if bounce_count + 1 >= GUARANTEED_BOUNCES {
+            let survival_probability = throughput.max_channel().min(0.95);
+            if sampler.next_1d() >= survival_probability {
+                break;
+            }
+            throughput = throughput * (1.0 / survival_probability);
+        }
+    }
+    radiance
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::disk::Disk;
+    use crate::scene::material::Materials;
+    use crate::scene::{Light, Object};
+    use crate::vector_math::Vec3;
+
+    fn average_radiance(scene: &Scene, samples: u32) -> Color {
+        let mut sampler = Sampler::new(7);
+        let integrator = PathTracing { max_bounces: 8 };
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sum = Color::BLACK;
+        for _ in 0..samples {
+            sum += integrator.radiance(&ray, scene, &mut sampler);
+        }
+        sum * (1.0 / samples as f32)
+    }
+
+    #[test]
+    fn direct_light_matches_whitted_shading() {
+        let scene = Scene::new(
+            vec![floor(0.0, Color::new(0.5, 0.5, 0.5))],
+            vec![Light::point(Vec3::new(0.0, 10.0, 0.0), 1.0)],
+            Materials::solid_color(Color::BLACK),
+        );
+
+        let (r, g, b) = average_radiance(&scene, 16).as_channels();
+
+        assert!((r - 0.5).abs() < 1e-3 && (g - 0.5).abs() < 1e-3 && (b - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn diffuse_surface_under_uniform_sky_reflects_its_albedo() {
+        // a lambertian floor lit by a white sky from every direction reflects exactly its color,
+        // anything else would mean the hemisphere sampling is biased
+        let scene = Scene::new(
+            vec![floor(0.0, Color::new(0.8, 0.4, 0.2))],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+
+        let (r, g, b) = average_radiance(&scene, 4000).as_channels();
+
+        assert!((r - 0.8).abs() < 0.03, "{}", r);
+        assert!((g - 0.4).abs() < 0.03, "{}", g);
+        assert!((b - 0.2).abs() < 0.03, "{}", b);
+    }
+
+    #[test]
+    fn sampling_the_sky_directly_agrees_with_hitting_it() {
+        let mut scene = Scene::new(
+            vec![floor(0.0, Color::new(0.8, 0.4, 0.2))],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+        scene.light_with_sky(4);
+
+        let (r, g, b) = average_radiance(&scene, 1000).as_channels();
+
+        assert!((r - 0.8).abs() < 0.03, "{}", r);
+        assert!((g - 0.4).abs() < 0.03, "{}", g);
+        assert!((b - 0.2).abs() < 0.03, "{}", b);
+    }
+
+    #[test]
+    fn paths_pass_through_cut_out_surfaces() {
+        let mut floor = floor(0.0, Color::BLACK);
+        floor.material.opacity = 0.25;
+        let scene = Scene::new(vec![floor], vec![], Materials::solid_color(Color::WHITE));
+
+        let (r, _, _) = average_radiance(&scene, 4000).as_channels();
+
+        assert!((r - 0.75).abs() < 0.03, "{}", r);
+    }
+
+    #[test]
+    fn emissive_disk_lights_floor_by_its_solid_angle() {
+        // a white floor under a disk of radiance 1 reflects r² / (h² + r²) of it, 0.5 when the
+        // radius and the height of the disk are the same
+        let scene = Scene::new(
+            vec![
+                floor(0.0, Color::WHITE),
+                Object {
+                    surface: Box::new(Disk {
+                        center: Vec3::new(0.0, 2.0, 0.0),
+                        normal: Vec3::new(0.0, -1.0, 0.0),
+                        radius: 2.0,
+                    }),
+                    material: Materials::emissive(Color::WHITE),
+                },
+            ],
+            vec![],
+            Materials::solid_color(Color::BLACK),
+        );
+
+        let (r, _, _) = average_radiance(&scene, 2000).as_channels();
+
+        assert!((r - 0.5).abs() < 0.02, "{}", r);
+        let looking_up = PathTracing::default().radiance(
+            &Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            &scene,
+            &mut Sampler::new(1),
+        );
+        assert_eq!(looking_up, Color::WHITE);
+    }
+
+    #[test]
+    fn metal_reflects_the_emitters_its_paths_run_into() {
+        // the shadow rays hardly ever hit the narrow highlight of a smooth metal, the paths
+        // sampled from its BRDF have to pick up the emitter
+        for roughness in [0.0, 0.3] {
+            let scene = Scene::new(
+                vec![
+                    Object {
+                        material: Materials::metallic_roughness(
+                            Color::new(0.9, 0.9, 0.9),
+                            1.0,
+                            roughness,
+                        ),
+                        ..floor(0.0, Color::BLACK)
+                    },
+                    Object {
+                        surface: Box::new(Disk {
+                            center: Vec3::new(0.0, 2.0, 0.0),
+                            normal: Vec3::new(0.0, -1.0, 0.0),
+                            radius: 100.0,
+                        }),
+                        material: Materials::emissive(Color::WHITE),
+                    },
+                ],
+                vec![],
+                Materials::solid_color(Color::BLACK),
+            );
+
+            let (r, _, _) = average_radiance(&scene, 1000).as_channels();
+
+            assert!((r - 0.9).abs() < 0.05, "{} {}", roughness, r);
+        }
+    }
+
+    #[test]
+    fn metal_reflects_the_sky_lighting_the_scene() {
+        for roughness in [0.0, 0.3] {
+            let mut scene = Scene::new(
+                vec![Object {
+                    material: Materials::metallic_roughness(
+                        Color::new(0.9, 0.9, 0.9),
+                        1.0,
+                        roughness,
+                    ),
+                    ..floor(0.0, Color::BLACK)
+                }],
+                vec![],
+                Materials::solid_color(Color::WHITE),
+            );
+            scene.light_with_sky(4);
+
+            let (r, _, _) = average_radiance(&scene, 1000).as_channels();
+
+            assert!((r - 0.9).abs() < 0.05, "{} {}", roughness, r);
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_under_uniform_sky_reflects_nearly_its_albedo() {
+        // what the diffuse base doesn't get is reflected by the microfacets, only a little is
+        // lost to light bouncing between them
+        let scene = Scene::new(
+            vec![Object {
+                material: Materials::metallic_roughness(Color::new(0.8, 0.4, 0.2), 0.0, 0.5),
+                ..floor(0.0, Color::BLACK)
+            }],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+
+        let (r, g, b) = average_radiance(&scene, 4000).as_channels();
+
+        assert!(r > 0.75 && r < 0.85, "{}", r);
+        assert!(g > 0.4 && g < 0.5, "{}", g);
+        assert!(b > 0.2 && b < 0.3, "{}", b);
+    }
+}
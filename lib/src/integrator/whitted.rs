@@ -0,0 +1,465 @@
+use super::{
+    direct_light, fresnel_reflectance, shading_normal, sky_color, texture_query, Integrator, Media,
+    Microfacet, Ray, Reflection, SPEC_BASE_COLOR, SURFACE_OFFSET,
+};
+use crate::color::Color;
+use crate::ray_tracing;
+use crate::sampling::Sampler;
+use crate::scene::Scene;
+
+/// Direct diffuse and Phong lighting from the lights plus perfect mirror and refraction rays,
+/// deterministic apart from area lights so a single sample per pixel is usually enough. Shadows
+/// of refractive objects let light through tinted by their color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Whitted {
+    pub max_bounces: u8,
+}
+
+impl Default for Whitted {
+    fn default() -> Whitted {
+        Whitted { max_bounces: 4 }
+    }
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Color {
+        cast_ray(self.max_bounces, sampler, ray, scene, 0, &Media::default())
+    }
+}
+
+fn cast_ray<'a>(
+    max_bounces: u8,
+    sampler: &mut Sampler,
+    ray: &Ray,
+    scene: &'a Scene,
+    bounce_count: u8,
+    media: &Media<'a>,
+) -> Color {
+    if let Some((object, intersection)) =
+        ray_tracing::scene_intersect(&ray.origin, &ray.direction, scene)
+    {
+        let ray_direction = ray.direction.normalize();
+
+        let point_on_object = ray.origin + (ray_direction * intersection.distance);
+        let normal = shading_normal(ray, object, &intersection);
+        let query = texture_query(ray, object, &intersection);
+        let diffuse_color = object.material.diffuse_color.get(&query);
+        let parameters = object.material.parameters(&query);
+        let albedo = parameters.albedo;
+        let opacity = parameters.opacity;
+        // where the surface is cut out the light behind it comes straight through
+        let mut passed_component = Color::BLACK;
+        if opacity < 1.0 && bounce_count < max_bounces {
+            passed_component = cast_ray(
+                max_bounces,
+                sampler,
+                &ray.continued(
+                    point_on_object + ray_direction * SURFACE_OFFSET,
+                    ray_direction,
+                    intersection.distance,
+                ),
+                scene,
+                bounce_count + 1,
+                media,
+            ) * (1.0 - opacity);
+        }
+        let microfacet = parameters
+            .metallic_roughness
+            .map(|metallic_roughness| Microfacet::new(&metallic_roughness, diffuse_color));
+
+        let reflection = match &microfacet {
+            Some(microfacet) => Reflection::MirroredMicrofacet(microfacet),
+            None => Reflection::new(None, &parameters),
+        };
+        let direct_light = direct_light(
+            object,
+            &point_on_object,
+            &normal,
+            &ray_direction,
+            &reflection,
+            scene,
+            sampler,
+        );
+
+        // the BRDF already covers the shading, only the mirror reflection has to be traced
+        if let Some(microfacet) = &microfacet {
+            let entering = ray_direction * intersection.normal < 0.0;
+            let facing_normal = if entering { normal } else { -normal };
+            let mut reflection_component = Color::BLACK;
+            if bounce_count < max_bounces {
+                let reflection_origin = if entering {
+                    object.surface.approximate_outside(point_on_object)
+                } else {
+                    object.surface.approximate_inside(point_on_object)
+                };
+                reflection_component = cast_ray(
+                    max_bounces,
+                    sampler,
+                    &ray.continued(
+                        reflection_origin,
+                        -ray_direction.reflection(&normal),
+                        intersection.distance,
+                    ),
+                    scene,
+                    bounce_count + 1,
+                    media,
+                ) * microfacet
+                    .mirror_reflectance(&facing_normal, &-ray_direction);
+            }
+            return media.attenuation(intersection.distance)
+                * ((direct_light.microfacet + reflection_component + object.material.emission)
+                    * opacity
+                    + passed_component);
+        }
+
+        let mut reflection_component = Color::BLACK;
+        let mut refraction_component = Color::BLACK;
+
+        if bounce_count < max_bounces {
+            let entering = ray_direction * intersection.normal < 0.0;
+            let facing_normal = if entering { normal } else { -normal };
+            let next_media = media.crossing(object, entering);
+
+            // the refraction albedo is split between the reflected and the transmitted ray by
+            // the Fresnel term, all of it is reflected on total internal reflection
+            let (current_index, next_index) =
+                (media.refractive_index(), next_media.refractive_index());
+            let refraction_direction =
+                ray_direction.refraction(&facing_normal, current_index, next_index);
+            let reflectance = if albedo.refraction > 0.0 {
+                fresnel_reflectance(-ray_direction * facing_normal, current_index, next_index)
+            } else {
+                0.0
+            };
+            let reflection_albedo = albedo.reflection + albedo.refraction * reflectance;
+            let refraction_albedo = albedo.refraction * (1.0 - reflectance);
+
+            if reflection_albedo > 0.0 {
+                let reflection_direction = -ray_direction.reflection(&normal);
+                let reflection_origin = if entering {
+                    object.surface.approximate_outside(point_on_object)
+                } else {
+                    object.surface.approximate_inside(point_on_object)
+                };
+
+                reflection_component = cast_ray(
+                    max_bounces,
+                    sampler,
+                    &ray.continued(
+                        reflection_origin,
+                        reflection_direction,
+                        intersection.distance,
+                    ),
+                    scene,
+                    bounce_count + 1,
+                    media,
+                ) * reflection_albedo;
+            }
+            if let Some(refraction_direction) =
+                refraction_direction.filter(|_| refraction_albedo > 0.0)
+            {
+                let refraction_origin = if entering {
+                    object.surface.approximate_inside(point_on_object)
+                } else {
+                    object.surface.approximate_outside(point_on_object)
+                };
+
+                refraction_component = cast_ray(
+                    max_bounces,
+                    sampler,
+                    &ray.continued(
+                        refraction_origin,
+                        refraction_direction,
+                        intersection.distance,
+                    ),
+                    scene,
+                    bounce_count + 1,
+                    &next_media,
+                ) * refraction_albedo;
+            }
+        }
+
+        // whatever comes back from the surface is absorbed on its way through the medium
+        return media.attenuation(intersection.distance)
+            * ((diffuse_color * direct_light.diffuse * albedo.diffuse
+                + SPEC_BASE_COLOR * direct_light.specular * albedo.specular
+                + reflection_component
+                + refraction_component
+                + object.material.emission)
+                * opacity
+                + passed_component);
+    }
+    media.attenuation(f32::INFINITY) * sky_color(&ray.direction, ray.spread, scene)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::disk::Disk;
+    use crate::scene::material::{Material, Materials, SolidColor};
+    use crate::scene::sphere::Sphere;
+    use crate::scene::{Falloff, Light, Object};
+    use crate::vector_math::Vec3;
+
+    fn lit_floor(blocker: Option<Sphere>) -> f32 {
+        let mut objects = vec![floor(0.0, Color::WHITE)];
+        if let Some(blocker) = blocker {
+            objects.push(Object {
+                surface: Box::new(blocker),
+                material: Materials::solid_color(Color::BLACK),
+            });
+        }
+        let light = Light::area(
+            Box::new(Sphere {
+                origin: Vec3::new(0.0, 10.0, 0.0),
+                radius: 2.0,
+            }),
+            1.0,
+            256,
+        )
+        .unwrap();
+        let scene = Scene::new(objects, vec![light], Materials::solid_color(Color::BLACK));
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+
+        let (r, _, _) = Whitted::default()
+            .radiance(&ray, &scene, &mut Sampler::new(1))
+            .as_channels();
+        r
+    }
+
+    #[test]
+    fn area_light_casts_soft_shadows() {
+        let unblocked = lit_floor(None);
+        let fully_blocked = lit_floor(Some(Sphere {
+            origin: Vec3::new(0.0, 5.0, 0.0),
+            radius: 3.0,
+        }));
+        // the blocker only covers part of the light as seen from the floor
+        let penumbra = lit_floor(Some(Sphere {
+            origin: Vec3::new(0.6, 5.0, 0.0),
+            radius: 0.5,
+        }));
+
+        assert!(unblocked > 0.9, "{}", unblocked);
+        assert_eq!(fully_blocked, 0.0);
+        assert!(penumbra > 0.1 && penumbra < unblocked - 0.1, "{}", penumbra);
+    }
+
+    fn floor_under(light: Light) -> Color {
+        let scene = Scene::new(
+            vec![floor(0.0, Color::WHITE)],
+            vec![light],
+            Materials::solid_color(Color::BLACK),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        Whitted::default().radiance(&ray, &scene, &mut Sampler::new(1))
+    }
+
+    #[test]
+    fn light_color_tints_diffuse_lighting() {
+        let mut sun = Light::directional(Vec3::new(-1.0, -1.0, 0.0), 1.0);
+        sun.color = Color::new(1.0, 0.5, 0.0);
+
+        let (r, g, b) = floor_under(sun).as_channels();
+
+        // the sun comes in at 45 degrees
+        let cosine = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((r - cosine).abs() < 1e-4 && (g - cosine * 0.5).abs() < 1e-4 && b == 0.0);
+    }
+
+    #[test]
+    fn inverse_square_falloff_dims_distant_lights() {
+        let mut light = Light::point(Vec3::new(0.0, 2.0, 0.0), 1.0);
+        light.falloff = Falloff::InverseSquare;
+
+        assert_eq!(floor_under(light), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn bright_lights_are_not_clipped() {
+        // range handling is left to the tone mapping of the output
+        assert_eq!(
+            floor_under(Light::point(Vec3::new(0.0, 2.0, 0.0), 4.0)),
+            Color::new(4.0, 4.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn spot_light_only_lights_inside_its_cone() {
+        let pointing_at_floor = Light::spot(
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            20.0,
+            5.0,
+            1.0,
+        );
+        let pointing_away = Light::spot(
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            20.0,
+            5.0,
+            1.0,
+        );
+
+        assert_eq!(floor_under(pointing_at_floor), Color::WHITE);
+        assert_eq!(floor_under(pointing_away), Color::BLACK);
+    }
+
+    #[test]
+    fn metal_reflects_emitters_once() {
+        // the mirror ray and the shadow rays towards the emitter share the specular lobe
+        let scene = Scene::new(
+            vec![
+                Object {
+                    material: Materials::metallic_roughness(Color::new(0.9, 0.9, 0.9), 1.0, 0.3),
+                    ..floor(0.0, Color::BLACK)
+                },
+                Object {
+                    surface: Box::new(Disk {
+                        center: Vec3::new(0.0, 2.0, 0.0),
+                        normal: Vec3::new(0.0, -1.0, 0.0),
+                        radius: 2.0,
+                    }),
+                    material: Materials::emissive(Color::WHITE),
+                },
+            ],
+            vec![],
+            Materials::solid_color(Color::BLACK),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(1);
+        let mut sum = 0.0;
+        for _ in 0..200 {
+            let (r, _, _) = Whitted::default()
+                .radiance(&ray, &scene, &mut sampler)
+                .as_channels();
+            sum += r;
+        }
+
+        let r = sum / 200.0;
+        assert!((r - 0.9).abs() < 0.05, "{}", r);
+    }
+
+    #[test]
+    fn metal_reflects_the_sky_once() {
+        let mut scene = Scene::new(
+            vec![Object {
+                material: Materials::metallic_roughness(Color::new(0.9, 0.9, 0.9), 1.0, 0.3),
+                ..floor(0.0, Color::BLACK)
+            }],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+        scene.light_with_sky(16);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(1);
+        let mut sum = 0.0;
+        for _ in 0..4000 {
+            let (r, _, _) = Whitted::default()
+                .radiance(&ray, &scene, &mut sampler)
+                .as_channels();
+            sum += r;
+        }
+
+        let r = sum / 4000.0;
+        assert!((r - 0.9).abs() < 0.05, "{}", r);
+    }
+
+    fn floor_in_shadow_of(blocker_material: Material) -> Color {
+        let scene = Scene::new(
+            vec![
+                floor(0.0, Color::WHITE),
+                Object {
+                    surface: Box::new(Sphere {
+                        origin: Vec3::new(0.0, 3.0, 0.0),
+                        radius: 0.5,
+                    }),
+                    material: blocker_material,
+                },
+            ],
+            vec![Light::point(Vec3::new(0.0, 5.0, 0.0), 1.0)],
+            Materials::solid_color(Color::BLACK),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        Whitted::default().radiance(&ray, &scene, &mut Sampler::new(1))
+    }
+
+    #[test]
+    fn glass_lets_light_through_its_shadow() {
+        let clear = floor_in_shadow_of(glass());
+        let mut red_glass = glass();
+        red_glass.diffuse_color = Arc::new(SolidColor(Color::new(0.5, 0.1, 0.1)));
+        let tinted = floor_in_shadow_of(red_glass);
+        let opaque = floor_in_shadow_of(Materials::solid_color(Color::WHITE));
+
+        // the shadow ray crosses the surface of the sphere twice
+        let (r, g, b) = clear.as_channels();
+        assert!((r - 0.81).abs() < 1e-4 && r == g && g == b, "{}", r);
+        let (r, g, b) = tinted.as_channels();
+        assert!((r - 0.81).abs() < 1e-4 && (g - 0.0324).abs() < 1e-4 && g == b);
+        assert_eq!(opaque, Color::BLACK);
+    }
+
+    #[test]
+    fn half_opaque_blockers_let_half_the_light_through() {
+        let mut veil = Materials::solid_color(Color::WHITE);
+        veil.opacity = 0.5;
+
+        // once through the top of the sphere and once through its bottom
+        assert_eq!(floor_in_shadow_of(veil), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn cut_out_surfaces_show_what_is_behind_them_unbent() {
+        let seen_through = |material: Material| {
+            let scene = Scene::new(
+                vec![Object {
+                    surface: Box::new(Sphere {
+                        origin: Vec3::new(0.0, 0.0, 0.0),
+                        radius: 1.0,
+                    }),
+                    material,
+                }],
+                vec![],
+                Materials::solid_color(Color::WHITE),
+            );
+            let ray = Ray::new(Vec3::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            Whitted::default().radiance(&ray, &scene, &mut Sampler::new(1))
+        };
+        let mut cut_out_glass = Materials::dielectric(1.5, Color::BLACK);
+        cut_out_glass.opacity = 0.0;
+        let mut half_black = Materials::solid_color(Color::BLACK);
+        half_black.opacity = 0.5;
+
+        assert_eq!(seen_through(cut_out_glass), Color::WHITE);
+        assert_eq!(seen_through(half_black), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn absorbing_glass_tints_what_is_seen_through_it() {
+        // light entering the sphere head on travels at least its diameter inside
+        let scene = Scene::new(
+            vec![Object {
+                surface: Box::new(Sphere {
+                    origin: Vec3::new(0.0, 0.0, 0.0),
+                    radius: 1.0,
+                }),
+                material: Materials::dielectric(1.5, Color::new(0.0, 0.5, 0.5)),
+            }],
+            vec![],
+            Materials::solid_color(Color::WHITE),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let (r, g, b) = Whitted::default()
+            .radiance(&ray, &scene, &mut Sampler::new(1))
+            .as_channels();
+
+        // all that isn't absorbed is either reflected or let through
+        assert!(r > 0.95, "{}", r);
+        assert!(g < 0.05 + (-1.0f32).exp() && g == b, "{}", g);
+    }
+}
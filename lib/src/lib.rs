@@ -1,220 +1,186 @@
 pub mod buffer;
+pub mod camera;
+pub mod color;
 mod common;
+pub mod film;
+pub mod integrator;
 mod ray_tracing;
-mod scene;
+pub mod sampling;
+pub mod scene;
+pub mod tone_mapping;
 pub mod vector_math;
 
-use common::DEBUG_PINK;
-use ray_tracing::Intersection;
-use scene::sphere::Sphere;
-use std::ptr;
 use std::sync::{mpsc, Arc};
 use threadpool::ThreadPool;
 
-use scene::{Object, Scene, Surface};
+use scene::Scene;
 
-use crate::buffer::{Buffer, Point, Rgb};
-use crate::vector_math::Vec3;
+use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::integrator::{Integrator, Ray, Whitted};
+use crate::sampling::{Filter, SamplePattern, Sampler};
 
-pub const WIDTH: u32 = 1920;
-pub const HEIGHT: u32 = 1024;
 pub const CHANNELS: u8 = 3;
 pub const THREADS: usize = 16;
 
-const HORIZONTAL_FOV: f32 = 90.0;
+pub struct RenderSettings {
+    pub samples_per_pixel: u32,
+    pub sample_pattern: SamplePattern,
+    pub filter: Filter,
+    pub integrator: Arc<dyn Integrator>,
+}
 
-pub fn draw(buffer: &mut Buffer, camera_position: Vec3, looking_direction: Vec3) {
-    let (in_world_top_left, in_world_pixel_x_offset, in_world_pixel_y_offset) =
-        set_up_3d_world(camera_position, looking_direction);
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            samples_per_pixel: 1,
+            sample_pattern: SamplePattern::Stratified,
+            filter: Filter::default(),
+            integrator: Arc::new(Whitted::default()),
+        }
+    }
+}
 
-    let scene = Arc::new(scene::create_scene());
+pub fn draw(buffer: &mut Buffer, scene: &Arc<Scene>, camera: &Camera, settings: &RenderSettings) {
+    assert!(
+        *buffer.width() == camera.width && *buffer.height() == camera.height,
+        "buffer size must match the camera resolution"
+    );
+    let viewport = camera.viewport();
+    let samples_per_pixel = settings.samples_per_pixel.max(1);
+    let sample_pattern = settings.sample_pattern;
 
     let pool = ThreadPool::new(THREADS);
     let (tx, rx) = mpsc::channel();
 
-    for x in 0..WIDTH {
-        for y in 0..HEIGHT {
-            // mapping between the pixel on the png and the vector looking at its representation
-            // on the virtual screen from the pov of the camera
-            let camera_to_pixel_direction = in_world_pixel_x_offset * x as f32
-                + in_world_pixel_y_offset * y as f32
-                + in_world_top_left
-                - camera_position;
-
+    for x in 0..camera.width {
+        for y in 0..camera.height {
             let tx = tx.clone();
-            let scene = Arc::clone(&scene);
+            let scene = Arc::clone(scene);
+            let integrator = Arc::clone(&settings.integrator);
 
             pool.execute(move || {
-                tx.send((
-                    x,
-                    y,
-                    cast_ray(
-                        &camera_position,
-                        &camera_to_pixel_direction,
-                        &scene,
-                        0,
-                        None,
-                    ),
-                ))
-                .unwrap();
+                let mut sampler = Sampler::for_pixel(x, y);
+                let pixel_samples = sample_pattern.generate(samples_per_pixel, &mut sampler);
+                let lens_samples =
+                    sample_pattern.generate_shuffled(samples_per_pixel, &mut sampler);
+
+                let samples: Vec<_> = pixel_samples
+                    .into_iter()
+                    .zip(lens_samples)
+                    .map(|((dx, dy), lens_sample)| {
+                        let (sample_x, sample_y) = (x as f32 + dx, y as f32 + dy);
+                        let (ray_origin, ray_direction) =
+                            viewport.ray_through(sample_x, sample_y, lens_sample);
+                        let ray = Ray {
+                            spread: viewport.pixel_spread(),
+                            ..Ray::new(ray_origin, ray_direction)
+                        };
+                        let color = integrator.radiance(&ray, &scene, &mut sampler);
+                        (sample_x, sample_y, color)
+                    })
+                    .collect();
+                tx.send(samples).unwrap();
             });
         }
     }
     drop(tx);
-    for (x, y, color) in rx {
-        buffer.set(&Point(x, y), &color);
-    }
-}
-
-fn cast_ray(
-    ray_origin: &Vec3,
-    ray_direction: &Vec3,
-    scene: &Scene,
-    bounce_count: u8,
-    current_medium: Option<&Object>,
-) -> Rgb {
-    if let Some((object, intersection)) =
-        ray_tracing::scene_intersect(ray_origin, ray_direction, &scene)
-    {
-        let mut diffuse_intensity: f32 = 0.0;
-        let mut specular_intensity: f32 = 0.0;
-
-        let ray_direction = ray_direction.normalize();
-
-        let point_on_object = *ray_origin + (ray_direction * intersection.distance);
-        let normal = intersection.normal.normalize();
-
-        for light in &scene.lights {
-            let light_direction = (light.origin - point_on_object).normalize();
-
-            if let Some((obstructing_object, _)) = ray_tracing::scene_intersect(
-                &light.origin,
-                &(point_on_object - light.origin),
-                &scene,
-            ) {
-                if !ptr::eq(obstructing_object, object) {
-                    continue;
-                }
-            }
-
-            diffuse_intensity += (light_direction * normal).max(0.0) * light.intensity;
-
-            specular_intensity += (light_direction.reflection(&normal) * -ray_direction)
-                .max(0.0)
-                .powf(object.material.shininess);
-        }
-
-        let mut reflection_component = Rgb::new(0, 0, 0);
-        let mut refraction_component = Rgb::new(0, 0, 0);
-
-        if bounce_count < BOUNCE_LIMIT {
-            if object.material.albedo.2 > 0.0 {
-                let reflection_direction = -ray_direction.reflection(&normal);
-
-                let reflection_origin = *ray_origin + (ray_direction * intersection.distance);
-
-                let reflection_origin = object.surface.approximate_outside(reflection_origin);
-
-                reflection_component = cast_ray(
-                    &reflection_origin,
-                    &reflection_direction,
-                    scene,
-                    bounce_count + 1,
-                    None,
-                ) * object.material.albedo.2;
-            }
-            if object.material.albedo.3 > 0.0 {
-                let next_refraction_medium = match current_medium {
-                    None => Some(object),
-                    Some(_) => None,
-                };
-
-                let normal = normal * current_medium.map_or(1.0, |_| -1.0);
-
-                let get_reflective_index = |object: &Object| object.material.refractive_index;
 
-                let current_index = current_medium.map_or(1.0, get_reflective_index);
-                let next_index = next_refraction_medium.map_or(1.0, get_reflective_index);
-
-                let refraction_direciton =
-                    ray_direction.refraction(&normal, current_index, next_index);
-
-                let refraction_origin = *ray_origin + (ray_direction * (intersection.distance));
-
-                let refraction_origin = match current_medium {
-                    Some(object) => object.surface.approximate_outside(refraction_origin),
-                    None => object.surface.approximate_inside(refraction_origin),
-                };
-
-                refraction_component = cast_ray(
-                    &refraction_origin,
-                    &refraction_direciton,
-                    scene,
-                    bounce_count + 1,
-                    next_refraction_medium,
-                ) * object.material.albedo.3;
-            }
+    let mut film = Film::new(camera.width, camera.height, settings.filter);
+    for samples in rx {
+        for (x, y, color) in samples {
+            film.add_sample(x, y, color);
         }
-
-        return object
-            .material
-            .diffuse_color
-            .get(intersection.texture_coords)
-            .clone()
-            * (diffuse_intensity * (object.material.albedo.0)).min(1.0)
-            + SPEC_BASE_COLOR.clone() * (specular_intensity * object.material.albedo.1)
-            + reflection_component
-            + refraction_component;
     }
-    get_sky_color(ray_direction, scene)
+    film.write_to(buffer);
 }
 
-fn get_sky_color(ray_direction: &Vec3, scene: &Scene) -> Rgb {
-    const SKY_SPHERE: Sphere = Sphere {
-        origin: Vec3::new(0.0, 0.0, 0.0),
-        radius: 1.0,
-    };
-    const WORLD_ORIGIN: Vec3 = Vec3::new(0.0, 0.0, 0.0);
-    if let Some(Intersection { texture_coords, .. }) =
-        SKY_SPHERE.find_intersection(&WORLD_ORIGIN, ray_direction)
-    {
-        return scene.sky_sphere.diffuse_color.get(texture_coords);
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::Dimensions;
+    use crate::color::Color;
+    use crate::scene::material::Materials;
+    use crate::scene::rect::Rect;
+    use crate::scene::sphere::Sphere;
+    use crate::scene::Object;
+    use crate::vector_math::Vec3;
+
+    const WIDTH: u32 = 32;
+    const HEIGHT: u32 = 24;
+
+    fn test_scene() -> Arc<Scene> {
+        Arc::new(Scene::new(
+            vec![
+                Object {
+                    surface: Box::new(Sphere {
+                        origin: Vec3::new(-0.5, 0.0, -3.0),
+                        radius: 0.5,
+                    }),
+                    material: Materials::solid_color(Color::from_bytes(200, 30, 30)),
+                },
+                Object {
+                    surface: Box::new(Sphere {
+                        origin: Vec3::new(1.0, 0.3, -6.0),
+                        radius: 1.0,
+                    }),
+                    material: Materials::solid_color(Color::from_bytes(30, 200, 30)),
+                },
+                Object {
+                    surface: Box::new(Rect::new(
+                        Vec3::new(-5.0, -1.0, 0.0),
+                        Vec3::new(-5.0, -1.0, -10.0),
+                        Vec3::new(5.0, -1.0, -10.0),
+                        Vec3::new(5.0, -1.0, 0.0),
+                    )),
+                    material: Materials::solid_color(Color::from_bytes(180, 120, 60)),
+                },
+            ],
+            vec![scene::Light::point(Vec3::new(2.0, 5.0, 0.0), 1.0)],
+            Materials::solid_color(Color::from_bytes(100, 150, 250)),
+        ))
     }
-    DEBUG_PINK
-}
-
-const SPEC_BASE_COLOR: Rgb = Rgb::new(255, 255, 255);
-
-const BOUNCE_LIMIT: u8 = 4;
 
-fn set_up_3d_world(camera_position: Vec3, looking_direction: Vec3) -> (Vec3, Vec3, Vec3) {
-    let vertical_fov = (HEIGHT as f32 * (HORIZONTAL_FOV / 2.0).to_radians().tan() / WIDTH as f32)
-        .atan()
-        .to_degrees()
-        * 2.0;
-
-    // virtual screen size in world coordinates
-    let in_world_screen_width = 2.0 * (HORIZONTAL_FOV / 2.0).to_radians().tan();
-    let in_world_screen_height = 2.0 * (vertical_fov / 2.0).to_radians().tan();
-
-    let camera_z = -looking_direction.normalize();
-
-    let looking_direction = looking_direction.as_coords();
-    let camera_x = Vec3::new(-looking_direction.2, 0.0, looking_direction.0).normalize();
-    let camera_y = camera_z.cross_product(&camera_x);
-
-    // size of a pixel in the output translated to world coordinates
-    let in_world_pixel_x_offset = camera_x * (in_world_screen_width / (WIDTH as f32));
-    let in_world_pixel_y_offset = camera_y * (-in_world_screen_height / (HEIGHT as f32));
+    fn render(camera: &Camera, settings: &RenderSettings) -> Buffer {
+        let mut buffer = Buffer::new(Dimensions(WIDTH, HEIGHT), CHANNELS);
+        draw(&mut buffer, &test_scene(), camera, settings);
+        buffer
+    }
 
-    // top left of the virtual screen
-    let in_world_top_left = camera_position - camera_x * (in_world_screen_width / 2.0)
-        + camera_y * (in_world_screen_height / 2.0)
-        - camera_z;
+    fn mean_difference(a: &Buffer, b: &Buffer) -> f32 {
+        let (a, b) = (a.to_bytes(), b.to_bytes());
+        let total: u32 = a.iter().zip(&b).map(|(a, b)| a.abs_diff(*b) as u32).sum();
+        total as f32 / a.len() as f32
+    }
 
-    (
-        in_world_top_left,
-        in_world_pixel_x_offset,
-        in_world_pixel_y_offset,
-    )
+    #[test]
+    fn depth_of_field_converges_to_pinhole_image() {
+        let mut camera = Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -3.0),
+            WIDTH,
+            HEIGHT,
+        );
+        let settings = RenderSettings {
+            samples_per_pixel: 16,
+            ..RenderSettings::default()
+        };
+        let pinhole = render(&camera, &settings);
+
+        let differences: Vec<f32> = [0.3, 0.03, 0.003, 0.0]
+            .into_iter()
+            .map(|aperture_radius| {
+                camera.aperture_radius = aperture_radius;
+                mean_difference(&render(&camera, &settings), &pinhole)
+            })
+            .collect();
+
+        assert!(differences[0] > 1.0, "wide aperture must blur the image");
+        for pair in differences.windows(2) {
+            assert!(pair[1] < pair[0], "{:?} must decrease", differences);
+        }
+        assert!(differences[2] < 0.5, "{:?}", differences);
+        assert_eq!(differences[3], 0.0);
+    }
 }
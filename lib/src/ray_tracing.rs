@@ -1,3 +1,9 @@
+mod aabb;
+mod bvh;
+
+pub use aabb::Aabb;
+pub use bvh::Bvh;
+
 use crate::scene::{Object, Scene};
 use crate::vector_math::Vec3;
 
@@ -6,45 +12,44 @@ pub fn scene_intersect<'a>(
     ray_direction: &Vec3,
     scene: &'a Scene,
 ) -> Option<(&'a Object, Intersection)> {
-    let mut closest = f32::MAX;
-    let mut closest_object: Option<(&Object, Intersection)> = None;
-    for object in &scene.objects {
-        closest_object = match object
-            .surface
-            .find_intersection(&ray_origin, &ray_direction)
-        {
-            Some(intersection) => {
-                if intersection.distance < closest && intersection.distance > 0.0 {
-                    closest = intersection.distance;
-                    Some((object, intersection))
-                } else {
-                    closest_object
-                }
-            }
-            None => closest_object,
-        }
-    }
-    closest_object
+    let objects = scene.objects();
+    scene
+        .bvh()
+        .closest_intersection(ray_origin, ray_direction, |idx| {
+            objects[idx]
+                .surface
+                .find_intersection(ray_origin, ray_direction)
+                .filter(|intersection| intersection.distance > 0.0)
+        })
+        .map(|(idx, intersection)| (&objects[idx], intersection))
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Intersection {
     pub distance: f32,
+    /// normal of the geometry, which side of the surface the ray comes from is decided by it
     pub normal: Vec3,
+    /// normal the surface is shaded with, smoothed over the faces of meshes with vertex normals
+    pub shading_normal: Vec3,
     pub texture_coords: Option<(f32, f32)>,
+    /// unit vectors along the surface in the directions the texture coordinates grow in, `u`
+    /// along the tangent and `v` along the bitangent
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::buffer::Rgb;
+    use crate::color::Color;
+    use crate::common::test::*;
     use crate::scene::{material::Materials, sphere::Sphere};
     use std::ptr;
 
     #[test]
     fn scene_intersect_picks_closest() {
-        let scene = Scene {
-            objects: vec![
+        let scene = Scene::new(
+            vec![
                 Object {
                     surface: Box::new(Sphere {
                         origin: Vec3::new(3.0, 0.0, 0.0),
@@ -67,16 +72,70 @@ mod tests {
                     material: Materials::solid_color(BLACK),
                 },
             ],
-            lights: vec![],
-            sky_sphere: Materials::solid_color(BLACK),
-        };
+            vec![],
+            Materials::solid_color(BLACK),
+        );
 
         let intersection =
             scene_intersect(&Vec3::new(0.0, 0.0, 0.0), &Vec3::new(1.0, 0.0, 0.0), &scene);
 
-        assert!(ptr::eq(intersection.unwrap().0, &scene.objects[0]));
+        assert!(ptr::eq(intersection.unwrap().0, &scene.objects()[0]));
+    }
+
+    fn brute_force_scene_intersect<'a>(
+        ray_origin: &Vec3,
+        ray_direction: &Vec3,
+        scene: &'a Scene,
+    ) -> Option<(&'a Object, Intersection)> {
+        let mut closest: Option<(&Object, Intersection)> = None;
+        for object in scene.objects() {
+            if let Some(intersection) = object.surface.find_intersection(ray_origin, ray_direction)
+            {
+                let closest_distance = closest.as_ref().map_or(f32::MAX, |(_, i)| i.distance);
+                if intersection.distance > 0.0 && intersection.distance < closest_distance {
+                    closest = Some((object, intersection));
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn scene_intersect_matches_brute_force() {
+        let mut random = SmallRng::seed_from_u64(3);
+        let objects = (0..200)
+            .map(|_| Object {
+                surface: Box::new(Sphere {
+                    origin: random_vec3(&mut random, 40.0),
+                    radius: 0.2 + random.gen::<f32>() * 2.0,
+                }),
+                material: Materials::solid_color(RED),
+            })
+            .collect();
+        let scene = Scene::new(objects, vec![], Materials::solid_color(BLACK));
+
+        for _ in 0..2000 {
+            let ray_origin = random_vec3(&mut random, 50.0);
+            let ray_direction = random_vec3(&mut random, 1.0);
+
+            let expected = brute_force_scene_intersect(&ray_origin, &ray_direction, &scene);
+            let actual = scene_intersect(&ray_origin, &ray_direction, &scene);
+
+            match (expected, actual) {
+                (Some((expected_object, expected)), Some((actual_object, actual))) => {
+                    assert!(ptr::eq(expected_object, actual_object));
+                    assert_eq!(expected, actual);
+                }
+                (None, None) => {}
+                (expected, actual) => panic!(
+                    "bvh found {:?} where brute force found {:?}",
+                    actual.map(|(_, i)| i),
+                    expected.map(|(_, i)| i)
+                ),
+            }
+        }
     }
 
-    const RED: Rgb = Rgb::new(255, 0, 0);
-    const BLACK: Rgb = Rgb::new(0, 0, 0);
+    const RED: Color = Color::new(1.0, 0.0, 0.0);
+    const BLACK: Color = Color::BLACK;
 }
@@ -0,0 +1,154 @@
+use crate::vector_math::Vec3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub const fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points
+            .iter()
+            .fold(Aabb::empty(), |aabb, point| aabb.grow(point))
+    }
+
+    pub fn grow(self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: self.min.component_min(point),
+            max: self.max.component_max(point),
+        }
+    }
+
+    pub fn union(self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.component_min(&other.min),
+            max: self.max.component_max(&other.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let (x, y, z) = (self.max - self.min).as_coords();
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        (0..3)
+            .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+            .unwrap()
+    }
+
+    // slab test, returns the distances at which the ray enters and leaves the box;
+    // the direction is expected to be normalized and passed in as its component-wise inverse
+    pub fn intersect(&self, ray_origin: &Vec3, inverse_direction: &Vec3) -> Option<(f32, f32)> {
+        let mut t_enter = 0.0_f32;
+        let mut t_exit = f32::MAX;
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - ray_origin[axis]) * inverse_direction[axis];
+            let t2 = (self.max[axis] - ray_origin[axis]) * inverse_direction[axis];
+
+            t_enter = t_enter.max(t1.min(t2));
+            t_exit = t_exit.min(t1.max(t2));
+        }
+        // rounding errors in the slab distances must not make rays that graze the box miss it
+        if t_enter <= t_exit * (1.0 + ROUNDING_MARGIN) {
+            Some((t_enter, t_exit))
+        } else {
+            None
+        }
+    }
+}
+
+const ROUNDING_MARGIN: f32 = 4.0 * f32::EPSILON;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inverse(direction: Vec3) -> Vec3 {
+        let (x, y, z) = direction.normalize().as_coords();
+        Vec3::new(1.0 / x, 1.0 / y, 1.0 / z)
+    }
+
+    const UNIT_BOX: Aabb = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+
+    #[test]
+    fn ray_hits_box() {
+        let hit = UNIT_BOX.intersect(
+            &Vec3::new(-3.0, 0.0, 0.0),
+            &inverse(Vec3::new(1.0, 0.0, 0.0)),
+        );
+
+        assert_eq!(hit, Some((2.0, 4.0)));
+    }
+
+    #[test]
+    fn ray_misses_box() {
+        let hit = UNIT_BOX.intersect(
+            &Vec3::new(-3.0, 2.0, 0.0),
+            &inverse(Vec3::new(1.0, 0.0, 0.0)),
+        );
+
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn box_behind_ray_is_missed() {
+        let hit = UNIT_BOX.intersect(
+            &Vec3::new(3.0, 0.0, 0.0),
+            &inverse(Vec3::new(1.0, 0.0, 0.0)),
+        );
+
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn ray_starting_inside_box() {
+        let hit = UNIT_BOX.intersect(
+            &Vec3::new(0.0, 0.0, 0.0),
+            &inverse(Vec3::new(0.0, 1.0, 0.0)),
+        );
+
+        assert_eq!(hit, Some((0.0, 1.0)));
+    }
+
+    #[test]
+    fn union_and_surface_area() {
+        let aabb = Aabb::from_points(&[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0)]).union(
+            &Aabb::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 1.0)),
+        );
+
+        assert_eq!(
+            aabb,
+            Aabb::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0))
+        );
+        assert_eq!(
+            aabb.surface_area(),
+            2.0 * (2.0 * 2.0 + 2.0 * 3.0 + 3.0 * 2.0)
+        );
+        assert_eq!(aabb.longest_axis(), 2);
+    }
+}
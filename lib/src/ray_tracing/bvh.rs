@@ -0,0 +1,313 @@
+use super::aabb::Aabb;
+use super::Intersection;
+use crate::vector_math::Vec3;
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over a list of primitives, built with the surface area heuristic.
+/// It only stores indices, so the primitives themselves stay wherever the caller keeps them.
+#[derive(Debug, PartialEq)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+const BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+// cost of visiting a node relative to intersecting a single primitive
+const TRAVERSAL_COST: f32 = 0.5;
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Vec3> = bounds.iter().map(Aabb::centroid).collect();
+            bvh.build_node(bounds, &centroids, 0, bounds.len());
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or(Aabb::empty(), |root| *root.bounds())
+    }
+
+    fn build_node(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        first: usize,
+        count: usize,
+    ) -> usize {
+        let primitives = first..first + count;
+        let node_bounds = self.indices[primitives.clone()]
+            .iter()
+            .fold(Aabb::empty(), |aabb, idx| aabb.union(&bounds[*idx]));
+
+        let node_idx = self.nodes.len();
+        self.nodes.push(Node::Leaf {
+            bounds: node_bounds,
+            first,
+            count,
+        });
+
+        let centroid_bounds = self.indices[primitives.clone()]
+            .iter()
+            .fold(Aabb::empty(), |aabb, idx| aabb.grow(&centroids[*idx]));
+
+        let Some((axis, split_bin, split_cost)) = find_split(
+            bounds,
+            centroids,
+            &self.indices[primitives],
+            &node_bounds,
+            &centroid_bounds,
+        ) else {
+            // all centroids coincide, there is nothing to gain from splitting
+            return node_idx;
+        };
+
+        let leaf_cost = count as f32;
+        if count <= MAX_LEAF_SIZE && split_cost >= leaf_cost {
+            return node_idx;
+        }
+
+        let (left_count, right_count) = {
+            let primitives = &mut self.indices[first..first + count];
+            let (left, right): (Vec<usize>, Vec<usize>) = primitives
+                .iter()
+                .partition(|idx| bin_of(&centroids[**idx], &centroid_bounds, axis) <= split_bin);
+            let left_count = left.len();
+            for (slot, idx) in primitives.iter_mut().zip(left.into_iter().chain(right)) {
+                *slot = idx;
+            }
+            (left_count, count - left_count)
+        };
+
+        let left = self.build_node(bounds, centroids, first, left_count);
+        let right = self.build_node(bounds, centroids, first + left_count, right_count);
+        self.nodes[node_idx] = Node::Interior {
+            bounds: node_bounds,
+            left,
+            right,
+        };
+        node_idx
+    }
+
+    /// Finds the closest intersection along the ray. `intersect` is called with the index of
+    /// every primitive whose bounds are hit and should return its intersection with the ray, if any.
+    pub fn closest_intersection<F>(
+        &self,
+        ray_origin: &Vec3,
+        ray_direction: &Vec3,
+        intersect: F,
+    ) -> Option<(usize, Intersection)>
+    where
+        F: Fn(usize) -> Option<Intersection>,
+    {
+        let root = self.nodes.first()?;
+        let (x, y, z) = ray_direction.normalize().as_coords();
+        let inverse_direction = Vec3::new(1.0 / x, 1.0 / y, 1.0 / z);
+
+        let hit_distance = |node: &Node| {
+            node.bounds()
+                .intersect(ray_origin, &inverse_direction)
+                .map(|(enter, _)| enter)
+        };
+
+        let mut closest: Option<(usize, Intersection)> = None;
+        let mut closest_distance = f32::MAX;
+
+        let mut stack = vec![];
+        if let Some(distance) = hit_distance(root) {
+            stack.push((0, distance));
+        }
+
+        while let Some((node_idx, enter_distance)) = stack.pop() {
+            if enter_distance > closest_distance {
+                continue;
+            }
+            match &self.nodes[node_idx] {
+                Node::Leaf { first, count, .. } => {
+                    for idx in &self.indices[*first..first + count] {
+                        if let Some(intersection) = intersect(*idx) {
+                            if intersection.distance < closest_distance {
+                                closest_distance = intersection.distance;
+                                closest = Some((*idx, intersection));
+                            }
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    let left_hit = hit_distance(&self.nodes[*left]).map(|d| (*left, d));
+                    let right_hit = hit_distance(&self.nodes[*right]).map(|d| (*right, d));
+                    // the nearer child goes on top of the stack so it is visited first
+                    match (left_hit, right_hit) {
+                        (Some(l), Some(r)) if l.1 < r.1 => stack.extend([r, l]),
+                        (Some(l), Some(r)) => stack.extend([l, r]),
+                        (Some(hit), None) | (None, Some(hit)) => stack.push(hit),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+fn bin_of(centroid: &Vec3, centroid_bounds: &Aabb, axis: usize) -> usize {
+    let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+    let relative = (centroid[axis] - centroid_bounds.min[axis]) / extent;
+    ((relative * BINS as f32) as usize).min(BINS - 1)
+}
+
+// returns the axis, the last bin of the left half and the estimated cost of the best split
+fn find_split(
+    bounds: &[Aabb],
+    centroids: &[Vec3],
+    primitives: &[usize],
+    node_bounds: &Aabb,
+    centroid_bounds: &Aabb,
+) -> Option<(usize, usize, f32)> {
+    let node_area = node_bounds.surface_area();
+
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        if centroid_bounds.max[axis] - centroid_bounds.min[axis] <= 0.0 {
+            continue;
+        }
+
+        let mut bin_bounds = [Aabb::empty(); BINS];
+        let mut bin_counts = [0_usize; BINS];
+        for idx in primitives {
+            let bin = bin_of(&centroids[*idx], centroid_bounds, axis);
+            bin_bounds[bin] = bin_bounds[bin].union(&bounds[*idx]);
+            bin_counts[bin] += 1;
+        }
+
+        // sweep from the right to know the cost of every right half up front
+        let mut right_costs = [0.0_f32; BINS];
+        let mut right_bounds = Aabb::empty();
+        let mut right_count = 0;
+        for bin in (1..BINS).rev() {
+            right_bounds = right_bounds.union(&bin_bounds[bin]);
+            right_count += bin_counts[bin];
+            right_costs[bin - 1] = right_bounds.surface_area() * right_count as f32;
+        }
+
+        let mut left_bounds = Aabb::empty();
+        let mut left_count = 0;
+        for bin in 0..BINS - 1 {
+            left_bounds = left_bounds.union(&bin_bounds[bin]);
+            left_count += bin_counts[bin];
+            if left_count == 0 || left_count == primitives.len() {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (left_bounds.surface_area() * left_count as f32 + right_costs[bin])
+                    / node_area.max(f32::MIN_POSITIVE);
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, bin, cost));
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    fn points_as_boxes(points: &[Vec3]) -> Vec<Aabb> {
+        points
+            .iter()
+            .map(|point| Aabb::from_points(&[*point - Vec3::new(0.1, 0.1, 0.1), *point]))
+            .collect()
+    }
+
+    #[test]
+    fn every_primitive_ends_up_in_exactly_one_leaf() {
+        let mut random = SmallRng::seed_from_u64(7);
+        let points: Vec<Vec3> = (0..1000).map(|_| random_vec3(&mut random, 10.0)).collect();
+
+        let bvh = Bvh::build(&points_as_boxes(&points));
+
+        let mut seen = vec![0; points.len()];
+        for node in &bvh.nodes {
+            if let Node::Leaf { first, count, .. } = node {
+                for idx in &bvh.indices[*first..first + count] {
+                    seen[*idx] += 1;
+                }
+            }
+        }
+        assert!(seen.iter().all(|times| *times == 1));
+    }
+
+    #[test]
+    fn children_are_contained_in_parent_bounds() {
+        let mut random = SmallRng::seed_from_u64(11);
+        let points: Vec<Vec3> = (0..500).map(|_| random_vec3(&mut random, 10.0)).collect();
+
+        let bvh = Bvh::build(&points_as_boxes(&points));
+
+        for node in &bvh.nodes {
+            if let Node::Interior {
+                bounds,
+                left,
+                right,
+            } = node
+            {
+                for child in [left, right] {
+                    let child_bounds = bvh.nodes[*child].bounds();
+                    assert_eq!(bounds.union(child_bounds), *bounds);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn identical_primitives_form_a_single_leaf() {
+        let bounds = vec![Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)); 10];
+
+        let bvh = Bvh::build(&bounds);
+
+        assert_eq!(bvh.nodes.len(), 1);
+    }
+
+    #[test]
+    fn empty_bvh_has_no_intersections() {
+        let bvh = Bvh::build(&[]);
+
+        let intersection = bvh.closest_intersection(
+            &Vec3::new(0.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, -1.0),
+            |_| panic!("there are no primitives to intersect"),
+        );
+
+        assert_eq!(intersection, None);
+    }
+}
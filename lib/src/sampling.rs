@@ -0,0 +1,518 @@
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+
+use crate::vector_math::Vec3;
+
+/// How the sample positions within a pixel (and on the lens) are chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplePattern {
+    /// centers of a regular grid of strata
+    Stratified,
+    /// a random point within every stratum of the grid
+    Jittered,
+    /// Halton sequence in bases 2 and 3
+    Halton,
+    /// first two dimensions of the Sobol sequence
+    Sobol,
+}
+
+impl SamplePattern {
+    /// `count` points in 0..1 on both axes. Low discrepancy sequences are randomized per call
+    /// so that neighbouring pixels don't share the exact same pattern.
+    pub fn generate<R: Rng>(&self, count: u32, random: &mut R) -> Vec<(f32, f32)> {
+        match self {
+            SamplePattern::Stratified => grid(count, |_| (0.5, 0.5)),
+            SamplePattern::Jittered => grid(count, |_| (random.gen(), random.gen())),
+            SamplePattern::Halton => {
+                // Cranley-Patterson rotation keeps the points evenly spread but shifts them
+                let (shift_x, shift_y): (f32, f32) = random.gen();
+                (0..count)
+                    .map(|idx| {
+                        let (x, y) = halton(idx);
+                        (wrap(x + shift_x), wrap(y + shift_y))
+                    })
+                    .collect()
+            }
+            SamplePattern::Sobol => {
+                // xor scrambling preserves the stratification of the sequence
+                let (scramble_x, scramble_y): (u32, u32) = random.gen();
+                (0..count)
+                    .map(|idx| {
+                        (
+                            to_unit(van_der_corput(idx) ^ scramble_x),
+                            to_unit(sobol(idx) ^ scramble_y),
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Same as `generate` but in random order, used to pair up lens samples with pixel samples
+    /// without correlating the two.
+    pub fn generate_shuffled<R: Rng>(&self, count: u32, random: &mut R) -> Vec<(f32, f32)> {
+        let mut samples = self.generate(count, random);
+        samples.shuffle(random);
+        samples
+    }
+}
+
+/// Source of random numbers handed to integrators, one per pixel and seeded by its position
+/// so that renders are reproducible regardless of the order pixels are worked on.
+pub struct Sampler {
+    random: SmallRng,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        Sampler {
+            random: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn for_pixel(x: u32, y: u32) -> Sampler {
+        Sampler::new(((y as u64) << 32) | x as u64)
+    }
+
+    /// uniform in 0..1
+    pub fn next_1d(&mut self) -> f32 {
+        self.random.gen()
+    }
+
+    /// uniform in 0..1 on both axes
+    pub fn next_2d(&mut self) -> (f32, f32) {
+        self.random.gen()
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.random.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.random.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.random.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.random.try_fill_bytes(dest)
+    }
+}
+
+// splits the unit square in as square a grid as possible and places a point in each cell,
+// cells past `count` in the last row are left out
+fn grid<F>(count: u32, mut offset_in_cell: F) -> Vec<(f32, f32)>
+where
+    F: FnMut(u32) -> (f32, f32),
+{
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+    (0..count)
+        .map(|idx| {
+            let (dx, dy) = offset_in_cell(idx);
+            (
+                ((idx % columns) as f32 + dx) / columns as f32,
+                ((idx / columns) as f32 + dy) / rows as f32,
+            )
+        })
+        .collect()
+}
+
+fn wrap(value: f32) -> f32 {
+    let wrapped = value.fract();
+    if wrapped >= 1.0 {
+        0.0
+    } else {
+        wrapped
+    }
+}
+
+fn to_unit(bits: u32) -> f32 {
+    // 24 bits are all an f32 mantissa can hold, more would round up to 1.0
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+fn radical_inverse(mut idx: u32, base: u32) -> f32 {
+    let inverse_base = 1.0 / base as f32;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    while idx > 0 {
+        result += (idx % base) as f32 * factor;
+        idx /= base;
+        factor *= inverse_base;
+    }
+    result
+}
+
+fn halton(idx: u32) -> (f32, f32) {
+    (radical_inverse(idx, 2), radical_inverse(idx, 3))
+}
+
+fn van_der_corput(idx: u32) -> u32 {
+    idx.reverse_bits()
+}
+
+// second dimension of the Sobol sequence, the first one is van der Corput
+fn sobol(mut idx: u32) -> u32 {
+    let mut direction = 1 << 31;
+    let mut result = 0;
+    while idx != 0 {
+        if idx & 1 == 1 {
+            result ^= direction;
+        }
+        idx >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// maps the unit square onto the unit disk keeping the samples evenly spread
+pub(crate) fn concentric_disk((x, y): (f32, f32)) -> (f32, f32) {
+    let (x, y) = (2.0 * x - 1.0, 2.0 * y - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, angle) = if x.abs() > y.abs() {
+        (x, std::f32::consts::FRAC_PI_4 * (y / x))
+    } else {
+        (
+            y,
+            std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (x / y),
+        )
+    };
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+// uniformly distributed point on the unit sphere
+pub(crate) fn uniform_sphere((u, v): (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let ring_radius = (1.0 - z * z).max(0.0).sqrt();
+    let angle = 2.0 * std::f32::consts::PI * v;
+    Vec3::new(ring_radius * angle.cos(), ring_radius * angle.sin(), z)
+}
+
+// cosine weighted direction on the hemisphere around `normal`, the pdf is cos(theta) / pi
+pub(crate) fn cosine_hemisphere(sample: (f32, f32), normal: &Vec3) -> Vec3 {
+    let (x, y) = concentric_disk(sample);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    let normal = normal.normalize();
+    let (tangent, bitangent) = normal.perpendiculars();
+
+    tangent * x + bitangent * y + normal * z
+}
+
+// piecewise constant distribution over 0..1 made of equally wide steps of the given values,
+// uniform when they are all zero
+pub(crate) struct Distribution1D {
+    // running sum of the values normalized to end at 1, one more entry than there are steps
+    cdf: Vec<f32>,
+    // probability density of every step
+    densities: Vec<f32>,
+}
+
+impl Distribution1D {
+    pub(crate) fn new(values: &[f32]) -> Distribution1D {
+        let count = values.len().max(1);
+        let total: f32 = values.iter().map(|value| value.max(0.0)).sum();
+        let densities: Vec<f32> = if total > 0.0 {
+            values
+                .iter()
+                .map(|value| value.max(0.0) * count as f32 / total)
+                .collect()
+        } else {
+            vec![1.0; count]
+        };
+        let mut cdf = Vec::with_capacity(count + 1);
+        cdf.push(0.0);
+        for density in &densities {
+            cdf.push(cdf[cdf.len() - 1] + density / count as f32);
+        }
+        Distribution1D { cdf, densities }
+    }
+
+    pub(crate) fn total(values: &[f32]) -> f32 {
+        values.iter().map(|value| value.max(0.0)).sum()
+    }
+
+    /// Position in 0..1 for `sample` uniform in 0..1, together with the index of its step and
+    /// the probability density there.
+    pub(crate) fn sample(&self, sample: f32) -> (f32, usize, f32) {
+        let count = self.densities.len();
+        // last step whose cdf starts at or before the sample, skipping empty ones
+        let idx = (self.cdf.partition_point(|value| *value <= sample) - 1).min(count - 1);
+        let step = self.cdf[idx + 1] - self.cdf[idx];
+        let offset = if step > 0.0 {
+            (sample - self.cdf[idx]) / step
+        } else {
+            0.0
+        };
+        let position = ((idx as f32 + offset.clamp(0.0, 1.0)) / count as f32).min(1.0);
+        (position, idx, self.densities[idx])
+    }
+
+    /// Probability density `sample` picks `position` in 0..1 with.
+    pub(crate) fn density(&self, position: f32) -> f32 {
+        self.densities[self.step(position)]
+    }
+
+    fn step(&self, position: f32) -> usize {
+        let count = self.densities.len();
+        ((position.clamp(0.0, 1.0) * count as f32) as usize).min(count - 1)
+    }
+}
+
+// piecewise constant distribution over the unit square, `values` holds `width` values per row
+pub(crate) struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub(crate) fn new(values: &[f32], width: usize) -> Distribution2D {
+        let rows: Vec<&[f32]> = values.chunks(width.max(1)).collect();
+        let row_totals: Vec<f32> = rows.iter().map(|row| Distribution1D::total(row)).collect();
+        Distribution2D {
+            rows: rows.into_iter().map(Distribution1D::new).collect(),
+            marginal: Distribution1D::new(&row_totals),
+        }
+    }
+
+    /// Point in the unit square for `sample` uniform in 0..1 on both axes, and its probability
+    /// density.
+    pub(crate) fn sample(&self, (u, v): (f32, f32)) -> ((f32, f32), f32) {
+        let (y, row, row_density) = self.marginal.sample(v);
+        let (x, _, density) = self.rows[row].sample(u);
+        ((x, y), row_density * density)
+    }
+
+    /// Probability density `sample` picks the point `(u, v)` with.
+    pub(crate) fn density(&self, (u, v): (f32, f32)) -> f32 {
+        let row = self.marginal.step(v);
+        self.marginal.density(v) * self.rows[row].density(u)
+    }
+}
+
+/// Reconstruction filter used to weigh samples by their distance to the pixel center,
+/// `radius` is in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, alpha: f32 },
+}
+
+impl Filter {
+    pub fn radius(&self) -> f32 {
+        match self {
+            Filter::Box { radius } | Filter::Tent { radius } | Filter::Gaussian { radius, .. } => {
+                *radius
+            }
+        }
+    }
+
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, distance: f32) -> f32 {
+        let distance = distance.abs();
+        match *self {
+            Filter::Box { radius } => {
+                if distance <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - distance).max(0.0),
+            Filter::Gaussian { radius, alpha } => {
+                // shifted down so the filter reaches zero at its radius instead of being cut off
+                ((-alpha * distance * distance).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    const PATTERNS: [SamplePattern; 4] = [
+        SamplePattern::Stratified,
+        SamplePattern::Jittered,
+        SamplePattern::Halton,
+        SamplePattern::Sobol,
+    ];
+
+    fn quadrant_counts(samples: &[(f32, f32)]) -> [u32; 4] {
+        let mut counts = [0; 4];
+        for (x, y) in samples {
+            counts[(*x >= 0.5) as usize + 2 * (*y >= 0.5) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn all_patterns_produce_requested_samples_within_unit_square() {
+        let mut random = SmallRng::seed_from_u64(1);
+        for pattern in PATTERNS {
+            for count in [1, 2, 5, 16, 33] {
+                let samples = pattern.generate(count, &mut random);
+
+                assert_eq!(samples.len(), count as usize);
+                assert!(samples
+                    .iter()
+                    .all(|(x, y)| (0.0..1.0).contains(x) && (0.0..1.0).contains(y)));
+            }
+        }
+    }
+
+    #[test]
+    fn single_stratified_sample_is_pixel_center() {
+        let mut random = SmallRng::seed_from_u64(1);
+
+        assert_eq!(
+            SamplePattern::Stratified.generate(1, &mut random),
+            vec![(0.5, 0.5)]
+        );
+    }
+
+    #[test]
+    fn stratified_patterns_cover_every_quadrant_evenly() {
+        let mut random = SmallRng::seed_from_u64(1);
+        for pattern in [
+            SamplePattern::Stratified,
+            SamplePattern::Jittered,
+            SamplePattern::Sobol,
+        ] {
+            let samples = pattern.generate(16, &mut random);
+
+            assert_eq!(quadrant_counts(&samples), [4, 4, 4, 4], "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn halton_sequence() {
+        let expected = [
+            (0.0, 0.0),
+            (0.5, 1.0 / 3.0),
+            (0.25, 2.0 / 3.0),
+            (0.75, 1.0 / 9.0),
+        ];
+
+        for (idx, (expected_x, expected_y)) in expected.into_iter().enumerate() {
+            let (x, y) = halton(idx as u32);
+            assert!((x - expected_x).abs() < 1e-6 && (y - expected_y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sobol_sequence() {
+        let points: Vec<(f32, f32)> = (0..4)
+            .map(|idx| (to_unit(van_der_corput(idx)), to_unit(sobol(idx))))
+            .collect();
+
+        assert_eq!(
+            points,
+            vec![(0.0, 0.0), (0.5, 0.5), (0.25, 0.75), (0.75, 0.25)]
+        );
+    }
+
+    #[test]
+    fn concentric_disk_stays_within_unit_disk() {
+        for (x, y) in [(0.0, 0.0), (1.0, 1.0), (0.5, 0.5), (0.0, 1.0), (0.3, 0.9)] {
+            let (u, v) = concentric_disk((x, y));
+            assert!(u * u + v * v <= 1.0 + 1e-6);
+        }
+        assert_eq!(concentric_disk((0.5, 0.5)), (0.0, 0.0));
+        assert_eq!(concentric_disk((1.0, 0.5)), (1.0, 0.0));
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_above_surface() {
+        let mut random = SmallRng::seed_from_u64(1);
+        let normal = Vec3::new(0.3, -1.0, 0.2).normalize();
+        let mut mean_cosine = 0.0;
+        for _ in 0..4000 {
+            let direction = cosine_hemisphere(random.gen(), &normal);
+            assert!((direction.magnitude() - 1.0).abs() < 1e-4);
+            assert!(direction * normal >= 0.0);
+            mean_cosine += direction * normal / 4000.0;
+        }
+        // expected value of cos(theta) under a cosine distribution is 2/3
+        assert!((mean_cosine - 2.0 / 3.0).abs() < 0.02, "{}", mean_cosine);
+    }
+
+    #[test]
+    fn filters_peak_at_center_and_vanish_past_radius() {
+        for filter in [
+            Filter::Box { radius: 0.5 },
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            },
+        ] {
+            let radius = filter.radius();
+            assert!(filter.weight(0.0, 0.0) > 0.0);
+            assert!(filter.weight(0.0, 0.0) >= filter.weight(radius * 0.5, 0.0));
+            assert_eq!(filter.weight(radius * 1.01, 0.0), 0.0);
+            assert_eq!(filter.weight(0.0, -radius * 1.01), 0.0);
+        }
+    }
+
+    #[test]
+    fn tent_falls_off_linearly() {
+        let filter = Filter::Tent { radius: 1.0 };
+
+        assert_eq!(filter.weight(0.5, 0.0), 0.5);
+        assert_eq!(filter.weight(0.5, 0.5), 0.25);
+    }
+
+    #[test]
+    fn distribution_samples_proportionally_to_its_values() {
+        let distribution = Distribution1D::new(&[1.0, 0.0, 3.0]);
+        let mut random = SmallRng::seed_from_u64(2);
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            let (position, idx, density) = distribution.sample(random.gen());
+            assert_eq!((position * 3.0) as usize, idx);
+            assert_eq!(density, [0.75, 0.0, 2.25][idx]);
+            counts[idx] += 1;
+        }
+
+        assert_eq!(counts[1], 0);
+        assert!((counts[2] as f32 / counts[0] as f32 - 3.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn empty_distribution_is_uniform() {
+        let distribution = Distribution1D::new(&[0.0, 0.0]);
+
+        assert_eq!(distribution.sample(0.75), (0.75, 1, 1.0));
+    }
+
+    #[test]
+    fn two_dimensional_distribution_picks_the_brightest_cell() {
+        let distribution = Distribution2D::new(&[0.0, 0.0, 0.0, 1.0], 2);
+
+        let ((x, y), density) = distribution.sample((0.5, 0.5));
+
+        assert!(x >= 0.5 && y >= 0.5, "{} {}", x, y);
+        assert_eq!(density, 4.0);
+    }
+}
@@ -1,93 +1,94 @@
+pub mod disk;
+pub mod environment;
+pub mod light;
+mod loader;
 pub mod material;
-mod mesh;
-mod rect;
+pub mod mesh;
+pub mod nodes;
+pub mod obj;
+pub mod procedural;
+pub mod rect;
+pub mod sky;
 pub mod sphere;
-mod triangle;
+pub mod texture;
+pub mod triangle;
 
 use crate::vector_math::Vec3;
-use material::{Material, Materials};
-
-use crate::ray_tracing::Intersection;
-
-use mesh::Mesh;
-use rect::Rect;
-use sphere::Sphere;
-
-pub fn create_scene() -> Scene {
-    Scene {
-        objects: vec![
-            Object {
-                surface: Box::new(Sphere {
-                    origin: Vec3::new(1.7, 1.8, -10.0),
-                    radius: 2.0,
-                }),
-                material: Materials::rubbery_red_checker_board(),
-            },
-            Object {
-                surface: Box::new(Sphere {
-                    origin: Vec3::new(2.5, 0.8, -7.0),
-                    radius: 1.0,
-                }),
-                material: Materials::glossy_green(),
-            },
-            Object {
-                surface: Box::new(Sphere {
-                    origin: Vec3::new(-2.5, 0.2, -5.0),
-                    radius: 1.0,
-                }),
-                material: Materials::glossy_blue(),
-            },
-            Object {
-                surface: Box::new(Sphere {
-                    origin: Vec3::new(4.5, 5.2, -11.0),
-                    radius: 2.5,
-                }),
-                material: Materials::mirror(),
-            },
-            Object {
-                surface: Box::new(Sphere {
-                    origin: Vec3::new(-1.2, -0.6, -4.0),
-                    radius: 0.7,
-                }),
-                material: Materials::glass(),
-            },
-            Object {
-                surface: Box::new(Mesh::from_obj_file("cube.obj")),
-                material: Materials::rubbery_red(),
-            },
-            Object {
-                surface: Box::new(Rect::new(
-                    Vec3::new(-1.0, -2.0, -5.0),
-                    Vec3::new(-1.0, -2.0, -9.0),
-                    Vec3::new(3.0, -2.0, -9.0),
-                    Vec3::new(3.0, -2.0, -5.0),
-                )),
-                material: Materials::checker_board(),
-            },
-        ],
-        lights: vec![
-            Light {
-                origin: Vec3::new(10.0, 14.0, 10.0),
-                intensity: 0.4,
-            },
-            Light {
-                origin: Vec3::new(5.0, 0.5, -4.0),
-                intensity: 0.8,
-            },
-        ],
-        sky_sphere: Materials::skysphere(),
-    }
-}
+use material::Material;
+
+use crate::ray_tracing::{Aabb, Bvh, Intersection};
+
+use std::path::Path;
+
+pub use environment::EnvironmentLight;
+pub use light::{Falloff, Light, LightSample, LightShape};
+pub use loader::SceneError;
 
 pub struct Scene {
-    pub objects: Vec<Object>,
+    objects: Vec<Object>,
+    bvh: Bvh,
+    // indices of emissive objects that can be sampled as light sources
+    emitters: Vec<usize>,
     pub lights: Vec<Light>,
     pub sky_sphere: Material,
+    environment_light: Option<EnvironmentLight>,
+}
+
+impl Scene {
+    pub fn new(objects: Vec<Object>, lights: Vec<Light>, sky_sphere: Material) -> Scene {
+        let bounds: Vec<Aabb> = objects.iter().map(|object| object.surface.aabb()).collect();
+        let emitters = objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| {
+                object.material.emission.max_channel() > 0.0
+                    && object.surface.sample_surface((0.5, 0.5)).is_some()
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        Scene {
+            bvh: Bvh::build(&bounds),
+            emitters,
+            objects,
+            lights,
+            sky_sphere,
+            environment_light: None,
+        }
+    }
+
+    /// Lights the scene by the sky as well, with `samples` shadow rays towards it per shaded
+    /// point. Has to be called again after the sky sphere changes.
+    pub fn light_with_sky(&mut self, samples: u32) {
+        self.environment_light = Some(EnvironmentLight::new(&self.sky_sphere, samples));
+    }
+
+    pub fn environment_light(&self) -> Option<&EnvironmentLight> {
+        self.environment_light.as_ref()
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Emissive objects whose surface can be sampled, these are lit towards just like lights.
+    pub fn emitters(&self) -> impl Iterator<Item = &Object> {
+        self.emitters.iter().map(|idx| &self.objects[*idx])
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        loader::load(path.as_ref())
+    }
 }
 
 pub trait Surface {
     fn find_intersection(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<Intersection>;
 
+    fn aabb(&self) -> Aabb;
+
     fn approximate_inside(&self, point_on_surface: Vec3) -> Vec3 {
         point_on_surface
     }
@@ -95,14 +96,81 @@ pub trait Surface {
     fn approximate_outside(&self, point_on_surface: Vec3) -> Vec3 {
         point_on_surface
     }
+
+    /// Rough amount of texture coordinates covered per unit of length along the surface, tells
+    /// texture lookups how much of the texture a pixel sees. 0 without texture coordinates.
+    fn texture_scale(&self) -> f32 {
+        0.0
+    }
+
+    /// Point spread uniformly over the surface for `sample` uniform in 0..1 on both axes,
+    /// `None` for surfaces that can't be sampled and so can't be used as lights.
+    fn sample_surface(&self, _sample: (f32, f32)) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// Point on the surface to light `point` from, for `sample` uniform in 0..1 on both axes.
+    /// Spread over the whole surface unless the surface knows which part of it `point` sees.
+    fn sample_seen_from(&self, point: &Vec3, sample: (f32, f32)) -> Option<SolidAngleSample> {
+        Some(self.sample_surface(sample)?.seen_from(point))
+    }
+
+    /// Probability density per unit of solid angle `sample_seen_from` picks `direction` from
+    /// `point` with, 0 where the direction misses the surface.
+    fn density_seen_from(&self, point: &Vec3, direction: &Vec3) -> f32 {
+        match (
+            self.find_intersection(point, direction),
+            self.sample_surface((0.5, 0.5)),
+        ) {
+            (Some(intersection), Some(sample)) => area_to_solid_angle(
+                intersection.distance,
+                intersection.normal.normalize() * direction.normalize(),
+                sample.area,
+            ),
+            _ => 0.0,
+        }
+    }
+}
+
+pub struct SurfaceSample {
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// total area of the surface, the inverse of the sample's probability density
+    pub area: f32,
+}
+
+impl SurfaceSample {
+    /// The sample with its density per unit of area turned into one per unit of solid angle
+    /// as seen from `point`.
+    pub fn seen_from(self, point: &Vec3) -> SolidAngleSample {
+        let to_sample = self.point - *point;
+        let distance = to_sample.magnitude();
+        SolidAngleSample {
+            density: area_to_solid_angle(
+                distance,
+                self.normal.normalize() * to_sample * (1.0 / distance),
+                self.area,
+            ),
+            point: self.point,
+            normal: self.normal,
+        }
+    }
+}
+
+pub struct SolidAngleSample {
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// probability density per unit of solid angle, infinite for surfaces seen edge on
+    pub density: f32,
+}
+
+// density of points spread uniformly over `area` per unit of solid angle, seen from `distance`
+// away at `cosine` to the normal of the surface
+fn area_to_solid_angle(distance: f32, cosine: f32, area: f32) -> f32 {
+    distance * distance / (area * cosine.abs())
 }
 
 pub struct Object {
     pub material: Material,
     pub surface: Box<dyn Surface + Send + Sync>,
 }
-
-pub struct Light {
-    pub origin: Vec3,
-    pub intensity: f32,
-}
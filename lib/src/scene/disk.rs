@@ -0,0 +1,137 @@
+use super::{Surface, SurfaceSample};
+
+use crate::ray_tracing::{Aabb, Intersection};
+use crate::sampling::concentric_disk;
+use crate::vector_math::Vec3;
+
+/// Flat circle visible from both sides, mostly useful as a light source.
+#[derive(Debug, PartialEq)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+}
+
+impl Surface for Disk {
+    fn find_intersection(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<Intersection> {
+        let normal = self.normal.normalize();
+        let ray_direction = ray_direction.normalize();
+        let facing = ray_direction * normal;
+        if facing.abs() < f32::EPSILON {
+            return None;
+        }
+        let distance = ((self.center - *ray_origin) * normal) / facing;
+        if distance < 0.0 {
+            return None;
+        }
+
+        let center_to_point = *ray_origin + ray_direction * distance - self.center;
+        if center_to_point.magnitude() > self.radius {
+            return None;
+        }
+        // planar mapping of the square the disk is inscribed in
+        let (tangent, bitangent) = normal.perpendiculars();
+        Some(Intersection {
+            distance,
+            normal,
+            shading_normal: normal,
+            texture_coords: Some((
+                (center_to_point * tangent / self.radius + 1.0) / 2.0,
+                (center_to_point * bitangent / self.radius + 1.0) / 2.0,
+            )),
+            tangent,
+            bitangent,
+        })
+    }
+
+    fn aabb(&self) -> Aabb {
+        // extent of a circle along each axis shrinks the more the axis lines up with its normal
+        let (x, y, z) = self.normal.normalize().as_coords();
+        let extent = Vec3::new(
+            (1.0 - x * x).max(0.0).sqrt(),
+            (1.0 - y * y).max(0.0).sqrt(),
+            (1.0 - z * z).max(0.0).sqrt(),
+        ) * self.radius;
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+
+    fn texture_scale(&self) -> f32 {
+        1.0 / (2.0 * self.radius)
+    }
+
+    fn approximate_outside(&self, point_on_surface: Vec3) -> Vec3 {
+        point_on_surface + self.normal.normalize() * 1e-6
+    }
+
+    fn sample_surface(&self, sample: (f32, f32)) -> Option<SurfaceSample> {
+        let normal = self.normal.normalize();
+        let (tangent, bitangent) = normal.perpendiculars();
+        let (x, y) = concentric_disk(sample);
+        Some(SurfaceSample {
+            point: self.center + (tangent * x + bitangent * y) * self.radius,
+            normal,
+            area: std::f32::consts::PI * self.radius * self.radius,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    fn disk() -> Disk {
+        Disk {
+            center: Vec3::new(0.0, 2.0, 0.0),
+            normal: Vec3::new(0.0, -1.0, 0.0),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn ray_intersects_disk_from_both_sides() {
+        let disk = disk();
+
+        let from_below = disk
+            .find_intersection(&Vec3::new(0.5, 0.0, 0.0), &Vec3::new(0.0, 1.0, 0.0))
+            .unwrap();
+        let from_above = disk
+            .find_intersection(&Vec3::new(0.5, 5.0, 0.0), &Vec3::new(0.0, -1.0, 0.0))
+            .unwrap();
+
+        assert_eq!(from_below.distance, 2.0);
+        assert_eq!(from_above.distance, 3.0);
+    }
+
+    #[test]
+    fn ray_misses_outside_radius() {
+        let result = disk().find_intersection(&Vec3::new(1.1, 0.0, 0.0), &Vec3::new(0.0, 1.0, 0.0));
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn aabb_is_flat_along_normal() {
+        let aabb = disk().aabb();
+
+        assert_eq!(
+            aabb,
+            Aabb::new(Vec3::new(-1.0, 2.0, -1.0), Vec3::new(1.0, 2.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn surface_samples_lie_on_disk() {
+        let disk = disk();
+        let mut random = SmallRng::seed_from_u64(1);
+
+        for _ in 0..100 {
+            let sample = disk
+                .sample_surface((random.gen::<f32>(), random.gen::<f32>()))
+                .unwrap();
+            let center_to_point = sample.point - disk.center;
+            assert_eq!(cap_float(center_to_point * disk.normal), 0.0);
+            assert!(center_to_point.magnitude() <= disk.radius + 1e-6);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::f32::consts::PI;
+
+use super::material::{Material, TextureQuery};
+use super::sky::{sky_direction, sky_texture_coords};
+use crate::sampling::Distribution2D;
+use crate::vector_math::Vec3;
+
+// cells the sky is split into to find out where its light comes from
+const COLUMNS: usize = 256;
+const ROWS: usize = 128;
+
+/// The sky sphere used as a light source, directions towards it are picked proportionally to
+/// its brightness so that the sun and bright clouds get most of the shadow rays.
+///
+/// Every part of the sky texture covers the same solid angle, so no stretching has to be
+/// accounted for.
+pub struct EnvironmentLight {
+    distribution: Distribution2D,
+    /// shadow rays towards the sky per shaded point
+    pub samples: u32,
+}
+
+impl EnvironmentLight {
+    pub fn new(sky_sphere: &Material, samples: u32) -> EnvironmentLight {
+        let mut luminance = Vec::with_capacity(COLUMNS * ROWS);
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let texture_coords = (
+                    (column as f32 + 0.5) / COLUMNS as f32,
+                    (row as f32 + 0.5) / ROWS as f32,
+                );
+                // the average over the cell, a single texel could miss a small bright sun
+                let color = sky_sphere.diffuse_color.get(&TextureQuery {
+                    texture_coords: Some(texture_coords),
+                    footprint: 1.0 / ROWS as f32,
+                    ..TextureQuery::default()
+                });
+                luminance.push(color.luminance());
+            }
+        }
+        EnvironmentLight {
+            distribution: Distribution2D::new(&luminance, COLUMNS),
+            samples: samples.max(1),
+        }
+    }
+
+    /// Direction towards the sky for `sample` uniform in 0..1 on both axes and its probability
+    /// density per unit of solid angle.
+    pub fn sample(&self, sample: (f32, f32)) -> (Vec3, f32) {
+        let ((u, v), density) = self.distribution.sample(sample);
+        (sky_direction((u, v)), density / (4.0 * PI))
+    }
+
+    /// Probability density per unit of solid angle `sample` picks the unit vector `direction`
+    /// with.
+    pub fn density(&self, direction: &Vec3) -> f32 {
+        self.distribution.density(sky_texture_coords(direction)) / (4.0 * PI)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::color::Color;
+    use crate::common::test::*;
+    use crate::scene::material::{Materials, ValueGenerator};
+
+    // white above the horizon, black below
+    struct Daylight;
+
+    impl ValueGenerator<Color> for Daylight {
+        fn get(&self, query: &TextureQuery) -> Color {
+            match query.texture_coords {
+                Some((_, v)) if v < 0.5 => Color::WHITE,
+                _ => Color::BLACK,
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_sky_is_sampled_uniformly_over_the_sphere() {
+        let light = EnvironmentLight::new(&Materials::solid_color(Color::WHITE), 1);
+        let mut random = SmallRng::seed_from_u64(4);
+        let mut solid_angle = 0.0;
+        for _ in 0..1000 {
+            let (_, density) = light.sample((random.gen::<f32>(), random.gen::<f32>()));
+            solid_angle += 1.0 / density;
+        }
+
+        assert_eq!(cap_float(solid_angle / 1000.0), cap_float(4.0 * PI));
+    }
+
+    #[test]
+    fn dark_parts_of_the_sky_are_never_sampled() {
+        let mut sky = Materials::solid_color(Color::BLACK);
+        sky.diffuse_color = Arc::new(Daylight);
+        let light = EnvironmentLight::new(&sky, 1);
+        let mut random = SmallRng::seed_from_u64(5);
+
+        for _ in 0..1000 {
+            let (direction, density) = light.sample((random.gen::<f32>(), random.gen::<f32>()));
+            let (_, height, _) = direction.as_coords();
+
+            assert!(height >= 0.0, "{:?}", direction);
+            assert_eq!(cap_float(density), cap_float(1.0 / (2.0 * PI)));
+            assert_eq!(light.density(&direction), density);
+        }
+        assert_eq!(light.density(&Vec3::new(0.3, -1.0, 0.2).normalize()), 0.0);
+    }
+}
@@ -0,0 +1,241 @@
+use super::Surface;
+
+use crate::color::Color;
+use crate::vector_math::Vec3;
+
+/// Light source that is not part of the visible geometry. The light given off is `color`
+/// scaled by `intensity`, area lights spread it evenly over their surface and are sampled with
+/// `samples` shadow rays per shaded point.
+pub struct Light {
+    pub shape: LightShape,
+    pub color: Color,
+    pub intensity: f32,
+    pub falloff: Falloff,
+    pub samples: u32,
+}
+
+pub enum LightShape {
+    Point(Vec3),
+    Area(Box<dyn Surface + Send + Sync>),
+    /// infinitely far away light such as the sun, `direction` is where the light travels
+    Directional(Vec3),
+    /// point light limited to a cone around `direction`, fading out over the outermost
+    /// `penumbra_angle` degrees of the `cone_angle` degrees wide half-angle
+    Spot {
+        origin: Vec3,
+        direction: Vec3,
+        cone_angle: f32,
+        penumbra_angle: f32,
+    },
+}
+
+/// How the light gets dimmer with the distance to the light, the intensity is the one seen
+/// from a distance of 1. Directional lights never fall off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    InverseSquare,
+}
+
+impl Falloff {
+    fn attenuation(&self, distance: f32) -> f32 {
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 / distance.max(f32::EPSILON),
+            Falloff::InverseSquare => 1.0 / (distance * distance).max(f32::EPSILON),
+        }
+    }
+}
+
+/// Light arriving at a point from a single sample on the light.
+pub struct LightSample {
+    /// unit vector from the lit point towards the light
+    pub direction: Vec3,
+    /// where the light comes from, `None` for directional lights
+    pub position: Option<Vec3>,
+    /// light color with the falloff and the spot cone applied
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Light {
+    fn new(shape: LightShape, intensity: f32) -> Light {
+        Light {
+            shape,
+            color: Color::WHITE,
+            intensity,
+            falloff: Falloff::Constant,
+            samples: 1,
+        }
+    }
+
+    pub fn point(origin: Vec3, intensity: f32) -> Light {
+        Light::new(LightShape::Point(origin), intensity)
+    }
+
+    /// `None` if the surface can't be sampled.
+    pub fn area(
+        surface: Box<dyn Surface + Send + Sync>,
+        intensity: f32,
+        samples: u32,
+    ) -> Option<Light> {
+        surface.sample_surface((0.5, 0.5))?;
+        Some(Light {
+            samples: samples.max(1),
+            ..Light::new(LightShape::Area(surface), intensity)
+        })
+    }
+
+    pub fn directional(direction: Vec3, intensity: f32) -> Light {
+        Light::new(LightShape::Directional(direction.normalize()), intensity)
+    }
+
+    pub fn spot(
+        origin: Vec3,
+        direction: Vec3,
+        cone_angle: f32,
+        penumbra_angle: f32,
+        intensity: f32,
+    ) -> Light {
+        Light::new(
+            LightShape::Spot {
+                origin,
+                direction: direction.normalize(),
+                cone_angle,
+                penumbra_angle,
+            },
+            intensity,
+        )
+    }
+
+    /// Light reaching `point` from the light, `sample` is uniform in 0..1 on both axes and
+    /// picks the position on area lights.
+    pub fn sample(&self, point: &Vec3, sample: (f32, f32)) -> LightSample {
+        let position = match &self.shape {
+            LightShape::Directional(direction) => {
+                return LightSample {
+                    direction: -*direction,
+                    position: None,
+                    color: self.color,
+                    intensity: self.intensity,
+                }
+            }
+            LightShape::Point(origin) | LightShape::Spot { origin, .. } => *origin,
+            LightShape::Area(surface) => {
+                surface
+                    .sample_seen_from(point, sample)
+                    .expect("area lights are built from surfaces that can be sampled")
+                    .point
+            }
+        };
+
+        let to_light = position - *point;
+        let distance = to_light.magnitude();
+        let direction = to_light * (1.0 / distance);
+        let mut attenuation = self.falloff.attenuation(distance);
+        if let LightShape::Spot {
+            direction: spot_direction,
+            cone_angle,
+            penumbra_angle,
+            ..
+        } = self.shape
+        {
+            attenuation *= spot_factor(-direction * spot_direction, cone_angle, penumbra_angle);
+        }
+
+        LightSample {
+            direction,
+            position: Some(position),
+            color: self.color * attenuation,
+            intensity: self.intensity,
+        }
+    }
+}
+
+// 1 inside the inner cone, 0 outside the outer one and a smooth transition in between
+fn spot_factor(cosine: f32, cone_angle: f32, penumbra_angle: f32) -> f32 {
+    let outer = cone_angle.to_radians().cos();
+    let inner = (cone_angle - penumbra_angle).max(0.0).to_radians().cos();
+    if inner - outer <= f32::EPSILON {
+        return if cosine >= outer { 1.0 } else { 0.0 };
+    }
+    let t = ((cosine - outer) / (inner - outer)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    const ORIGIN: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+
+    #[test]
+    fn point_light_falloff() {
+        let mut light = Light::point(Vec3::new(0.0, 4.0, 0.0), 2.0);
+        light.color = Color::new(1.0, 0.5, 0.25);
+
+        let constant = light.sample(&ORIGIN, (0.5, 0.5));
+        light.falloff = Falloff::Linear;
+        let linear = light.sample(&ORIGIN, (0.5, 0.5));
+        light.falloff = Falloff::InverseSquare;
+        let inverse_square = light.sample(&ORIGIN, (0.5, 0.5));
+
+        assert_eq!(constant.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(constant.position, Some(Vec3::new(0.0, 4.0, 0.0)));
+        assert_eq!(constant.intensity, 2.0);
+        assert_eq!(constant.color, Color::new(1.0, 0.5, 0.25));
+        assert_eq!(linear.color, Color::new(0.25, 0.125, 0.0625));
+        assert_eq!(inverse_square.color, Color::new(0.0625, 0.03125, 0.015625));
+    }
+
+    #[test]
+    fn directional_light_comes_from_everywhere_the_same() {
+        let mut light = Light::directional(Vec3::new(0.0, -2.0, 0.0), 1.5);
+        light.falloff = Falloff::InverseSquare;
+
+        for point in [ORIGIN, Vec3::new(100.0, -50.0, 3.0)] {
+            let sample = light.sample(&point, (0.5, 0.5));
+
+            assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+            assert_eq!(sample.position, None);
+            assert_eq!(sample.color, Color::WHITE);
+        }
+    }
+
+    #[test]
+    fn spot_light_fades_out_over_penumbra() {
+        // pointing straight down from 1 unit above the floor
+        let light = Light::spot(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            45.0,
+            10.0,
+            1.0,
+        );
+        let brightness_at = |x: f32| {
+            let (r, _, _) = light
+                .sample(&Vec3::new(x, 0.0, 0.0), (0.5, 0.5))
+                .color
+                .as_channels();
+            r
+        };
+
+        assert_eq!(brightness_at(0.0), 1.0);
+        // 30 degrees off axis is still within the inner cone
+        assert_eq!(brightness_at(30f32.to_radians().tan()), 1.0);
+        let penumbra = brightness_at(40f32.to_radians().tan());
+        assert!(penumbra > 0.0 && penumbra < 1.0, "{}", penumbra);
+        assert_eq!(brightness_at(50f32.to_radians().tan()), 0.0);
+    }
+
+    #[test]
+    fn spot_without_penumbra_has_hard_edge() {
+        assert_eq!(spot_factor(44f32.to_radians().cos(), 45.0, 0.0), 1.0);
+        assert_eq!(
+            cap_float(spot_factor(46f32.to_radians().cos(), 45.0, 0.0)),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,1935 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+
+use super::disk::Disk;
+use super::material::{
+    Albedo, Channel, ChannelTexture, CheckerBoard, ImageTexture, Material, MetallicRoughness,
+    NormalMap, ParameterMaps, SolidColor, TextureFilter, UvTransform, ValueGenerator, WrapMode,
+};
+use super::nodes::{Add, ColorRamp, Mix, Multiply, Remap, TransformUv, Triplanar};
+use super::obj::{ObjError, ObjFile};
+use super::procedural::{Pattern, PatternSpace, Procedural};
+use super::rect::Rect;
+use super::sky::PreethamSky;
+use super::sphere::Sphere;
+use super::texture::TextureError;
+use super::{Falloff, Light, Object, Scene, Surface};
+use crate::color::Color;
+use crate::vector_math::Vec3;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Mesh(PathBuf, ObjError),
+    Texture(PathBuf, TextureError),
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, error) => {
+                write!(f, "can not read scene file {}: {}", path.display(), error)
+            }
+            SceneError::Parse(path, error) => {
+                write!(f, "malformed scene file {}: {}", path.display(), error)
+            }
+            SceneError::Mesh(path, error) => {
+                write!(f, "can not load mesh {}: {}", path.display(), error)
+            }
+            SceneError::Texture(path, error) => {
+                write!(f, "can not load texture {}: {}", path.display(), error)
+            }
+            SceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(_, error) => Some(error),
+            SceneError::Texture(_, error) => Some(error),
+            SceneError::Parse(_, error) => Some(error),
+            SceneError::Mesh(_, error) => Some(error),
+            SceneError::Invalid(_) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    // files of named materials objects can refer to, a name may only be defined once
+    #[serde(default)]
+    material_libraries: Vec<PathBuf>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+    sky: TextureDescription,
+    // the sky lights the scene as well when given
+    environment_light: Option<EnvironmentLightDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentLightDescription {
+    #[serde(default = "default_light_samples")]
+    samples: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDescription {
+    surface: SurfaceDescription,
+    // meshes may leave it to the materials of their file
+    material: Option<MaterialReference>,
+}
+
+// either the name of a material from the scene or its libraries, or the material itself
+enum MaterialReference {
+    Named(String),
+    Inline(Box<MaterialDescription>),
+}
+
+impl<'de> Deserialize<'de> for MaterialReference {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReferenceVisitor;
+
+        impl<'de> Visitor<'de> for ReferenceVisitor {
+            type Value = MaterialReference;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a material name or a material table")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(MaterialReference::Named(name.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                MaterialDescription::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(|material| MaterialReference::Inline(Box::new(material)))
+            }
+        }
+
+        deserializer.deserialize_any(ReferenceVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SurfaceDescription {
+    Sphere {
+        origin: [f32; 3],
+        radius: f32,
+    },
+    Rect {
+        vertices: [[f32; 3]; 4],
+    },
+    Disk {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+    },
+    Mesh {
+        path: PathBuf,
+    },
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    diffuse_color: TextureDescription,
+    #[serde(default)]
+    shininess: f32,
+    #[serde(default)]
+    albedo: AlbedoDescription,
+    #[serde(default = "default_refractive_index")]
+    refractive_index: f32,
+    // linear radiance, unlike the texture colors this is not limited to 0..255
+    #[serde(default)]
+    emission: [f32; 3],
+    // per unit of distance inside refractive objects
+    #[serde(default)]
+    absorption: [f32; 3],
+    // 0 lets all the light pass straight through the surface
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    // replaces shininess and albedo, the diffuse color becomes the base color
+    metallic_roughness: Option<MetallicRoughnessDescription>,
+    normal_map: Option<NormalMapDescription>,
+    #[serde(default)]
+    parameter_maps: ParameterMapsDescription,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ParameterMapsDescription {
+    shininess: Option<ScalarTextureDescription>,
+    diffuse: Option<ScalarTextureDescription>,
+    specular: Option<ScalarTextureDescription>,
+    reflection: Option<ScalarTextureDescription>,
+    opacity: Option<ScalarTextureDescription>,
+    metallic: Option<ScalarTextureDescription>,
+    roughness: Option<ScalarTextureDescription>,
+}
+
+// a channel of a texture holding plain numbers, remapped from the range `from` onto `to`
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct ScalarTextureDescription {
+    texture: TextureDescription,
+    #[serde(default = "default_channel")]
+    channel: ChannelDescription,
+    #[serde(default = "default_range")]
+    from: [f32; 2],
+    #[serde(default = "default_range")]
+    to: [f32; 2],
+}
+
+fn default_range() -> [f32; 2] {
+    [0.0, 1.0]
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ChannelDescription {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+fn default_channel() -> ChannelDescription {
+    ChannelDescription::Luminance
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum NormalMapDescription {
+    TangentSpace {
+        texture: TextureDescription,
+    },
+    Bump {
+        heights: TextureDescription,
+        depth: f32,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+struct AlbedoDescription {
+    diffuse: f32,
+    specular: f32,
+    reflection: f32,
+    refraction: f32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct MetallicRoughnessDescription {
+    metallic: f32,
+    roughness: f32,
+    #[serde(default = "default_specular")]
+    specular: f32,
+}
+
+fn default_specular() -> f32 {
+    0.5
+}
+
+fn default_refractive_index() -> f32 {
+    1.0
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    SolidColor {
+        color: [u8; 3],
+    },
+    CheckerBoard {
+        light: [u8; 3],
+        dark: [u8; 3],
+        #[serde(default = "default_checker_board_rows")]
+        rows: u32,
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default = "default_filter")]
+        filter: FilterDescription,
+        #[serde(default = "default_wrap")]
+        wrap_u: WrapDescription,
+        #[serde(default = "default_wrap")]
+        wrap_v: WrapDescription,
+        #[serde(default = "default_uv_scale")]
+        scale: [f32; 2],
+        #[serde(default)]
+        offset: [f32; 2],
+    },
+    PreethamSky {
+        sun_direction: [f32; 3],
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: [u8; 3],
+    },
+    Procedural {
+        pattern: PatternDescription,
+        #[serde(default = "default_pattern_space")]
+        space: PatternSpaceDescription,
+        #[serde(default = "default_pattern_scale")]
+        scale: f32,
+        #[serde(default = "default_pattern_colors")]
+        colors: [[u8; 3]; 2],
+    },
+    Mix {
+        first: Box<TextureDescription>,
+        second: Box<TextureDescription>,
+        mask: Box<ScalarTextureDescription>,
+    },
+    Multiply {
+        first: Box<TextureDescription>,
+        second: Box<TextureDescription>,
+    },
+    Add {
+        first: Box<TextureDescription>,
+        second: Box<TextureDescription>,
+    },
+    ColorRamp {
+        input: Box<ScalarTextureDescription>,
+        stops: Vec<ColorStopDescription>,
+    },
+    TransformUv {
+        texture: Box<TextureDescription>,
+        #[serde(default = "default_uv_scale")]
+        scale: [f32; 2],
+        #[serde(default)]
+        offset: [f32; 2],
+    },
+    Triplanar {
+        texture: Box<TextureDescription>,
+        #[serde(default = "default_pattern_scale")]
+        scale: f32,
+        #[serde(default = "default_sharpness")]
+        sharpness: f32,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct ColorStopDescription {
+    position: f32,
+    color: [u8; 3],
+}
+
+fn default_sharpness() -> f32 {
+    4.0
+}
+
+fn default_checker_board_rows() -> u32 {
+    8
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_ground_albedo() -> [u8; 3] {
+    [100, 100, 100]
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FilterDescription {
+    Nearest,
+    Bilinear,
+    Trilinear,
+}
+
+fn default_filter() -> FilterDescription {
+    FilterDescription::Trilinear
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum WrapDescription {
+    Repeat,
+    MirroredRepeat,
+    Clamp,
+}
+
+fn default_wrap() -> WrapDescription {
+    WrapDescription::Repeat
+}
+
+fn default_uv_scale() -> [f32; 2] {
+    [1.0, 1.0]
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PatternDescription {
+    Noise,
+    Fbm {
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+    Turbulence {
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+    Marble {
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_distortion")]
+        distortion: f32,
+    },
+    Wood {
+        #[serde(default = "default_distortion")]
+        distortion: f32,
+    },
+    Worley,
+    Cells,
+    Stripes {
+        axis: [f32; 3],
+        #[serde(default = "default_stripe_width")]
+        width: f32,
+    },
+    Gradient {
+        axis: [f32; 3],
+    },
+    Checker,
+}
+
+fn default_octaves() -> u32 {
+    5
+}
+
+fn default_distortion() -> f32 {
+    1.0
+}
+
+fn default_stripe_width() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum PatternSpaceDescription {
+    Uv,
+    Object,
+}
+
+fn default_pattern_space() -> PatternSpaceDescription {
+    PatternSpaceDescription::Object
+}
+
+fn default_pattern_scale() -> f32 {
+    1.0
+}
+
+fn default_pattern_colors() -> [[u8; 3]; 2] {
+    [[0, 0, 0], [255, 255, 255]]
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDescription {
+    // a point light at `origin`, a spot light when `spot` is given as well, an area light in the
+    // shape of a surface or a directional light shining along `direction`
+    origin: Option<[f32; 3]>,
+    spot: Option<SpotDescription>,
+    shape: Option<SurfaceDescription>,
+    direction: Option<[f32; 3]>,
+    intensity: f32,
+    #[serde(default = "default_light_color")]
+    color: [u8; 3],
+    #[serde(default = "default_falloff")]
+    falloff: FalloffDescription,
+    #[serde(default = "default_light_samples")]
+    samples: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpotDescription {
+    direction: [f32; 3],
+    cone_angle: f32,
+    #[serde(default)]
+    penumbra_angle: f32,
+}
+
+fn default_light_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FalloffDescription {
+    Constant,
+    Linear,
+    InverseSquare,
+}
+
+fn default_falloff() -> FalloffDescription {
+    FalloffDescription::Constant
+}
+
+fn default_light_samples() -> u32 {
+    16
+}
+
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    let description: SceneDescription =
+        toml::from_str(&source).map_err(|e| SceneError::Parse(path.to_path_buf(), e))?;
+
+    // files referenced by the scene are looked up next to the scene file itself
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    build_scene(description, base_dir)
+}
+
+pub fn load_material_library(path: &Path) -> Result<HashMap<String, Material>, SceneError> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    read_material_library(path)?
+        .into_iter()
+        .map(|(name, description)| {
+            let material = build_material(description, base_dir)
+                .map_err(|e| in_context(e, &format!("material \"{}\"", name)))?;
+            Ok((name, material))
+        })
+        .collect()
+}
+
+fn read_material_library(path: &Path) -> Result<BTreeMap<String, MaterialDescription>, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    toml::from_str(&source).map_err(|e| SceneError::Parse(path.to_path_buf(), e))
+}
+
+fn build_scene(description: SceneDescription, base_dir: &Path) -> Result<Scene, SceneError> {
+    // every named material is built once, whether an object uses it or not, and shared by all
+    // the objects that do
+    let mut named_materials = HashMap::new();
+    for library in &description.material_libraries {
+        let path = base_dir.join(library);
+        let library_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        for (name, material) in read_material_library(&path)? {
+            add_named_material(&mut named_materials, name, material, &library_dir)?;
+        }
+    }
+    for (name, material) in description.materials {
+        add_named_material(&mut named_materials, name, material, base_dir)?;
+    }
+
+    let mut objects = Vec::new();
+    for (idx, object) in description.objects.into_iter().enumerate() {
+        let context = format!("object #{}", idx);
+        let given_material = object
+            .material
+            .as_ref()
+            .map(|reference| build_material_reference(reference, &named_materials, base_dir))
+            .transpose()
+            .map_err(|e| in_context(e, &context))?;
+        match object.surface {
+            // every model of the file becomes an object of its own
+            SurfaceDescription::Mesh { path } => {
+                let path = base_dir.join(path);
+                let mut file =
+                    ObjFile::load(&path).map_err(|e| SceneError::Mesh(path.clone(), e))?;
+                for model in std::mem::take(&mut file.models) {
+                    let material = match &given_material {
+                        Some(material) => material.clone(),
+                        None => file
+                            .material(&model)
+                            .map_err(|e| SceneError::Mesh(path.clone(), e))?
+                            .ok_or_else(|| {
+                                SceneError::Invalid(format!(
+                                    "{}: model \"{}\" has no material",
+                                    context, model.name
+                                ))
+                            })?,
+                    };
+                    objects.push(Object {
+                        surface: Box::new(model.mesh),
+                        material,
+                    });
+                }
+            }
+            surface => {
+                let material = given_material.ok_or_else(|| {
+                    SceneError::Invalid(format!("{}: material is missing", context))
+                })?;
+                objects.push(Object {
+                    surface: build_surface(surface).map_err(|e| in_context(e, &context))?,
+                    material,
+                });
+            }
+        }
+    }
+
+    let mut lights = description
+        .lights
+        .into_iter()
+        .enumerate()
+        .map(|(idx, light)| {
+            if !light.intensity.is_finite() || light.intensity < 0.0 {
+                return Err(SceneError::Invalid(format!(
+                    "light #{}: intensity must be a non-negative number, got {}",
+                    idx, light.intensity
+                )));
+            }
+            build_light(light).map_err(|e| in_context(e, &format!("light #{}", idx)))
+        })
+        .collect::<Result<Vec<_>, SceneError>>()?;
+
+    // the sun of a daylight sky isn't part of its texture and shines on the scene as a light
+    let sky: Arc<dyn ValueGenerator<Color> + Send + Sync> = match description.sky {
+        TextureDescription::PreethamSky {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+        } => {
+            let sky = build_preetham_sky(sun_direction, turbidity, ground_albedo)?;
+            lights.extend(sky.sun());
+            Arc::new(sky)
+        }
+        sky => build_texture(sky, base_dir, Encoding::Srgb)?.into(),
+    };
+    let sky_sphere = Material {
+        diffuse_color: sky,
+        shininess: 0.0,
+        albedo: Albedo {
+            diffuse: 1.0,
+            ..Albedo::default()
+        },
+        refractive_index: 0.0,
+        emission: Color::BLACK,
+        absorption: Color::BLACK,
+        opacity: 1.0,
+        metallic_roughness: None,
+        normal_map: None,
+        parameter_maps: ParameterMaps::default(),
+    };
+
+    let mut scene = Scene::new(objects, lights, sky_sphere);
+    if let Some(environment_light) = description.environment_light {
+        scene.light_with_sky(environment_light.samples);
+    }
+    Ok(scene)
+}
+
+// textures of the material are looked up in `base_dir`
+fn add_named_material(
+    named_materials: &mut HashMap<String, Material>,
+    name: String,
+    material: MaterialDescription,
+    base_dir: &Path,
+) -> Result<(), SceneError> {
+    if named_materials.contains_key(&name) {
+        return Err(SceneError::Invalid(format!(
+            "material \"{}\" is defined more than once",
+            name
+        )));
+    }
+    let material = build_material(material, base_dir)
+        .map_err(|e| in_context(e, &format!("material \"{}\"", name)))?;
+    named_materials.insert(name, material);
+    Ok(())
+}
+
+fn build_material_reference(
+    reference: &MaterialReference,
+    named_materials: &HashMap<String, Material>,
+    base_dir: &Path,
+) -> Result<Material, SceneError> {
+    match reference {
+        MaterialReference::Inline(material) => build_material((**material).clone(), base_dir),
+        MaterialReference::Named(name) => match named_materials.get(name) {
+            Some(material) => Ok(material.clone()),
+            None => Err(SceneError::Invalid(format!(
+                "unknown material \"{}\"",
+                name
+            ))),
+        },
+    }
+}
+
+fn build_surface(
+    description: SurfaceDescription,
+) -> Result<Box<dyn Surface + Send + Sync>, SceneError> {
+    match description {
+        SurfaceDescription::Sphere { origin, radius } => {
+            if !is_positive(radius) {
+                return Err(SceneError::Invalid(format!(
+                    "sphere radius must be positive, got {}",
+                    radius
+                )));
+            }
+            Ok(Box::new(Sphere {
+                origin: to_vec3(origin),
+                radius,
+            }))
+        }
+        SurfaceDescription::Rect {
+            vertices: [a, b, c, d],
+        } => Rect::try_new(to_vec3(a), to_vec3(b), to_vec3(c), to_vec3(d))
+            .map(|rect| Box::new(rect) as Box<dyn Surface + Send + Sync>)
+            .ok_or_else(|| SceneError::Invalid("rect vertices must lie in one plane".to_string())),
+        SurfaceDescription::Disk {
+            center,
+            normal,
+            radius,
+        } => {
+            if !is_positive(radius) || !is_positive(to_vec3(normal).magnitude()) {
+                return Err(SceneError::Invalid(format!(
+                    "disk needs a positive radius and a non-zero normal, got {} and {:?}",
+                    radius, normal
+                )));
+            }
+            Ok(Box::new(Disk {
+                center: to_vec3(center),
+                normal: to_vec3(normal),
+                radius,
+            }))
+        }
+        // every model of the file becomes an object of its own, see `build_scene`
+        SurfaceDescription::Mesh { .. } => unreachable!("meshes are loaded model by model"),
+    }
+}
+
+fn build_light(description: LightDescription) -> Result<Light, SceneError> {
+    let intensity = description.intensity;
+    let mut light = match (
+        description.origin,
+        description.spot,
+        description.shape,
+        description.direction,
+    ) {
+        (Some(origin), None, None, None) => Light::point(to_vec3(origin), intensity),
+        (Some(origin), Some(spot), None, None) => {
+            if !is_positive(to_vec3(spot.direction).magnitude())
+                || !(0.0..=180.0).contains(&spot.cone_angle)
+                || !(0.0..=spot.cone_angle).contains(&spot.penumbra_angle)
+            {
+                return Err(SceneError::Invalid(
+                    "spot needs a non-zero direction, a cone angle within 0..180 degrees and a \
+                     penumbra no wider than the cone"
+                        .to_string(),
+                ));
+            }
+            Light::spot(
+                to_vec3(origin),
+                to_vec3(spot.direction),
+                spot.cone_angle,
+                spot.penumbra_angle,
+                intensity,
+            )
+        }
+        (None, None, Some(SurfaceDescription::Mesh { .. }), None) => {
+            return Err(SceneError::Invalid(
+                "meshes can not be used as lights".to_string(),
+            ))
+        }
+        (None, None, Some(shape), None) => {
+            Light::area(build_surface(shape)?, intensity, description.samples).ok_or_else(|| {
+                SceneError::Invalid("shape can not be used as a light".to_string())
+            })?
+        }
+        (None, None, None, Some(direction)) => {
+            if !is_positive(to_vec3(direction).magnitude()) {
+                return Err(SceneError::Invalid(
+                    "directional light needs a non-zero direction".to_string(),
+                ));
+            }
+            Light::directional(to_vec3(direction), intensity)
+        }
+        _ => {
+            return Err(SceneError::Invalid(
+                "light needs exactly one of origin, origin with spot, shape or direction"
+                    .to_string(),
+            ))
+        }
+    };
+    light.color = to_color(description.color);
+    light.falloff = match description.falloff {
+        FalloffDescription::Constant => Falloff::Constant,
+        FalloffDescription::Linear => Falloff::Linear,
+        FalloffDescription::InverseSquare => Falloff::InverseSquare,
+    };
+    Ok(light)
+}
+
+fn build_material(
+    description: MaterialDescription,
+    base_dir: &Path,
+) -> Result<Material, SceneError> {
+    let albedo = description.albedo;
+    for (name, value) in [
+        ("diffuse", albedo.diffuse),
+        ("specular", albedo.specular),
+        ("reflection", albedo.reflection),
+        ("refraction", albedo.refraction),
+    ] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(SceneError::Invalid(format!(
+                "{} albedo must be between 0 and 1, got {}",
+                name, value
+            )));
+        }
+    }
+    if !(0.0..=1.0).contains(&description.opacity) {
+        return Err(SceneError::Invalid(format!(
+            "opacity must be between 0 and 1, got {}",
+            description.opacity
+        )));
+    }
+    if !description.shininess.is_finite() || description.shininess < 0.0 {
+        return Err(SceneError::Invalid(format!(
+            "shininess must be a non-negative number, got {}",
+            description.shininess
+        )));
+    }
+    let [emission_r, emission_g, emission_b] = description.emission;
+    if description
+        .emission
+        .iter()
+        .any(|c| !c.is_finite() || *c < 0.0)
+    {
+        return Err(SceneError::Invalid(format!(
+            "emission must be non-negative numbers, got {:?}",
+            description.emission
+        )));
+    }
+    let [absorption_r, absorption_g, absorption_b] = description.absorption;
+    if description
+        .absorption
+        .iter()
+        .any(|c| !c.is_finite() || *c < 0.0)
+    {
+        return Err(SceneError::Invalid(format!(
+            "absorption must be non-negative numbers, got {:?}",
+            description.absorption
+        )));
+    }
+    let metallic_roughness = description
+        .metallic_roughness
+        .map(build_metallic_roughness)
+        .transpose()?;
+    if albedo.refraction > 0.0 && !is_positive(description.refractive_index) {
+        return Err(SceneError::Invalid(format!(
+            "refractive material needs a positive refractive index, got {}",
+            description.refractive_index
+        )));
+    }
+    let normal_map = match description.normal_map {
+        Some(NormalMapDescription::TangentSpace { texture }) => Some(NormalMap::TangentSpace(
+            build_texture(texture, base_dir, Encoding::Linear)?.into(),
+        )),
+        Some(NormalMapDescription::Bump { heights, depth }) => {
+            if !depth.is_finite() {
+                return Err(SceneError::Invalid(format!(
+                    "bump depth must be a number, got {}",
+                    depth
+                )));
+            }
+            Some(NormalMap::Bump {
+                heights: build_texture(heights, base_dir, Encoding::Linear)?.into(),
+                depth,
+            })
+        }
+        None => None,
+    };
+    let maps = description.parameter_maps;
+    let build_map = |map: Option<ScalarTextureDescription>| {
+        map.map(|map| Ok(build_scalar_texture(map, base_dir)?.into()))
+            .transpose()
+    };
+    let parameter_maps = ParameterMaps {
+        shininess: build_map(maps.shininess)?,
+        diffuse: build_map(maps.diffuse)?,
+        specular: build_map(maps.specular)?,
+        reflection: build_map(maps.reflection)?,
+        opacity: build_map(maps.opacity)?,
+        metallic: build_map(maps.metallic)?,
+        roughness: build_map(maps.roughness)?,
+    };
+    Ok(Material {
+        diffuse_color: build_texture(description.diffuse_color, base_dir, Encoding::Srgb)?.into(),
+        shininess: description.shininess,
+        albedo: Albedo {
+            diffuse: albedo.diffuse,
+            specular: albedo.specular,
+            reflection: albedo.reflection,
+            refraction: albedo.refraction,
+        },
+        refractive_index: description.refractive_index,
+        emission: Color::new(emission_r, emission_g, emission_b),
+        absorption: Color::new(absorption_r, absorption_g, absorption_b),
+        opacity: description.opacity,
+        metallic_roughness,
+        normal_map,
+        parameter_maps,
+    })
+}
+
+fn build_scalar_texture(
+    description: ScalarTextureDescription,
+    base_dir: &Path,
+) -> Result<Box<dyn ValueGenerator<f32> + Send + Sync>, SceneError> {
+    let channel = Box::new(ChannelTexture {
+        texture: build_texture(description.texture, base_dir, Encoding::Linear)?,
+        channel: match description.channel {
+            ChannelDescription::Red => Channel::Red,
+            ChannelDescription::Green => Channel::Green,
+            ChannelDescription::Blue => Channel::Blue,
+            ChannelDescription::Luminance => Channel::Luminance,
+        },
+    });
+    let ([from_start, from_end], [to_start, to_end]) = (description.from, description.to);
+    if (from_start, from_end, to_start, to_end) == (0.0, 1.0, 0.0, 1.0) {
+        return Ok(channel);
+    }
+    if from_start == from_end {
+        return Err(SceneError::Invalid(format!(
+            "range to remap from must not be empty, got {:?}",
+            description.from
+        )));
+    }
+    Ok(Box::new(Remap {
+        input: channel,
+        from: (from_start, from_end),
+        to: (to_start, to_end),
+    }))
+}
+
+fn build_metallic_roughness(
+    description: MetallicRoughnessDescription,
+) -> Result<MetallicRoughness, SceneError> {
+    for (name, value) in [
+        ("metallic", description.metallic),
+        ("roughness", description.roughness),
+        ("specular", description.specular),
+    ] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(SceneError::Invalid(format!(
+                "{} must be between 0 and 1, got {}",
+                name, value
+            )));
+        }
+    }
+    Ok(MetallicRoughness {
+        metallic: description.metallic,
+        roughness: description.roughness,
+        specular: description.specular,
+    })
+}
+
+// how the values of a texture are stored, colors are sRGB encoded while normal and height maps
+// hold plain numbers
+#[derive(Clone, Copy)]
+enum Encoding {
+    Srgb,
+    Linear,
+}
+
+impl Encoding {
+    fn color(self, [r, g, b]: [u8; 3]) -> Color {
+        match self {
+            Encoding::Srgb => to_color([r, g, b]),
+            Encoding::Linear => Color::from_bytes(r, g, b),
+        }
+    }
+}
+
+fn build_texture(
+    description: TextureDescription,
+    base_dir: &Path,
+    encoding: Encoding,
+) -> Result<Box<dyn ValueGenerator<Color> + Send + Sync>, SceneError> {
+    Ok(match description {
+        TextureDescription::SolidColor { color } => Box::new(SolidColor(encoding.color(color))),
+        TextureDescription::CheckerBoard { light, dark, rows } => {
+            if rows == 0 {
+                return Err(SceneError::Invalid(
+                    "checker board must have at least one row".to_string(),
+                ));
+            }
+            Box::new(CheckerBoard {
+                light: encoding.color(light),
+                dark: encoding.color(dark),
+                rows,
+            })
+        }
+        TextureDescription::Image {
+            path,
+            filter,
+            wrap_u,
+            wrap_v,
+            scale,
+            offset,
+        } => {
+            let path = base_dir.join(path);
+            let texture = match encoding {
+                Encoding::Srgb => ImageTexture::load(&path),
+                Encoding::Linear => ImageTexture::load_linear(&path),
+            };
+            let mut texture = texture.map_err(|e| SceneError::Texture(path, e))?;
+            texture.filter = match filter {
+                FilterDescription::Nearest => TextureFilter::Nearest,
+                FilterDescription::Bilinear => TextureFilter::Bilinear,
+                FilterDescription::Trilinear => TextureFilter::Trilinear,
+            };
+            texture.wrap_u = build_wrap(wrap_u);
+            texture.wrap_v = build_wrap(wrap_v);
+            texture.transform = UvTransform {
+                scale: (scale[0], scale[1]),
+                offset: (offset[0], offset[1]),
+            };
+            Box::new(texture)
+        }
+        TextureDescription::PreethamSky {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+        } => Box::new(build_preetham_sky(sun_direction, turbidity, ground_albedo)?),
+        TextureDescription::Procedural {
+            pattern,
+            space,
+            scale,
+            colors: [first, second],
+        } => {
+            if !is_positive(scale) {
+                return Err(SceneError::Invalid(format!(
+                    "pattern scale must be positive, got {}",
+                    scale
+                )));
+            }
+            Box::new(Procedural {
+                pattern: build_pattern(pattern)?,
+                space: match space {
+                    PatternSpaceDescription::Uv => PatternSpace::Uv,
+                    PatternSpaceDescription::Object => PatternSpace::Object,
+                },
+                scale,
+                colors: (encoding.color(first), encoding.color(second)),
+            })
+        }
+        TextureDescription::Mix {
+            first,
+            second,
+            mask,
+        } => Box::new(Mix {
+            first: build_texture(*first, base_dir, encoding)?,
+            second: build_texture(*second, base_dir, encoding)?,
+            mask: build_scalar_texture(*mask, base_dir)?,
+        }),
+        TextureDescription::Multiply { first, second } => Box::new(Multiply {
+            first: build_texture(*first, base_dir, encoding)?,
+            second: build_texture(*second, base_dir, encoding)?,
+        }),
+        TextureDescription::Add { first, second } => Box::new(Add {
+            first: build_texture(*first, base_dir, encoding)?,
+            second: build_texture(*second, base_dir, encoding)?,
+        }),
+        TextureDescription::ColorRamp { input, stops } => {
+            if stops.is_empty() {
+                return Err(SceneError::Invalid(
+                    "color ramp needs at least one stop".to_string(),
+                ));
+            }
+            Box::new(ColorRamp::new(
+                build_scalar_texture(*input, base_dir)?,
+                stops
+                    .iter()
+                    .map(|stop| (stop.position, encoding.color(stop.color)))
+                    .collect(),
+            ))
+        }
+        TextureDescription::TransformUv {
+            texture,
+            scale,
+            offset,
+        } => Box::new(TransformUv {
+            input: build_texture(*texture, base_dir, encoding)?,
+            transform: UvTransform {
+                scale: (scale[0], scale[1]),
+                offset: (offset[0], offset[1]),
+            },
+        }),
+        TextureDescription::Triplanar {
+            texture,
+            scale,
+            sharpness,
+        } => {
+            if !is_positive(scale) {
+                return Err(SceneError::Invalid(format!(
+                    "triplanar scale must be positive, got {}",
+                    scale
+                )));
+            }
+            if !sharpness.is_finite() || sharpness < 1.0 {
+                return Err(SceneError::Invalid(format!(
+                    "triplanar sharpness must be at least 1, got {}",
+                    sharpness
+                )));
+            }
+            Box::new(Triplanar {
+                input: build_texture(*texture, base_dir, encoding)?,
+                scale,
+                sharpness,
+            })
+        }
+    })
+}
+
+// every octave is another layer of noise computed for every lookup
+const MAX_OCTAVES: u32 = 16;
+
+fn build_pattern(description: PatternDescription) -> Result<Pattern, SceneError> {
+    let axis = |axis| {
+        let axis = to_vec3(axis);
+        if axis.magnitude() <= 0.0 {
+            return Err(SceneError::Invalid(
+                "pattern axis must not be zero".to_string(),
+            ));
+        }
+        Ok(axis)
+    };
+    let octaves = |octaves| {
+        if !(1..=MAX_OCTAVES).contains(&octaves) {
+            return Err(SceneError::Invalid(format!(
+                "octaves must be between 1 and {}, got {}",
+                MAX_OCTAVES, octaves
+            )));
+        }
+        Ok(octaves)
+    };
+    let distortion = |distortion: f32| {
+        if !distortion.is_finite() {
+            return Err(SceneError::Invalid(format!(
+                "distortion must be a number, got {}",
+                distortion
+            )));
+        }
+        Ok(distortion)
+    };
+    Ok(match description {
+        PatternDescription::Noise => Pattern::Noise,
+        PatternDescription::Fbm { octaves: o } => Pattern::Fbm {
+            octaves: octaves(o)?,
+        },
+        PatternDescription::Turbulence { octaves: o } => Pattern::Turbulence {
+            octaves: octaves(o)?,
+        },
+        PatternDescription::Marble {
+            octaves: o,
+            distortion: d,
+        } => Pattern::Marble {
+            octaves: octaves(o)?,
+            distortion: distortion(d)?,
+        },
+        PatternDescription::Wood { distortion: d } => Pattern::Wood {
+            distortion: distortion(d)?,
+        },
+        PatternDescription::Worley => Pattern::Worley,
+        PatternDescription::Cells => Pattern::Cells,
+        PatternDescription::Stripes { axis: a, width } => {
+            if !(0.0..=1.0).contains(&width) {
+                return Err(SceneError::Invalid(format!(
+                    "stripe width must be between 0 and 1, got {}",
+                    width
+                )));
+            }
+            Pattern::Stripes {
+                axis: axis(a)?,
+                width,
+            }
+        }
+        PatternDescription::Gradient { axis: a } => Pattern::Gradient { axis: axis(a)? },
+        PatternDescription::Checker => Pattern::Checker,
+    })
+}
+
+fn build_wrap(description: WrapDescription) -> WrapMode {
+    match description {
+        WrapDescription::Repeat => WrapMode::Repeat,
+        WrapDescription::MirroredRepeat => WrapMode::MirroredRepeat,
+        WrapDescription::Clamp => WrapMode::Clamp,
+    }
+}
+
+fn in_context(error: SceneError, context: &str) -> SceneError {
+    match error {
+        SceneError::Invalid(message) => SceneError::Invalid(format!("{}: {}", context, message)),
+        error => error,
+    }
+}
+
+fn build_preetham_sky(
+    sun_direction: [f32; 3],
+    turbidity: f32,
+    ground_albedo: [u8; 3],
+) -> Result<PreethamSky, SceneError> {
+    let sun_direction = to_vec3(sun_direction);
+    if !is_positive(sun_direction.magnitude()) {
+        return Err(SceneError::Invalid(
+            "sun direction must not be zero".to_string(),
+        ));
+    }
+    if !(2.0..=10.0).contains(&turbidity) {
+        return Err(SceneError::Invalid(format!(
+            "turbidity must be between 2 and 10, got {}",
+            turbidity
+        )));
+    }
+    Ok(PreethamSky::new(
+        sun_direction,
+        turbidity,
+        to_color(ground_albedo),
+    ))
+}
+
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+fn to_vec3([x, y, z]: [f32; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+fn to_color([r, g, b]: [u8; 3]) -> Color {
+    Color::from_srgb_bytes(r, g, b)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::scene::material::TextureQuery;
+    use crate::scene::LightShape;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        let description: SceneDescription =
+            toml::from_str(source).map_err(|e| SceneError::Parse(PathBuf::new(), e))?;
+        build_scene(
+            description,
+            Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap(),
+        )
+    }
+
+    #[test]
+    fn loads_objects_and_lights() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, albedo = { diffuse = 0.9, specular = 0.1 } }
+
+            [[objects]]
+            surface = { type = "rect", vertices = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0]] }
+            material = { diffuse_color = { type = "checker_board", light = [255, 255, 255], dark = [0, 0, 0] }, albedo = { diffuse = 0.8, specular = 0.6, reflection = 0.1 }, shininess = 50.0 }
+
+            [[lights]]
+            origin = [10.0, 14.0, 10.0]
+            intensity = 0.4
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.objects().len(), 2);
+        assert_eq!(scene.objects()[1].material.shininess, 50.0);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(matches!(
+            scene.lights[0].shape,
+            LightShape::Point(origin) if origin == Vec3::new(10.0, 14.0, 10.0)
+        ));
+    }
+
+    #[test]
+    fn loads_bundled_scene() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../scene.toml");
+
+        let scene = load(&path).unwrap();
+
+        assert_eq!(scene.objects().len(), 7);
+        assert_eq!(scene.lights.len(), 2);
+    }
+
+    #[test]
+    fn loads_area_lights_and_emissive_objects() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "disk", center = [0.0, 3.0, 0.0], normal = [0.0, -1.0, 0.0], radius = 0.5 }
+            material = { diffuse_color = { type = "solid_color", color = [0, 0, 0] }, emission = [4.0, 3.0, 2.0] }
+
+            [[lights]]
+            shape = { type = "sphere", origin = [0.0, 5.0, 0.0], radius = 1.0 }
+            intensity = 0.5
+            samples = 8
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.emitters().count(), 1);
+        assert_eq!(
+            scene.objects()[0].material.emission,
+            Color::new(4.0, 3.0, 2.0)
+        );
+        assert!(matches!(scene.lights[0].shape, LightShape::Area(_)));
+        assert_eq!(scene.lights[0].samples, 8);
+    }
+
+    #[test]
+    fn loads_colored_directional_and_spot_lights() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[lights]]
+            direction = [0.0, -1.0, 0.0]
+            intensity = 1.0
+            color = [255, 0, 0]
+
+            [[lights]]
+            origin = [0.0, 5.0, 0.0]
+            spot = { direction = [0.0, -1.0, 0.0], cone_angle = 30.0, penumbra_angle = 5.0 }
+            intensity = 2.0
+            falloff = "inverse_square"
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            scene.lights[0].shape,
+            LightShape::Directional(direction) if direction == Vec3::new(0.0, -1.0, 0.0)
+        ));
+        assert_eq!(scene.lights[0].color, Color::new(1.0, 0.0, 0.0));
+        assert!(matches!(
+            scene.lights[1].shape,
+            LightShape::Spot { cone_angle, penumbra_angle, .. }
+                if cone_angle == 30.0 && penumbra_angle == 5.0
+        ));
+        assert_eq!(scene.lights[1].falloff, Falloff::InverseSquare);
+        assert_eq!(scene.lights[1].color, Color::WHITE);
+    }
+
+    #[test]
+    fn rejects_mesh_lights_and_lights_without_position() {
+        for light in [
+            // rejected before the file is even looked for
+            r#"shape = { type = "mesh", path = "missing.obj" }"#,
+            r#"origin = [0.0, 0.0, 0.0]
+            shape = { type = "sphere", origin = [0.0, 5.0, 0.0], radius = 1.0 }"#,
+            r#"direction = [0.0, 0.0, 0.0]"#,
+            r#"origin = [0.0, 0.0, 0.0]
+            spot = { direction = [0.0, -1.0, 0.0], cone_angle = 30.0, penumbra_angle = 40.0 }"#,
+            "",
+        ] {
+            let result = parse(&format!(
+                r#"
+                sky = {{ type = "solid_color", color = [0, 0, 0] }}
+
+                [[lights]]
+                {}
+                intensity = 0.5
+                "#,
+                light
+            ));
+
+            assert!(matches!(result, Err(SceneError::Invalid(_))), "{}", light);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_surface() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "torus", origin = [0.0, 0.0, -5.0] }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, albedo = { diffuse = 0.9, specular = 0.1 } }
+            "#,
+        );
+
+        assert!(matches!(result, Err(SceneError::Parse(..))));
+    }
+
+    #[test]
+    fn rejects_negative_radius() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = -1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, albedo = { diffuse = 0.9, specular = 0.1 } }
+            "#,
+        );
+
+        match result {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: object #0: sphere radius must be positive, got -1"
+            ),
+            _ => panic!("negative radius must be rejected"),
+        }
+    }
+
+    #[test]
+    fn rejects_negative_absorption() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [0, 0, 0] }, albedo = { refraction = 1.0 }, refractive_index = 1.33, absorption = [0.1, -0.2, 0.0] }
+            "#,
+        );
+
+        match result {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: object #0: absorption must be non-negative numbers, got [0.1, -0.2, 0.0]"
+            ),
+            _ => panic!("negative absorption must be rejected"),
+        }
+    }
+
+    #[test]
+    fn loads_metallic_roughness_materials() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 200, 80] }, metallic_roughness = { metallic = 1.0, roughness = 0.3 } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            scene.objects()[0].material.metallic_roughness,
+            Some(MetallicRoughness {
+                metallic: 1.0,
+                roughness: 0.3,
+                specular: 0.5,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_roughness_out_of_range() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 200, 80] }, metallic_roughness = { metallic = 0.0, roughness = 1.5 } }
+            "#,
+        );
+
+        match result {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: object #0: roughness must be between 0 and 1, got 1.5"
+            ),
+            _ => panic!("roughness above 1 must be rejected"),
+        }
+    }
+
+    #[test]
+    fn loads_bundled_material_library() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../materials.toml");
+
+        let materials = load_material_library(&path).unwrap();
+
+        assert_eq!(materials.len(), 7);
+        assert_eq!(
+            materials["glass"].albedo,
+            Albedo {
+                specular: 0.6,
+                refraction: 0.9,
+                ..Albedo::default()
+            }
+        );
+        assert_eq!(materials["glass"].refractive_index, 1.8);
+    }
+
+    #[test]
+    fn objects_refer_to_named_materials() {
+        let scene = parse(
+            r#"
+            material_libraries = ["materials.toml"]
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.lamp]
+            diffuse_color = { type = "solid_color", color = [0, 0, 0] }
+            emission = [2.0, 2.0, 2.0]
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "mirror"
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 3.0, -5.0], radius = 1.0 }
+            material = "lamp"
+
+            [[objects]]
+            surface = { type = "sphere", origin = [3.0, 3.0, -5.0], radius = 1.0 }
+            material = "lamp"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.objects()[0].material.albedo.reflection, 0.8);
+        assert_eq!(
+            scene.objects()[1].material.emission,
+            Color::new(2.0, 2.0, 2.0)
+        );
+        // the material is built once for both lamps
+        assert!(Arc::ptr_eq(
+            &scene.objects()[1].material.diffuse_color,
+            &scene.objects()[2].material.diffuse_color
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_materials_no_object_uses() {
+        let unused = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.broken]
+            diffuse_color = { type = "image", path = "missing.png" }
+            "#,
+        );
+
+        match unused {
+            Err(SceneError::Texture(path, _)) => assert!(path.ends_with("missing.png")),
+            _ => panic!("every material has to be valid"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_and_redefined_materials() {
+        let unknown = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "unobtainium"
+            "#,
+        );
+        let redefined = parse(
+            r#"
+            material_libraries = ["materials.toml"]
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.glass]
+            diffuse_color = { type = "solid_color", color = [0, 0, 0] }
+            "#,
+        );
+
+        match unknown {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: object #0: unknown material \"unobtainium\""
+            ),
+            _ => panic!("unknown materials must be rejected"),
+        }
+        match redefined {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: material \"glass\" is defined more than once"
+            ),
+            _ => panic!("materials must not be redefined"),
+        }
+    }
+
+    #[test]
+    fn rejects_albedo_out_of_range() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, albedo = { diffuse = 1.5 } }
+            "#,
+        );
+        let see_through = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, opacity = -0.5 }
+            "#,
+        );
+
+        assert!(
+            matches!(see_through, Err(SceneError::Invalid(message)) if message.contains("opacity"))
+        );
+        match result {
+            Err(error @ SceneError::Invalid(_)) => assert_eq!(
+                error.to_string(),
+                "invalid scene: object #0: diffuse albedo must be between 0 and 1, got 1.5"
+            ),
+            _ => panic!("albedo above 1 must be rejected"),
+        }
+    }
+
+    #[test]
+    fn reports_missing_mesh_file() {
+        let result = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "mesh", path = "does_not_exist.obj" }
+            material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] }, albedo = { diffuse = 0.9, specular = 0.1 } }
+            "#,
+        );
+
+        assert!(matches!(result, Err(SceneError::Mesh(..))));
+    }
+
+    #[test]
+    fn meshes_without_a_material_need_one_from_their_file() {
+        let mesh = |material: &str| {
+            parse(&format!(
+                r#"
+                sky = {{ type = "solid_color", color = [0, 0, 0] }}
+
+                [[objects]]
+                surface = {{ type = "mesh", path = "cube.obj" }}
+                {}
+                "#,
+                material
+            ))
+        };
+
+        let given =
+            mesh(r#"material = { diffuse_color = { type = "solid_color", color = [255, 0, 0] } }"#);
+        // the material library of the cube isn't bundled
+        let missing = mesh("");
+
+        assert_eq!(given.unwrap().objects().len(), 1);
+        assert!(matches!(
+            missing,
+            Err(SceneError::Mesh(_, ObjError::Materials(_)))
+        ));
+    }
+
+    #[test]
+    fn lights_the_scene_with_the_sky() {
+        let lit = parse(
+            r#"
+            sky = { type = "solid_color", color = [255, 255, 255] }
+            environment_light = { samples = 8 }
+            "#,
+        )
+        .unwrap();
+        let unlit = parse(
+            r#"
+            sky = { type = "solid_color", color = [255, 255, 255] }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(lit.environment_light().unwrap().samples, 8);
+        assert!(unlit.environment_light().is_none());
+    }
+
+    #[test]
+    fn loads_procedural_sky() {
+        let scene = parse(
+            r#"
+            sky = { type = "preetham_sky", sun_direction = [1.0, 1.0, 0.0], turbidity = 4.0 }
+            environment_light = {}
+            "#,
+        );
+        let too_hazy = parse(
+            r#"
+            sky = { type = "preetham_sky", sun_direction = [1.0, 1.0, 0.0], turbidity = 20.0 }
+            "#,
+        );
+        let too_clear = parse(
+            r#"
+            sky = { type = "preetham_sky", sun_direction = [1.0, 1.0, 0.0], turbidity = 1.0 }
+            "#,
+        );
+        let nowhere = parse(
+            r#"
+            sky = { type = "preetham_sky", sun_direction = [nan, 1.0, 0.0] }
+            "#,
+        );
+
+        let scene = scene.unwrap();
+        assert!(scene.environment_light().is_some());
+        assert_eq!(scene.lights.len(), 1);
+        assert!(matches!(scene.lights[0].shape, LightShape::Directional(_)));
+        for hazy in [too_hazy, too_clear] {
+            assert!(
+                matches!(hazy, Err(SceneError::Invalid(message)) if message.contains("turbidity"))
+            );
+        }
+        assert!(
+            matches!(nowhere, Err(SceneError::Invalid(message)) if message.contains("sun direction"))
+        );
+    }
+
+    #[test]
+    fn loads_normal_and_bump_maps() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.bumpy]
+            diffuse_color = { type = "solid_color", color = [255, 255, 255] }
+            normal_map = { type = "bump", heights = { type = "checker_board", light = [255, 255, 255], dark = [0, 0, 0] }, depth = 0.1 }
+
+            [materials.flat]
+            diffuse_color = { type = "solid_color", color = [255, 255, 255] }
+            normal_map = { type = "tangent_space", texture = { type = "solid_color", color = [128, 128, 255] } }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "bumpy"
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, 5.0], radius = 1.0 }
+            material = "flat"
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            scene.objects()[0].material.normal_map,
+            Some(NormalMap::Bump { depth, .. }) if depth == 0.1
+        ));
+        // normal maps hold plain numbers, not sRGB colors
+        let Some(NormalMap::TangentSpace(normals)) = &scene.objects()[1].material.normal_map else {
+            panic!("expected a tangent space normal map");
+        };
+        let (r, _, _) = normals.get(&TextureQuery::at(None)).as_channels();
+        assert_eq!(r, 128.0 / 255.0);
+    }
+
+    #[test]
+    fn loads_image_texture_options() {
+        let scene = parse(
+            r#"
+            sky = { type = "image", path = "sky.png", filter = "bilinear", wrap_u = "mirrored_repeat", wrap_v = "clamp", scale = [2.0, 1.0], offset = [0.5, 0.0] }
+            "#,
+        );
+        let unknown_wrap_mode = parse(
+            r#"
+            sky = { type = "image", path = "sky.png", wrap_u = "tile" }
+            "#,
+        );
+
+        assert!(scene.is_ok());
+        assert!(matches!(unknown_wrap_mode, Err(SceneError::Parse(..))));
+    }
+
+    #[test]
+    fn loads_procedural_textures() {
+        let scene = parse(
+            r#"
+            sky = { type = "checker_board", light = [255, 255, 255], dark = [0, 0, 0], rows = 2 }
+
+            [materials.marble]
+            diffuse_color = { type = "procedural", pattern = { type = "marble", octaves = 3 }, scale = 2.0, colors = [[40, 40, 40], [230, 230, 230]] }
+
+            [materials.stripes]
+            diffuse_color = { type = "procedural", pattern = { type = "stripes", axis = [0.0, 1.0, 0.0], width = 0.25 }, space = "uv" }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "marble"
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, 5.0], radius = 1.0 }
+            material = "stripes"
+            "#,
+        )
+        .unwrap();
+        let zero_axis = parse(
+            r#"
+            sky = { type = "procedural", pattern = { type = "gradient", axis = [0.0, 0.0, 0.0] } }
+            "#,
+        );
+        let no_rows = parse(
+            r#"
+            sky = { type = "checker_board", light = [255, 255, 255], dark = [0, 0, 0], rows = 0 }
+            "#,
+        );
+        let too_many_octaves = parse(
+            r#"
+            sky = { type = "procedural", pattern = { type = "fbm", octaves = 100 } }
+            "#,
+        );
+        let no_octaves = parse(
+            r#"
+            sky = { type = "procedural", pattern = { type = "turbulence", octaves = 0 } }
+            "#,
+        );
+        let infinite_distortion = parse(
+            r#"
+            sky = { type = "procedural", pattern = { type = "wood", distortion = inf } }
+            "#,
+        );
+
+        let stripes = &scene.objects()[1].material.diffuse_color;
+        // a quarter of every stripe period down `v` is the first color
+        let color_at = |v| stripes.get(&TextureQuery::at(Some((0.5, v))));
+        assert_eq!(color_at(0.2), Color::BLACK);
+        assert_eq!(color_at(0.3), Color::WHITE);
+        // the second square of the top row
+        let sky = &scene.sky_sphere.diffuse_color;
+        assert_eq!(sky.get(&TextureQuery::at(Some((0.6, 0.1)))), Color::BLACK);
+        assert!(matches!(zero_axis, Err(SceneError::Invalid(message)) if message.contains("axis")));
+        assert!(matches!(no_rows, Err(SceneError::Invalid(message)) if message.contains("row")));
+        for result in [too_many_octaves, no_octaves] {
+            assert!(
+                matches!(result, Err(SceneError::Invalid(message)) if message.contains("octaves"))
+            );
+        }
+        assert!(matches!(
+            infinite_distortion,
+            Err(SceneError::Invalid(message)) if message.contains("distortion")
+        ));
+    }
+
+    #[test]
+    fn loads_parameter_maps() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.worn]
+            diffuse_color = { type = "solid_color", color = [200, 200, 200] }
+            metallic_roughness = { metallic = 1.0, roughness = 1.0 }
+
+            [materials.worn.parameter_maps]
+            roughness = { texture = { type = "solid_color", color = [0, 51, 0] }, channel = "green" }
+            metallic = { texture = { type = "procedural", pattern = { type = "checker" }, space = "uv", scale = 2.0 } }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "worn"
+            "#,
+        )
+        .unwrap();
+        let unknown_map = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = { diffuse_color = { type = "solid_color", color = [0, 0, 0] }, parameter_maps = { glow = { texture = { type = "solid_color", color = [0, 0, 0] } } } }
+            "#,
+        );
+
+        let material = &scene.objects()[0].material;
+        let metallic_roughness_at = |texture_coords| {
+            material
+                .parameters(&TextureQuery::at(Some(texture_coords)))
+                .metallic_roughness
+                .unwrap()
+        };
+        // the maps hold plain numbers, 51 is a fifth of 255 and not sRGB encoded
+        assert_eq!(metallic_roughness_at((0.1, 0.1)).roughness, 0.2);
+        // the squares of the checker pattern are metal and non-metal in turn
+        assert_eq!(metallic_roughness_at((0.1, 0.1)).metallic, 0.0);
+        assert_eq!(metallic_roughness_at((0.6, 0.1)).metallic, 1.0);
+        assert!(matches!(unknown_map, Err(SceneError::Parse(..))));
+    }
+
+    #[test]
+    fn loads_texture_node_graphs() {
+        let scene = parse(
+            r#"
+            sky = { type = "solid_color", color = [0, 0, 0] }
+
+            [materials.layered.diffuse_color]
+            type = "mix"
+            first = { type = "solid_color", color = [255, 0, 0] }
+            second = { type = "multiply", first = { type = "solid_color", color = [255, 255, 255] }, second = { type = "solid_color", color = [0, 0, 255] } }
+            mask = { texture = { type = "transform_uv", texture = { type = "checker_board", light = [255, 255, 255], dark = [0, 0, 0], rows = 2 }, scale = [2.0, 2.0] }, to = [0.0, 0.5] }
+
+            [materials.ramp]
+            diffuse_color = { type = "triplanar", texture = { type = "color_ramp", input = { texture = { type = "procedural", pattern = { type = "noise" } } }, stops = [{ position = 0.0, color = [0, 0, 0] }, { position = 1.0, color = [255, 255, 255] }] } }
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, -5.0], radius = 1.0 }
+            material = "layered"
+
+            [[objects]]
+            surface = { type = "sphere", origin = [0.0, 0.0, 5.0], radius = 1.0 }
+            material = "ramp"
+            "#,
+        )
+        .unwrap();
+        let no_stops = parse(
+            r#"
+            sky = { type = "color_ramp", input = { texture = { type = "solid_color", color = [0, 0, 0] } }, stops = [] }
+            "#,
+        );
+        let blurry = parse(
+            r#"
+            sky = { type = "triplanar", texture = { type = "solid_color", color = [0, 0, 0] }, sharpness = 0.5 }
+            "#,
+        );
+        let shrunk = parse(
+            r#"
+            sky = { type = "triplanar", texture = { type = "solid_color", color = [0, 0, 0] }, scale = 0.0 }
+            "#,
+        );
+
+        let layered = &scene.objects()[0].material.diffuse_color;
+        // the checker board repeats twice, its white squares let half of the blue through
+        assert_eq!(
+            layered.get(&TextureQuery::at(Some((0.1, 0.1)))),
+            Color::new(0.5, 0.0, 0.5)
+        );
+        assert_eq!(
+            layered.get(&TextureQuery::at(Some((0.3, 0.1)))),
+            Color::new(1.0, 0.0, 0.0)
+        );
+        assert!(matches!(no_stops, Err(SceneError::Invalid(message)) if message.contains("stop")));
+        assert!(
+            matches!(blurry, Err(SceneError::Invalid(message)) if message.contains("sharpness"))
+        );
+        assert!(matches!(shrunk, Err(SceneError::Invalid(message)) if message.contains("scale")));
+    }
+}
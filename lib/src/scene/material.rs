@@ -1,183 +1,377 @@
 use core::f32;
 
-use crate::buffer::{Buffer, Dimensions, Point, Rgb};
-use crate::common::DEBUG_PINK;
+use crate::color::Color;
+use crate::vector_math::Vec3;
 
-use std::{fs::File, io::Error};
+use std::{collections::HashMap, path::Path, sync::Arc};
 
+use super::loader::load_material_library;
+pub use super::texture::{
+    Channel, ChannelTexture, CheckerBoard, ImageTexture, SolidColor, TextureError, TextureFilter,
+    TextureQuery, UvTransform, WrapMode,
+};
+use super::SceneError;
+
+/// Textures are shared between clones, so a material built once can be handed to any number of
+/// objects.
+#[derive(Clone)]
 pub struct Material {
-    pub diffuse_color: Box<dyn ValueGenerator<Rgb> + Send + Sync>,
+    pub diffuse_color: Arc<dyn ValueGenerator<Color> + Send + Sync>,
     pub shininess: f32,
-    pub albedo: (f32, f32, f32, f32), // diffuse, specular, reflection
+    pub albedo: Albedo,
     pub refractive_index: f32,
+    /// light given off by the surface itself, in the same linear units as the rendered image
+    pub emission: Color,
+    /// Beer–Lambert absorption per unit of distance travelled inside a refractive object
+    pub absorption: Color,
+    /// share of the light the surface stops and shades, the rest passes straight through it
+    /// without bending as through a cutout
+    pub opacity: f32,
+    pub metallic_roughness: Option<MetallicRoughness>,
+    pub normal_map: Option<NormalMap>,
+    pub parameter_maps: ParameterMaps,
 }
 
-pub trait ValueGenerator<U> {
-    fn get(&self, texture_coords: Option<(f32, f32)>) -> U;
+/// How much of the light arriving at a surface each part of the shading accounts for, all of
+/// them between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Albedo {
+    /// Lambertian reflection tinted by the diffuse color
+    pub diffuse: f32,
+    /// Phong highlights of the lights, sharper the higher the shininess
+    pub specular: f32,
+    /// perfect mirror reflection
+    pub reflection: f32,
+    /// light going through the surface, split with the mirror reflection by the Fresnel term
+    pub refraction: f32,
 }
 
-struct SolidColor(Rgb);
-impl ValueGenerator<Rgb> for SolidColor {
-    fn get(&self, _: Option<(f32, f32)>) -> Rgb {
-        self.0.clone()
-    }
+/// glTF style metallic-roughness parameters. When a material has them they take the place of
+/// `shininess` and `albedo` and `diffuse_color` becomes the base color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetallicRoughness {
+    pub metallic: f32,
+    pub roughness: f32,
+    /// reflectance of non-metals seen head on, 0.5 gives the 4% of common dielectrics
+    pub specular: f32,
 }
 
-struct CheckerBoard(Rgb, Rgb);
-
-const CHECKER_BOARD_ROWS: u8 = 8;
+/// Detail added to a surface by bending its normal, looked up with the texture coordinates of
+/// the surface. Surfaces without texture coordinates are left as they are.
+#[derive(Clone)]
+pub enum NormalMap {
+    /// tangent space normals stored as colors, every channel mapped from 0..1 to -1..1 and green
+    /// pointing up the image as OpenGL and glTF have it. Images have to be loaded as linear data.
+    TangentSpace(Arc<dyn ValueGenerator<Color> + Send + Sync>),
+    /// brightness of the texture as the height of the surface, the brightest parts `depth`
+    /// above the darkest
+    Bump {
+        heights: Arc<dyn ValueGenerator<Color> + Send + Sync>,
+        depth: f32,
+    },
+}
 
-impl ValueGenerator<Rgb> for CheckerBoard {
-    fn get(&self, texture_coords: Option<(f32, f32)>) -> Rgb {
-        let light_color = &self.0;
-        let dark_color = &self.1;
-        if let Some((x, y)) = texture_coords {
-            let x = f32::min(1.0 - f32::EPSILON, x);
-            let y = f32::min(1.0 - f32::EPSILON, y);
+// distance in texture coordinates heights are compared over for point lookups
+const MIN_BUMP_STEP: f32 = 1e-3;
 
-            let x = (x * (CHECKER_BOARD_ROWS as f32)) as u8;
-            let y = (y * (CHECKER_BOARD_ROWS as f32)) as u8;
+impl NormalMap {
+    /// Bent normal at the point looked up. `tangent` and `bitangent` run along the surface in
+    /// the directions the texture coordinates grow in and `texture_scale` is the amount of
+    /// texture coordinates covered per unit of length.
+    pub fn shading_normal(
+        &self,
+        query: &TextureQuery,
+        normal: &Vec3,
+        tangent: &Vec3,
+        bitangent: &Vec3,
+        texture_scale: f32,
+    ) -> Vec3 {
+        let normal = normal.normalize();
+        let Some((u, v)) = query.texture_coords else {
+            return normal;
+        };
+        // the frame has to be orthonormal for the tangent space to keep its angles
+        let tangent = (*tangent - normal * (*tangent * normal)).normalize();
+        let bitangent =
+            (*bitangent - normal * (*bitangent * normal) - tangent * (*bitangent * tangent))
+                .normalize();
 
-            return if (x + y) % 2 == 0 {
-                light_color.clone()
-            } else {
-                dark_color.clone()
-            };
+        match self {
+            NormalMap::TangentSpace(normals) => {
+                let (x, y, z) = normals
+                    .get(query)
+                    .map(|channel| channel * 2.0 - 1.0)
+                    .as_channels();
+                // `v` grows down the image
+                (tangent * x - bitangent * y + normal * z).normalize()
+            }
+            NormalMap::Bump { heights, depth } => {
+                let height = |u, v| {
+                    heights
+                        .get(&TextureQuery {
+                            texture_coords: Some((u, v)),
+                            ..*query
+                        })
+                        .luminance()
+                        * depth
+                };
+                // differences over what the ray sees of the texture, so that distant bumps
+                // flatten out along with the blurred heights
+                let step = query.footprint.max(MIN_BUMP_STEP);
+                let center = height(u, v);
+                let slope_u = (height(u + step, v) - center) / step * texture_scale;
+                let slope_v = (height(u, v + step) - center) / step * texture_scale;
+                (normal - tangent * slope_u - bitangent * slope_v).normalize()
+            }
         }
-        DEBUG_PINK.clone()
     }
 }
 
-struct ImageTexture {
-    image_buffer: Buffer,
+/// Textures varying the scalar parameters of a material over its surface. As in glTF the value
+/// looked up, clamped to 0..1, multiplies the constant of the material, which is left as it is
+/// without a map.
+#[derive(Clone, Default)]
+pub struct ParameterMaps {
+    pub shininess: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    pub diffuse: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    /// scales the specular albedo, or the specular reflectance of a metallic-roughness material
+    pub specular: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    pub reflection: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    pub opacity: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    pub metallic: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
+    pub roughness: Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>,
 }
 
-impl ImageTexture {
-    fn load(file_path: &str) -> Result<ImageTexture, Error> {
-        let texture_file = File::open(file_path)?;
-        let decoder = png::Decoder::new(texture_file);
-        let mut reader = decoder.read_info()?;
-        let width = reader.info().width;
-        let height = reader.info().height;
-
-        let mut buffer = Buffer::new(Dimensions(width, height), 3);
-
-        let mut buf = vec![0; reader.output_buffer_size()];
-        reader.next_frame(&mut buf)?;
-        let mut buf = buf.into_iter();
-        let mut idx = 0;
-        let mut idx_without_alpha = 0;
-        while let Some(value) = buf.next() {
-            if (idx + 1) % 4 == 0 {
-                idx += 1;
-                continue;
-            }
-            buffer.set_raw_value(idx_without_alpha, value);
-            idx_without_alpha += 1;
-            idx += 1;
-        }
+/// Scalar parameters of a material at one point of its surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceParameters {
+    pub shininess: f32,
+    pub albedo: Albedo,
+    pub opacity: f32,
+    pub metallic_roughness: Option<MetallicRoughness>,
+}
 
-        Ok(ImageTexture {
-            image_buffer: buffer,
-        })
+impl Material {
+    /// Parameters at the point looked up, with the parameter maps applied. The refractive
+    /// index belongs to the medium inside the object rather than to its surface and can't be
+    /// mapped.
+    pub fn parameters(&self, query: &TextureQuery) -> SurfaceParameters {
+        let maps = &self.parameter_maps;
+        let scale = |map: &Option<Arc<dyn ValueGenerator<f32> + Send + Sync>>, value: f32| {
+            map.as_ref()
+                .map_or(value, |map| map.get(query).clamp(0.0, 1.0) * value)
+        };
+        let albedo = Albedo {
+            diffuse: scale(&maps.diffuse, self.albedo.diffuse),
+            specular: scale(&maps.specular, self.albedo.specular),
+            reflection: scale(&maps.reflection, self.albedo.reflection),
+            refraction: self.albedo.refraction,
+        };
+        SurfaceParameters {
+            shininess: scale(&maps.shininess, self.shininess),
+            albedo,
+            opacity: scale(&maps.opacity, self.opacity),
+            metallic_roughness: self.metallic_roughness.map(|parameters| MetallicRoughness {
+                metallic: scale(&maps.metallic, parameters.metallic),
+                roughness: scale(&maps.roughness, parameters.roughness),
+                specular: scale(&maps.specular, parameters.specular),
+            }),
+        }
     }
 }
 
-impl ValueGenerator<Rgb> for ImageTexture {
-    fn get(&self, texture_coords: Option<(f32, f32)>) -> Rgb {
-        if let Some((x, y)) = texture_coords {
-            let x = (f32::round((*self.image_buffer.width() - 1) as f32 * x) as u32)
-                .min(*self.image_buffer.width() - 1);
-            let y = (f32::round((*self.image_buffer.height() - 1) as f32 * y) as u32)
-                .min(*self.image_buffer.height() - 1);
+pub trait ValueGenerator<U> {
+    fn get(&self, query: &TextureQuery) -> U;
 
-            return self.image_buffer.get(&Point(x, y));
-        }
-        DEBUG_PINK
+    /// Whether the value is looked up by texture coordinates, which some surfaces don't have.
+    fn uses_texture_coords(&self) -> bool {
+        false
     }
 }
 
 pub struct Materials {}
 
 impl Materials {
-    pub fn solid_color(color: Rgb) -> Material {
+    pub fn solid_color(color: Color) -> Material {
         Material {
-            diffuse_color: Box::new(SolidColor(color)),
+            diffuse_color: Arc::new(SolidColor(color)),
             shininess: 0.0,
-            albedo: (0.5, 0.5, 0.0, 0.0),
+            albedo: Albedo {
+                diffuse: 0.5,
+                specular: 0.5,
+                ..Albedo::default()
+            },
             refractive_index: 0.0,
+            emission: Color::BLACK,
+            absorption: Color::BLACK,
+            opacity: 1.0,
+            metallic_roughness: None,
+            normal_map: None,
+            parameter_maps: ParameterMaps::default(),
         }
     }
 
-    pub fn checker_board() -> Material {
+    /// Clear refractive material, the light it reflects and lets through is split by the
+    /// Fresnel equations and `absorption` gives it a color the thicker it gets.
+    pub fn dielectric(refractive_index: f32, absorption: Color) -> Material {
         Material {
-            diffuse_color: Box::new(CheckerBoard(Rgb::new(179, 118, 62), Rgb::new(67, 45, 35))),
-            shininess: 50.0,
-            albedo: (0.8, 0.6, 0.1, 0.0),
-            refractive_index: 0.0,
+            diffuse_color: Arc::new(SolidColor(Color::BLACK)),
+            shininess: 200.0,
+            albedo: Albedo {
+                specular: 0.6,
+                refraction: 1.0,
+                ..Albedo::default()
+            },
+            refractive_index,
+            emission: Color::BLACK,
+            absorption,
+            opacity: 1.0,
+            metallic_roughness: None,
+            normal_map: None,
+            parameter_maps: ParameterMaps::default(),
         }
     }
 
-    pub fn rubbery_red_checker_board() -> Material {
+    pub fn metallic_roughness(base_color: Color, metallic: f32, roughness: f32) -> Material {
         Material {
-            diffuse_color: Box::new(CheckerBoard(Rgb::new(190, 30, 30), Rgb::new(230, 30, 30))),
-            shininess: 10.0,
-            albedo: (0.9, 0.1, 0.0, 0.0),
+            diffuse_color: Arc::new(SolidColor(base_color)),
+            shininess: 0.0,
+            albedo: Albedo::default(),
             refractive_index: 0.0,
+            emission: Color::BLACK,
+            absorption: Color::BLACK,
+            opacity: 1.0,
+            metallic_roughness: Some(MetallicRoughness {
+                metallic,
+                roughness,
+                specular: 0.5,
+            }),
+            normal_map: None,
+            parameter_maps: ParameterMaps::default(),
         }
     }
 
-    pub fn glossy_blue() -> Material {
+    pub fn emissive(emission: Color) -> Material {
         Material {
-            diffuse_color: Box::new(SolidColor(Rgb::new(50, 50, 170))),
-            shininess: 200.0,
-            albedo: (0.5, 0.5, 0.0, 0.0),
+            diffuse_color: Arc::new(SolidColor(Color::BLACK)),
+            shininess: 0.0,
+            albedo: Albedo::default(),
             refractive_index: 0.0,
+            emission,
+            absorption: Color::BLACK,
+            opacity: 1.0,
+            metallic_roughness: None,
+            normal_map: None,
+            parameter_maps: ParameterMaps::default(),
         }
     }
 
-    pub fn rubbery_red() -> Material {
-        Material {
-            diffuse_color: Box::new(SolidColor(Rgb::new(190, 30, 30))),
-            shininess: 10.0,
-            albedo: (0.9, 0.1, 0.0, 0.0),
-            refractive_index: 0.0,
-        }
+    /// Named materials from a library file, a TOML table per material in the same format as the
+    /// materials in scene files.
+    pub fn load_library(path: impl AsRef<Path>) -> Result<HashMap<String, Material>, SceneError> {
+        load_material_library(path.as_ref())
     }
+}
 
-    pub fn glossy_green() -> Material {
-        Material {
-            diffuse_color: Box::new(SolidColor(Rgb::new(50, 250, 50))),
-            shininess: 50.0,
-            albedo: (0.8, 0.6, 0.1, 0.0),
-            refractive_index: 0.0,
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    const NORMAL: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+    const TANGENT: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+    const BITANGENT: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    // heights rising along `u`
+    struct Ramp;
+
+    impl ValueGenerator<Color> for Ramp {
+        fn get(&self, query: &TextureQuery) -> Color {
+            let (u, _) = query.texture_coords.unwrap();
+            Color::new(u, u, u)
         }
-    }
 
-    pub fn mirror() -> Material {
-        Material {
-            diffuse_color: Box::new(SolidColor(Rgb::new(10, 10, 10))),
-            shininess: 200.0,
-            albedo: (0.2, 0.6, 0.8, 0.0),
-            refractive_index: 0.0,
+        fn uses_texture_coords(&self) -> bool {
+            true
         }
     }
 
-    pub fn glass() -> Material {
-        Material {
-            diffuse_color: Box::new(SolidColor(Rgb::new(10, 10, 10))),
-            shininess: 200.0,
-            albedo: (0.0, 0.6, 0.0, 0.9),
-            refractive_index: 1.8,
-        }
+    fn bend(normal_map: &NormalMap, texture_coords: Option<(f32, f32)>) -> (f32, f32, f32) {
+        let normal = normal_map.shading_normal(
+            &TextureQuery::at(texture_coords),
+            &NORMAL,
+            &TANGENT,
+            &BITANGENT,
+            1.0,
+        );
+        let (x, y, z) = normal.as_coords();
+        (cap_float(x), cap_float(y), cap_float(z))
     }
 
-    pub fn skysphere() -> Material {
-        Material {
-            diffuse_color: Box::new(ImageTexture::load("sky.png").unwrap()),
-            shininess: 0.0,
-            albedo: (1.0, 0.0, 0.0, 0.0),
-            refractive_index: 0.0,
-        }
+    #[test]
+    fn flat_normal_map_leaves_the_normal_alone() {
+        let flat = NormalMap::TangentSpace(Arc::new(SolidColor(Color::new(0.5, 0.5, 1.0))));
+
+        assert_eq!(bend(&flat, Some((0.3, 0.3))), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn tangent_space_normals_follow_the_texture_directions() {
+        let towards_u = NormalMap::TangentSpace(Arc::new(SolidColor(Color::new(1.0, 0.5, 0.5))));
+        // green points up the image, against the direction `v` grows in
+        let up_the_image = NormalMap::TangentSpace(Arc::new(SolidColor(Color::new(0.5, 1.0, 0.5))));
+
+        assert_eq!(bend(&towards_u, Some((0.3, 0.3))), (1.0, 0.0, 0.0));
+        assert_eq!(bend(&up_the_image, Some((0.3, 0.3))), (0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn bumps_tilt_the_normal_away_from_the_slope() {
+        let bumps = NormalMap::Bump {
+            heights: Arc::new(Ramp),
+            depth: 1.0,
+        };
+
+        // rising by 1 over 1 unit of length tilts the normal by 45 degrees
+        assert_eq!(bend(&bumps, Some((0.3, 0.3))), (-0.7, 0.0, 0.7));
+        assert_eq!(bend(&bumps, None), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn parameter_maps_scale_the_constants() {
+        let mut material = Materials::metallic_roughness(Color::WHITE, 1.0, 0.8);
+        let unmapped = material.parameters(&TextureQuery::at(Some((0.5, 0.5))));
+        material.parameter_maps.roughness = Some(Arc::new(ChannelTexture {
+            texture: Box::new(Ramp),
+            channel: Channel::Red,
+        }));
+
+        let mapped = material.parameters(&TextureQuery::at(Some((0.5, 0.5))));
+
+        assert_eq!(unmapped.metallic_roughness, material.metallic_roughness);
+        let MetallicRoughness {
+            metallic,
+            roughness,
+            ..
+        } = mapped.metallic_roughness.unwrap();
+        assert_eq!((metallic, roughness), (1.0, 0.4));
+    }
+
+    #[test]
+    fn opacity_maps_cut_out_the_surface() {
+        let mut glass = glass();
+        glass.parameter_maps.opacity = Some(Arc::new(ChannelTexture {
+            texture: Box::new(Ramp),
+            channel: Channel::Luminance,
+        }));
+        let parameters_at = |texture_coords| glass.parameters(&TextureQuery::at(texture_coords));
+
+        assert_eq!(parameters_at(Some((0.0, 0.0))).opacity, 0.0);
+        assert_eq!(parameters_at(Some((0.25, 0.0))).opacity, 0.25);
+        // the glass itself is left as it is, it only shows less
+        assert_eq!(parameters_at(Some((0.25, 0.0))).albedo, glass.albedo);
+        // without texture coordinates the map can't be looked up and the glass stays whole
+        assert_eq!(parameters_at(None).opacity, 1.0);
+        assert_eq!(parameters_at(None).albedo, glass.albedo);
     }
 }
@@ -1,81 +1,126 @@
 use super::triangle::Triangle;
 use super::Surface;
 
-use crate::ray_tracing::Intersection;
+use crate::ray_tracing::{Aabb, Bvh, Intersection};
 use crate::vector_math::Vec3;
 
 #[derive(Debug, PartialEq)]
 pub struct Mesh {
     triangles: Vec<Triangle>,
+    bvh: Bvh,
+    texture_scale: f32,
 }
 
 impl Surface for Mesh {
     fn find_intersection(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<Intersection> {
-        let mut closest_distance = f32::MAX;
-        let mut closest_traingle = None;
-        let mut intersection_coords = None;
-        for triangle in &self.triangles {
-            if let Some(Intersection {
-                distance,
-                texture_coords,
-                ..
-            }) = triangle.find_intersection(ray_origin, ray_direction)
-            {
-                if distance < closest_distance {
-                    closest_distance = distance;
-                    closest_traingle = Some(triangle);
-                    intersection_coords = texture_coords;
-                }
-            }
-        }
-        closest_traingle.map(|triangle| Intersection {
-            distance: closest_distance,
-            normal: triangle.normal(),
-            texture_coords: intersection_coords,
-        })
+        self.bvh
+            .closest_intersection(ray_origin, ray_direction, |idx| {
+                self.triangles[idx].find_intersection(ray_origin, ray_direction)
+            })
+            .map(|(_, intersection)| intersection)
+    }
+
+    fn aabb(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn texture_scale(&self) -> f32 {
+        self.texture_scale
     }
 }
 
 impl Mesh {
     pub fn new(triangles: Vec<Triangle>) -> Mesh {
-        Mesh { triangles }
+        let bounds: Vec<Aabb> = triangles.iter().map(|triangle| triangle.aabb()).collect();
+        // a single scale for the whole mesh, averaged over the triangles that are textured
+        let (texture_area, area) = triangles
+            .iter()
+            .filter_map(|triangle| Some((triangle.texture_area()?, triangle.area())))
+            .fold((0.0, 0.0), |(texture_sum, sum), (texture_area, area)| {
+                (texture_sum + texture_area, sum + area)
+            });
+        Mesh {
+            bvh: Bvh::build(&bounds),
+            triangles,
+            texture_scale: if area > 0.0 {
+                (texture_area / area).sqrt()
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::obj::ObjFile;
+
+    fn brute_force_intersection(
+        mesh: &Mesh,
+        ray_origin: &Vec3,
+        ray_direction: &Vec3,
+    ) -> Option<Intersection> {
+        let mut closest: Option<Intersection> = None;
+        for triangle in &mesh.triangles {
+            if let Some(intersection) = triangle.find_intersection(ray_origin, ray_direction) {
+                if closest
+                    .as_ref()
+                    .is_none_or(|closest| intersection.distance < closest.distance)
+                {
+                    closest = Some(intersection);
+                }
+            }
+        }
+        closest
+    }
+
+    fn random_mesh(random: &mut SmallRng, number_of_triangles: usize) -> Mesh {
+        Mesh::new(
+            (0..number_of_triangles)
+                .map(|_| {
+                    let a = random_vec3(random, 20.0);
+                    Triangle::new(
+                        a,
+                        a + random_vec3(random, 2.0),
+                        a + random_vec3(random, 2.0),
+                    )
+                })
+                .collect(),
+        )
     }
 
-    pub fn from_obj_file(file_name: &str) -> Mesh {
-        let (models, _) = tobj::load_obj(file_name, &tobj::LoadOptions::default())
-            .expect(&format!("Can not load file {}", file_name));
-
-        let model = &models[0];
-
-        let mut triangles = vec![];
-
-        let number_of_triangles = model.mesh.indices.len() / 3;
-
-        let indices: Vec<_> = model.mesh.indices.iter().map(|idx| *idx as usize).collect();
-        let positions = &model.mesh.positions;
-
-        for face_idx in 0..number_of_triangles {
-            let offset = face_idx * 3;
-
-            triangles.push(Triangle::new(
-                Vec3::new(
-                    positions[indices[offset] * 3] as f32,
-                    positions[indices[offset] * 3 + 1] as f32,
-                    positions[indices[offset] * 3 + 2] as f32,
-                ),
-                Vec3::new(
-                    positions[indices[offset + 1] * 3] as f32,
-                    positions[indices[offset + 1] * 3 + 1] as f32,
-                    positions[indices[offset + 1] * 3 + 2] as f32,
-                ),
-                Vec3::new(
-                    positions[indices[offset + 2] * 3] as f32,
-                    positions[indices[offset + 2] * 3 + 1] as f32,
-                    positions[indices[offset + 2] * 3 + 2] as f32,
-                ),
-            ));
+    #[test]
+    fn bvh_finds_same_hits_as_brute_force() {
+        let mut random = SmallRng::seed_from_u64(42);
+        let mesh = random_mesh(&mut random, 1000);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray_origin = random_vec3(&mut random, 30.0);
+            let ray_direction = random_vec3(&mut random, 1.0);
+
+            let expected = brute_force_intersection(&mesh, &ray_origin, &ray_direction);
+            let actual = mesh.find_intersection(&ray_origin, &ray_direction);
+
+            hits += expected.is_some() as u32;
+            assert_eq!(expected, actual);
         }
+        assert!(hits > 100, "too few rays hit the mesh to be meaningful");
+    }
+
+    #[test]
+    fn loads_cube() {
+        let mut file = ObjFile::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../cube.obj")).unwrap();
+        let mesh = file.models.remove(0).mesh;
 
-        Mesh { triangles }
+        assert_eq!(mesh.triangles.len(), 12);
+        let aabb = mesh.aabb();
+        assert!(aabb.min[1] < 0.6 && aabb.max[1] > 3.5);
     }
 }
@@ -0,0 +1,311 @@
+use std::ops;
+
+use super::material::{TextureQuery, UvTransform, ValueGenerator};
+use crate::color::Color;
+
+/// What the nodes combine, colors as well as scalars.
+pub trait Blend:
+    Copy + ops::Add<Output = Self> + ops::Mul<Output = Self> + ops::Mul<f32, Output = Self>
+{
+}
+
+impl<T> Blend for T where
+    T: Copy + ops::Add<Output = T> + ops::Mul<Output = T> + ops::Mul<f32, Output = T>
+{
+}
+
+/// `first` where the mask is 0, `second` where it is 1 and a blend of both in between.
+pub struct Mix<T> {
+    pub first: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub second: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub mask: Box<dyn ValueGenerator<f32> + Send + Sync>,
+}
+
+impl<T: Blend> ValueGenerator<T> for Mix<T> {
+    fn get(&self, query: &TextureQuery) -> T {
+        let mask = self.mask.get(query).clamp(0.0, 1.0);
+        self.first.get(query) * (1.0 - mask) + self.second.get(query) * mask
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.first.uses_texture_coords()
+            || self.second.uses_texture_coords()
+            || self.mask.uses_texture_coords()
+    }
+}
+
+pub struct Multiply<T> {
+    pub first: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub second: Box<dyn ValueGenerator<T> + Send + Sync>,
+}
+
+impl<T: Blend> ValueGenerator<T> for Multiply<T> {
+    fn get(&self, query: &TextureQuery) -> T {
+        self.first.get(query) * self.second.get(query)
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.first.uses_texture_coords() || self.second.uses_texture_coords()
+    }
+}
+
+pub struct Add<T> {
+    pub first: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub second: Box<dyn ValueGenerator<T> + Send + Sync>,
+}
+
+impl<T: Blend> ValueGenerator<T> for Add<T> {
+    fn get(&self, query: &TextureQuery) -> T {
+        self.first.get(query) + self.second.get(query)
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.first.uses_texture_coords() || self.second.uses_texture_coords()
+    }
+}
+
+/// Maps the range `from` of a scalar linearly onto `to`, values outside of it are clamped.
+pub struct Remap {
+    pub input: Box<dyn ValueGenerator<f32> + Send + Sync>,
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+}
+
+impl ValueGenerator<f32> for Remap {
+    fn get(&self, query: &TextureQuery) -> f32 {
+        let (from_start, from_end) = self.from;
+        let (to_start, to_end) = self.to;
+        let position = if from_end != from_start {
+            ((self.input.get(query) - from_start) / (from_end - from_start)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        to_start + position * (to_end - to_start)
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.input.uses_texture_coords()
+    }
+}
+
+/// Colors a scalar by blending between the colors of the stops around it. The stops are sorted
+/// by their position, before the first and after the last one their colors carry on.
+pub struct ColorRamp {
+    pub input: Box<dyn ValueGenerator<f32> + Send + Sync>,
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(
+        input: Box<dyn ValueGenerator<f32> + Send + Sync>,
+        mut stops: Vec<(f32, Color)>,
+    ) -> ColorRamp {
+        stops.sort_by(|(first, _), (second, _)| first.total_cmp(second));
+        ColorRamp { input, stops }
+    }
+}
+
+impl ValueGenerator<Color> for ColorRamp {
+    fn get(&self, query: &TextureQuery) -> Color {
+        let value = self.input.get(query);
+        let next = self
+            .stops
+            .partition_point(|(position, _)| *position <= value);
+        match (self.stops.get(next.wrapping_sub(1)), self.stops.get(next)) {
+            (Some(&(start, start_color)), Some(&(end, end_color))) => {
+                let blend = (value - start) / (end - start);
+                start_color * (1.0 - blend) + end_color * blend
+            }
+            (Some(&(_, color)), None) | (None, Some(&(_, color))) => color,
+            (None, None) => Color::BLACK,
+        }
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.input.uses_texture_coords()
+    }
+}
+
+/// Looks its input up at scaled and shifted texture coordinates, so that the same texture can
+/// be laid out differently in every layer.
+pub struct TransformUv<T> {
+    pub input: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub transform: UvTransform,
+}
+
+impl<T> ValueGenerator<T> for TransformUv<T> {
+    fn get(&self, query: &TextureQuery) -> T {
+        let UvTransform { scale, offset } = self.transform;
+        self.input.get(&TextureQuery {
+            texture_coords: query
+                .texture_coords
+                .map(|(u, v)| (u * scale.0 + offset.0, v * scale.1 + offset.1)),
+            footprint: query.footprint * scale.0.abs().max(scale.1.abs()),
+            ..*query
+        })
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.input.uses_texture_coords()
+    }
+}
+
+/// Projects its input onto the surface along the three axes and blends the projections by how
+/// much the surface faces each of them, for texturing surfaces without texture coordinates
+/// without the stretching of a single projection. The input is looked up at the position
+/// times `scale`, `sharpness` of at least 1 narrows down the blending at the edges between the
+/// projections.
+pub struct Triplanar<T> {
+    pub input: Box<dyn ValueGenerator<T> + Send + Sync>,
+    pub scale: f32,
+    pub sharpness: f32,
+}
+
+impl<T: Blend> ValueGenerator<T> for Triplanar<T> {
+    fn get(&self, query: &TextureQuery) -> T {
+        let (Some(position), Some(normal)) = (query.position, query.normal) else {
+            return self.input.get(query);
+        };
+        let (x, y, z) = (position * self.scale).as_coords();
+        let (normal_x, normal_y, normal_z) = normal.normalize().as_coords();
+        let weight = |coord: f32| coord.abs().powf(self.sharpness);
+        let weights = (weight(normal_x), weight(normal_y), weight(normal_z));
+        let total_weight = weights.0 + weights.1 + weights.2;
+        // `v` grows down images, so up stays up on the sides
+        let projection = |texture_coords, weight: f32| {
+            self.input.get(&TextureQuery {
+                texture_coords: Some(texture_coords),
+                ..*query
+            }) * (weight / total_weight)
+        };
+        projection((z, -y), weights.0)
+            + projection((x, z), weights.1)
+            + projection((x, -y), weights.2)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::material::SolidColor;
+    use crate::vector_math::Vec3;
+
+    // the first texture coordinate as a scalar
+    struct U;
+
+    impl ValueGenerator<f32> for U {
+        fn get(&self, query: &TextureQuery) -> f32 {
+            query.texture_coords.map_or(0.0, |(u, _)| u)
+        }
+    }
+
+    fn solid(r: f32, g: f32, b: f32) -> Box<dyn ValueGenerator<Color> + Send + Sync> {
+        Box::new(SolidColor(Color::new(r, g, b)))
+    }
+
+    fn at_u(u: f32) -> TextureQuery {
+        TextureQuery::at(Some((u, 0.0)))
+    }
+
+    #[test]
+    fn mix_blends_by_the_mask() {
+        let mix = Mix {
+            first: solid(1.0, 0.0, 0.0),
+            second: solid(0.0, 0.0, 1.0),
+            mask: Box::new(U),
+        };
+
+        assert_eq!(mix.get(&at_u(0.0)), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(mix.get(&at_u(0.25)), Color::new(0.75, 0.0, 0.25));
+        assert_eq!(mix.get(&at_u(2.0)), Color::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn arithmetic_nodes_work_on_colors_and_scalars() {
+        let multiply = Multiply {
+            first: solid(0.5, 1.0, 0.2),
+            second: solid(0.5, 0.5, 0.5),
+        };
+        let add = Add::<f32> {
+            first: Box::new(U),
+            second: Box::new(U),
+        };
+
+        assert_eq!(multiply.get(&at_u(0.0)), Color::new(0.25, 0.5, 0.1));
+        assert_eq!(add.get(&at_u(0.3)), 0.6);
+    }
+
+    #[test]
+    fn remap_stretches_the_range_and_clamps() {
+        let remap = Remap {
+            input: Box::new(U),
+            from: (0.2, 0.6),
+            to: (1.0, 0.0),
+        };
+
+        assert_eq!(remap.get(&at_u(0.2)), 1.0);
+        assert_eq!(cap_float(remap.get(&at_u(0.5))), 0.3);
+        assert_eq!(remap.get(&at_u(0.9)), 0.0);
+    }
+
+    #[test]
+    fn color_ramp_blends_between_the_stops_around_the_value() {
+        let ramp = ColorRamp::new(
+            Box::new(U),
+            vec![(1.0, Color::WHITE), (0.5, Color::new(1.0, 0.0, 0.0))],
+        );
+
+        assert_eq!(ramp.get(&at_u(0.0)), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(ramp.get(&at_u(0.75)), Color::new(1.0, 0.5, 0.5));
+        assert_eq!(ramp.get(&at_u(1.5)), Color::WHITE);
+    }
+
+    #[test]
+    fn transform_uv_moves_the_lookup() {
+        let shifted = TransformUv {
+            input: Box::new(U) as Box<dyn ValueGenerator<f32> + Send + Sync>,
+            transform: UvTransform {
+                scale: (2.0, 1.0),
+                offset: (0.1, 0.0),
+            },
+        };
+
+        assert_eq!(cap_float(shifted.get(&at_u(0.2))), 0.5);
+    }
+
+    #[test]
+    fn triplanar_projects_along_the_normal() {
+        // red where the first texture coordinate is 0 and green further along
+        let triplanar = Triplanar {
+            input: Box::new(ColorRamp::new(
+                Box::new(U),
+                vec![
+                    (0.0, Color::new(1.0, 0.0, 0.0)),
+                    (1.0, Color::new(0.0, 1.0, 0.0)),
+                ],
+            )),
+            scale: 1.0,
+            sharpness: 4.0,
+        };
+        let query = |normal| TextureQuery {
+            position: Some(Vec3::new(0.0, 0.5, 1.0)),
+            normal: Some(normal),
+            ..TextureQuery::default()
+        };
+
+        // facing along x the texture is laid out over z and y, along y over x and z
+        assert_eq!(
+            triplanar.get(&query(Vec3::new(1.0, 0.0, 0.0))),
+            Color::new(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            triplanar.get(&query(Vec3::new(0.0, -1.0, 0.0))),
+            Color::new(1.0, 0.0, 0.0)
+        );
+        let (r, g, _) = triplanar
+            .get(&query(Vec3::new(1.0, 1.0, 0.0)))
+            .as_channels();
+        assert_eq!((cap_float(r), cap_float(g)), (0.5, 0.5));
+    }
+}
@@ -0,0 +1,378 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::material::{
+    Albedo, ImageTexture, Material, ParameterMaps, SolidColor, TextureError, ValueGenerator,
+};
+use super::mesh::Mesh;
+use super::nodes::Multiply;
+use super::triangle::{generate_vertex_normals, Triangle};
+use crate::color::Color;
+use crate::vector_math::Vec3;
+
+// faces come as triangles, with normals and texture coordinates indexed like the positions
+const LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    single_index: true,
+    triangulate: true,
+    ignore_points: true,
+    ignore_lines: true,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(tobj::LoadError),
+    /// the material library the file refers to can't be read
+    Materials(tobj::LoadError),
+    Texture(PathBuf, TextureError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Load(error) => write!(f, "{}", error),
+            ObjError::Materials(error) => write!(f, "can not read its materials: {}", error),
+            ObjError::Texture(path, error) => {
+                write!(f, "can not load texture {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Load(error) | ObjError::Materials(error) => Some(error),
+            ObjError::Texture(_, error) => Some(error),
+        }
+    }
+}
+
+impl From<tobj::LoadError> for ObjError {
+    fn from(error: tobj::LoadError) -> ObjError {
+        ObjError::Load(error)
+    }
+}
+
+/// The models of a Wavefront OBJ file together with the materials of its MTL library.
+pub struct ObjFile {
+    pub models: Vec<ObjModel>,
+    // only an error once a model needs its material, many files refer to libraries that
+    // don't come with them. The models don't know which material they named then.
+    materials: Result<Vec<tobj::Material>, tobj::LoadError>,
+    // materials already built by their index in the library, models sharing one share its
+    // textures too
+    built_materials: RefCell<HashMap<usize, Material>>,
+    directory: PathBuf,
+}
+
+pub struct ObjModel {
+    pub name: String,
+    pub mesh: Mesh,
+    material_id: Option<usize>,
+}
+
+impl ObjFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<ObjFile, ObjError> {
+        let path = path.as_ref();
+        let (models, materials) = tobj::load_obj(path, &LOAD_OPTIONS)?;
+        ObjFile::new(models, materials, path.parent().unwrap_or(Path::new("")))
+    }
+
+    // textures of the materials are looked up in `directory`
+    fn new(
+        models: Vec<tobj::Model>,
+        materials: Result<Vec<tobj::Material>, tobj::LoadError>,
+        directory: &Path,
+    ) -> Result<ObjFile, ObjError> {
+        let models = models
+            .into_iter()
+            .map(|model| {
+                Ok(ObjModel {
+                    mesh: Mesh::new(triangles(&model.mesh)?),
+                    material_id: model.mesh.material_id,
+                    name: model.name,
+                })
+            })
+            .collect::<Result<Vec<_>, ObjError>>()?;
+        Ok(ObjFile {
+            // lines and points leave models without any faces behind
+            models: models
+                .into_iter()
+                .filter(|model| !model.mesh.triangles().is_empty())
+                .collect(),
+            materials,
+            built_materials: RefCell::default(),
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    /// Material the MTL library gives `model`, `None` when it names none. Each material is
+    /// only built once, models using the same one share its textures.
+    pub fn material(&self, model: &ObjModel) -> Result<Option<Material>, ObjError> {
+        let materials = self
+            .materials
+            .as_ref()
+            .map_err(|error| ObjError::Materials(*error))?;
+        let Some((material_id, material)) = model
+            .material_id
+            .and_then(|material_id| Some((material_id, materials.get(material_id)?)))
+        else {
+            return Ok(None);
+        };
+        if let Some(built) = self.built_materials.borrow().get(&material_id) {
+            return Ok(Some(built.clone()));
+        }
+        let built = build_material(material, &self.directory)?;
+        self.built_materials
+            .borrow_mut()
+            .insert(material_id, built.clone());
+        Ok(Some(built))
+    }
+}
+
+fn triangles(mesh: &tobj::Mesh) -> Result<Vec<Triangle>, ObjError> {
+    let vertex_count = mesh.positions.len() / 3;
+    let has_normals = mesh.normals.len() == vertex_count * 3;
+    let has_texture_coords = mesh.texcoords.len() == vertex_count * 2;
+    let position = |idx: usize| {
+        Vec3::new(
+            mesh.positions[idx * 3],
+            mesh.positions[idx * 3 + 1],
+            mesh.positions[idx * 3 + 2],
+        )
+    };
+    let normal = |idx: usize| {
+        Vec3::new(
+            mesh.normals[idx * 3],
+            mesh.normals[idx * 3 + 1],
+            mesh.normals[idx * 3 + 2],
+        )
+    };
+    // `v` grows up in OBJ files and down the images here
+    let texture_coords = |idx: usize| (mesh.texcoords[idx * 2], 1.0 - mesh.texcoords[idx * 2 + 1]);
+
+    let mut triangles = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|face| {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|idx| idx as usize);
+            if [a, b, c].iter().any(|idx| *idx >= vertex_count) {
+                return Err(ObjError::Load(tobj::LoadError::FaceVertexOutOfBounds));
+            }
+            let mut triangle = Triangle::new(position(a), position(b), position(c));
+            if has_texture_coords {
+                triangle = triangle.with_texture_coords([a, b, c].map(texture_coords));
+            }
+            if has_normals {
+                triangle = triangle.with_vertex_normals([a, b, c].map(normal));
+            }
+            Ok(triangle)
+        })
+        .collect::<Result<Vec<_>, ObjError>>()?;
+    if !has_normals {
+        generate_vertex_normals(&mut triangles);
+    }
+    Ok(triangles)
+}
+
+// the Phong parameters of MTL: diffuse and specular colors, specular exponent, refractive
+// index and dissolve, the opacity of the surface
+fn build_material(material: &tobj::Material, directory: &Path) -> Result<Material, ObjError> {
+    let diffuse = material.diffuse.map(|[r, g, b]| Color::new(r, g, b));
+    let diffuse_color: Arc<dyn ValueGenerator<Color> + Send + Sync> =
+        match (&material.diffuse_texture, diffuse) {
+            (Some(texture), diffuse) => {
+                let path = directory.join(texture);
+                let texture =
+                    ImageTexture::load(&path).map_err(|error| ObjError::Texture(path, error))?;
+                match diffuse {
+                    // the color of the texture is scaled by the diffuse color
+                    Some(diffuse) => Arc::new(Multiply {
+                        first: Box::new(texture),
+                        second: Box::new(SolidColor(diffuse)),
+                    }),
+                    None => Arc::new(texture),
+                }
+            }
+            (None, diffuse) => Arc::new(SolidColor(diffuse.unwrap_or(Color::WHITE))),
+        };
+    let specular = material
+        .specular
+        .map_or(0.0, |[r, g, b]| r.max(g).max(b).clamp(0.0, 1.0));
+    let refractive_index = material
+        .optical_density
+        .filter(|index| *index > 0.0)
+        .unwrap_or(1.0);
+
+    Ok(Material {
+        diffuse_color,
+        shininess: material.shininess.unwrap_or(0.0).max(0.0),
+        albedo: Albedo {
+            diffuse: 1.0,
+            specular,
+            ..Albedo::default()
+        },
+        refractive_index,
+        emission: Color::BLACK,
+        absorption: Color::BLACK,
+        opacity: material.dissolve.unwrap_or(1.0).clamp(0.0, 1.0),
+        metallic_roughness: None,
+        normal_map: None,
+        parameter_maps: ParameterMaps::default(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::*;
+    use crate::scene::material::TextureQuery;
+    use crate::scene::Surface;
+
+    const TWO_MODELS: &str = "
+mtllib models.mtl
+o Wall
+v 0 0 -2
+v 1 0 -2
+v 0 1 -2
+f 1 2 3
+o Floor
+v 0 0 0
+v 1 0 0
+v 1 0 -1
+v 0 0 -1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 1 0
+usemtl Glass
+f 4/1/1 5/2/1 6/3/1 7/4/1
+";
+
+    const MATERIALS: &str = "
+newmtl Glass
+Kd 0.2 0.4 0.6
+Ks 0.5 0.5 0.5
+Ns 80
+Ni 1.5
+d 0.5
+
+newmtl Sky
+map_Kd sky.png
+";
+
+    fn parse(obj: &str, mtl: Option<&'static str>) -> ObjFile {
+        let (models, materials) =
+            tobj::load_obj_buf(&mut obj.as_bytes(), &LOAD_OPTIONS, |_| match mtl {
+                Some(mtl) => tobj::load_mtl_buf(&mut mtl.as_bytes()),
+                None => Err(tobj::LoadError::OpenFileFailed),
+            })
+            .unwrap();
+        ObjFile::new(
+            models,
+            materials,
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join(".."),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn every_model_is_loaded() {
+        let file = parse(TWO_MODELS, Some(MATERIALS));
+
+        let names: Vec<_> = file
+            .models
+            .iter()
+            .map(|model| model.name.as_str())
+            .collect();
+        assert_eq!(names, ["Wall", "Floor"]);
+        // the quad is split into two triangles
+        assert_eq!(file.models[0].mesh.triangles().len(), 1);
+        assert_eq!(file.models[1].mesh.triangles().len(), 2);
+    }
+
+    #[test]
+    fn texture_coords_and_normals_are_carried_into_the_triangles() {
+        let file = parse(TWO_MODELS, Some(MATERIALS));
+        let floor = &file.models[1].mesh;
+
+        let intersection = floor
+            .find_intersection(&Vec3::new(0.25, 1.0, -0.5), &Vec3::new(0.0, -1.0, 0.0))
+            .unwrap();
+
+        // `v` is flipped to grow down the image
+        assert_eq!(intersection.texture_coords, Some((0.25, 0.5)));
+        assert_eq!(intersection.tangent, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(intersection.bitangent, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(floor.texture_scale(), 1.0);
+        assert_eq!(
+            floor.triangles()[0].vertex_normals(),
+            Some([Vec3::new(0.0, 1.0, 0.0); 3])
+        );
+        // the wall has no normals in the file, they are generated from its face
+        assert_eq!(
+            file.models[0].mesh.triangles()[0].vertex_normals(),
+            Some([Vec3::new(0.0, 0.0, 1.0); 3])
+        );
+    }
+
+    #[test]
+    fn materials_come_from_the_material_library() {
+        let file = parse(TWO_MODELS, Some(MATERIALS));
+
+        let glass = file.material(&file.models[1]).unwrap().unwrap();
+
+        assert_eq!(
+            glass.diffuse_color.get(&TextureQuery::default()),
+            Color::new(0.2, 0.4, 0.6)
+        );
+        assert_eq!(glass.shininess, 80.0);
+        assert_eq!(glass.refractive_index, 1.5);
+        assert_eq!(
+            glass.albedo,
+            Albedo {
+                diffuse: 1.0,
+                specular: 0.5,
+                ..Albedo::default()
+            }
+        );
+        // a half dissolved surface lets half of the light straight through
+        assert_eq!(glass.opacity, 0.5);
+        assert!(file.material(&file.models[0]).unwrap().is_none());
+        // asking again hands out the same material
+        let again = file.material(&file.models[1]).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&glass.diffuse_color, &again.diffuse_color));
+    }
+
+    #[test]
+    fn missing_material_library_fails_only_when_asked_for_materials() {
+        let file = parse(TWO_MODELS, None);
+
+        assert_eq!(file.models.len(), 2);
+        assert!(matches!(
+            file.material(&file.models[1]),
+            Err(ObjError::Materials(_))
+        ));
+    }
+
+    #[test]
+    fn diffuse_textures_are_loaded_next_to_the_file() {
+        let sky = "mtllib sky.mtl\nusemtl Sky\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        let missing = "newmtl Sky\nmap_Kd missing.png\n";
+
+        let file = parse(sky, Some(MATERIALS));
+        let broken = parse(sky, Some(missing));
+
+        assert!(file.material(&file.models[0]).unwrap().is_some());
+        assert!(matches!(
+            broken.material(&broken.models[0]),
+            Err(ObjError::Texture(path, TextureError::Io(error)))
+                if path.ends_with("missing.png") && error.kind() == io::ErrorKind::NotFound
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+use std::f32::consts::PI;
+
+use super::material::{TextureQuery, ValueGenerator};
+use crate::color::Color;
+use crate::common::DEBUG_PINK;
+use crate::vector_math::Vec3;
+
+/// Coordinates a procedural texture is evaluated at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternSpace {
+    /// texture coordinates of the surface, with 0 as the third coordinate
+    Uv,
+    /// point on the surface, so that surfaces without texture coordinates can be textured as
+    /// well. Objects are placed without a transform of their own, so this is the space of the
+    /// scene and a pattern runs through all objects alike.
+    Object,
+}
+
+/// Field of values in 0..1 a procedural texture is made of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// gradient noise after Perlin, cells of one unit
+    Noise,
+    /// fractal Brownian motion, `octaves` layers of noise each twice as fine and half as strong
+    /// as the one before
+    Fbm { octaves: u32 },
+    /// like fBm, but adding up how far the noise strays from its middle, which gives creases
+    Turbulence { octaves: u32 },
+    /// veins one unit apart along x, bent by `distortion` times turbulence
+    Marble { octaves: u32, distortion: f32 },
+    /// rings one unit apart around the y axis, bent by `distortion` times noise
+    Wood { distortion: f32 },
+    /// distance to the closest of points scattered one per unit cube after Worley, dark in the
+    /// middle of each cell
+    Worley,
+    /// Voronoi cells around the same points, each with a random value of its own
+    Cells,
+    /// bands one unit apart across `axis`, `width` is the part of each taken by the first color
+    Stripes { axis: Vec3, width: f32 },
+    /// ramp along `axis` over one unit, starting at the origin
+    Gradient { axis: Vec3 },
+    /// cubes of one unit in alternating colors, squares when evaluated on texture coordinates
+    Checker,
+}
+
+/// Texture computed from a pattern instead of looked up in an image, blending between two
+/// colors by the value of the pattern.
+pub struct Procedural {
+    pub pattern: Pattern,
+    pub space: PatternSpace,
+    /// coordinates are multiplied by it first, larger values give finer patterns
+    pub scale: f32,
+    /// colors at the values 0 and 1 of the pattern
+    pub colors: (Color, Color),
+}
+
+impl Procedural {
+    /// Value of the pattern at the point looked up, `None` when the surface has no texture
+    /// coordinates but the pattern is evaluated on them.
+    pub fn value(&self, query: &TextureQuery) -> Option<f32> {
+        let point = match self.space {
+            PatternSpace::Uv => query.texture_coords.map(|(u, v)| Vec3::new(u, v, 0.0)),
+            PatternSpace::Object => query.position,
+        }?;
+        Some(self.pattern.value(point * self.scale))
+    }
+}
+
+impl ValueGenerator<Color> for Procedural {
+    fn get(&self, query: &TextureQuery) -> Color {
+        let Some(value) = self.value(query) else {
+            return DEBUG_PINK;
+        };
+        let (first, second) = self.colors;
+        first * (1.0 - value) + second * value
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.space == PatternSpace::Uv
+    }
+}
+
+// surfaces the pattern can't be evaluated on keep the parameter it scales as it is
+impl ValueGenerator<f32> for Procedural {
+    fn get(&self, query: &TextureQuery) -> f32 {
+        self.value(query).unwrap_or(1.0)
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.space == PatternSpace::Uv
+    }
+}
+
+impl Pattern {
+    pub fn value(&self, point: Vec3) -> f32 {
+        let value = match *self {
+            Pattern::Noise => 0.5 + 0.5 * noise(point),
+            Pattern::Fbm { octaves } => 0.5 + 0.5 * fractal(point, octaves, noise),
+            Pattern::Turbulence { octaves } => fractal(point, octaves, |point| noise(point).abs()),
+            Pattern::Marble {
+                octaves,
+                distortion,
+            } => {
+                let turbulence = fractal(point, octaves, |point| noise(point).abs());
+                0.5 + 0.5 * (2.0 * PI * (point[0] + distortion * turbulence)).sin()
+            }
+            Pattern::Wood { distortion } => {
+                let (x, _, z) = point.as_coords();
+                let rings = (x * x + z * z).sqrt() + distortion * noise(point);
+                rings - rings.floor()
+            }
+            Pattern::Worley => closest_feature(point).0,
+            Pattern::Cells => {
+                let (x, y, z) = closest_feature(point).1;
+                unit(hash(x, y, z))
+            }
+            Pattern::Stripes { axis, width } => {
+                let position = point * axis;
+                if position - position.floor() < width {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Pattern::Gradient { axis } => point * axis,
+            Pattern::Checker => {
+                let (x, y, z) = lattice_cell(point);
+                ((x + y + z) & 1) as f32
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+// layers of `layer` at growing frequencies, the amplitudes add up to 1
+fn fractal(point: Vec3, octaves: u32, layer: impl Fn(Vec3) -> f32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += layer(point * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total_amplitude
+}
+
+// directions to the middles of the edges of a cube, gradients drawn from them have no bias
+// towards the axes or the diagonals
+const GRADIENTS: [(f32, f32, f32); 12] = [
+    (1.0, 1.0, 0.0),
+    (-1.0, 1.0, 0.0),
+    (1.0, -1.0, 0.0),
+    (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0),
+    (-1.0, 0.0, 1.0),
+    (1.0, 0.0, -1.0),
+    (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0),
+    (0.0, -1.0, 1.0),
+    (0.0, 1.0, -1.0),
+    (0.0, -1.0, -1.0),
+];
+
+/// Improved Perlin noise, smooth and roughly in -1..1. It is 0 at whole coordinates and
+/// its features are about one unit across.
+pub fn noise(point: Vec3) -> f32 {
+    let (x, y, z) = lattice_cell(point);
+    let (fx, fy, fz) = (point - Vec3::new(x as f32, y as f32, z as f32)).as_coords();
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let (gx, gy, gz) = GRADIENTS[hash(x + dx, y + dy, z + dz) as usize % GRADIENTS.len()];
+        gx * (fx - dx as f32) + gy * (fy - dy as f32) + gz * (fz - dz as f32)
+    };
+    let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    )
+}
+
+// eases the blending between the corners so that the noise has no creases at the cell borders
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// distance to the closest feature point, clamped to 1, and the cell it lies in
+fn closest_feature(point: Vec3) -> (f32, (i32, i32, i32)) {
+    let (x, y, z) = lattice_cell(point);
+    let mut closest = (f32::INFINITY, (x, y, z));
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let cell = (x + dx, y + dy, z + dz);
+                let feature = feature_point(cell);
+                let distance = (feature - point).magnitude();
+                if distance < closest.0 {
+                    closest = (distance, cell);
+                }
+            }
+        }
+    }
+    (closest.0.min(1.0), closest.1)
+}
+
+// the point scattered into a lattice cell
+fn feature_point((x, y, z): (i32, i32, i32)) -> Vec3 {
+    let seed = hash(x, y, z);
+    Vec3::new(
+        x as f32 + unit(seed),
+        y as f32 + unit(mix(seed ^ 0x68e31da4)),
+        z as f32 + unit(mix(seed ^ 0xb5297a4d)),
+    )
+}
+
+fn lattice_cell(point: Vec3) -> (i32, i32, i32) {
+    let (x, y, z) = point.as_coords();
+    (x.floor() as i32, y.floor() as i32, z.floor() as i32)
+}
+
+// pseudo random number for a lattice cell, the same cell always gets the same one
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    mix((x as u32).wrapping_mul(0x8da6b343)
+        ^ (y as u32).wrapping_mul(0xd8163841)
+        ^ (z as u32).wrapping_mul(0xcb1ab31f))
+}
+
+fn mix(mut value: u32) -> u32 {
+    value ^= value >> 16;
+    value = value.wrapping_mul(0x7feb352d);
+    value ^= value >> 15;
+    value = value.wrapping_mul(0x846ca68b);
+    value ^ (value >> 16)
+}
+
+fn unit(value: u32) -> f32 {
+    (value >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    const PATTERNS: [Pattern; 10] = [
+        Pattern::Noise,
+        Pattern::Fbm { octaves: 4 },
+        Pattern::Turbulence { octaves: 4 },
+        Pattern::Marble {
+            octaves: 4,
+            distortion: 2.0,
+        },
+        Pattern::Wood { distortion: 0.5 },
+        Pattern::Worley,
+        Pattern::Cells,
+        Pattern::Stripes {
+            axis: Vec3::new(1.0, 0.0, 0.0),
+            width: 0.5,
+        },
+        Pattern::Gradient {
+            axis: Vec3::new(0.0, 1.0, 0.0),
+        },
+        Pattern::Checker,
+    ];
+
+    fn in_object_space(pattern: Pattern) -> Procedural {
+        Procedural {
+            pattern,
+            space: PatternSpace::Object,
+            scale: 1.0,
+            colors: (Color::BLACK, Color::WHITE),
+        }
+    }
+
+    fn at_position(position: Vec3) -> TextureQuery {
+        TextureQuery {
+            position: Some(position),
+            ..TextureQuery::default()
+        }
+    }
+
+    #[test]
+    fn noise_vanishes_on_the_lattice_and_is_smooth_in_between() {
+        let mut random = SmallRng::seed_from_u64(8);
+        for _ in 0..1000 {
+            let point = random_vec3(&mut random, 20.0);
+            let nearby = point + Vec3::new(1e-3, -1e-3, 1e-3);
+            let value = noise(point);
+
+            assert!(value.abs() <= 1.05, "{}", value);
+            assert!((value - noise(nearby)).abs() < 0.01);
+        }
+        assert_eq!(noise(Vec3::new(3.0, -2.0, 7.0)), 0.0);
+    }
+
+    #[test]
+    fn patterns_stay_between_the_two_colors() {
+        let mut random = SmallRng::seed_from_u64(9);
+        for pattern in PATTERNS {
+            let texture = in_object_space(pattern);
+            for _ in 0..200 {
+                let color: Color = texture.get(&at_position(random_vec3(&mut random, 10.0)));
+                let (r, g, b) = color.as_channels();
+
+                assert!((0.0..=1.0).contains(&r), "{:?}: {}", pattern, r);
+                assert_eq!((r, r), (g, b));
+            }
+        }
+    }
+
+    #[test]
+    fn noise_patterns_vary() {
+        let mut random = SmallRng::seed_from_u64(10);
+        for pattern in &PATTERNS[..7] {
+            let values: Vec<f32> = (0..200)
+                .map(|_| pattern.value(random_vec3(&mut random, 10.0)))
+                .collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            let spread = values.iter().map(|v| (v - mean).abs()).sum::<f32>() / 200.0;
+
+            assert!(spread > 0.03, "{:?}: {}", pattern, spread);
+        }
+    }
+
+    #[test]
+    fn stripe_width_is_the_share_of_the_first_color() {
+        let stripes = Pattern::Stripes {
+            axis: Vec3::new(0.0, 0.0, 2.0),
+            width: 0.25,
+        };
+        let mut random = SmallRng::seed_from_u64(11);
+        let first_color = (0..1000)
+            .filter(|_| stripes.value(random_vec3(&mut random, 10.0)) == 0.0)
+            .count();
+
+        assert!(
+            (first_color as f32 / 1000.0 - 0.25).abs() < 0.05,
+            "{}",
+            first_color
+        );
+    }
+
+    #[test]
+    fn cells_are_uniform_around_their_feature_point() {
+        let mut random = SmallRng::seed_from_u64(12);
+        for _ in 0..100 {
+            let point = random_vec3(&mut random, 10.0);
+            let (_, cell) = closest_feature(point);
+            // going towards the closest point gets nearer to it than to any other
+            let halfway = (point + feature_point(cell)) * 0.5;
+
+            assert_eq!(closest_feature(halfway).1, cell);
+            assert_eq!(Pattern::Cells.value(point), Pattern::Cells.value(halfway));
+        }
+    }
+
+    #[test]
+    fn pattern_space_picks_the_coordinates() {
+        let gradient = |space| Procedural {
+            space,
+            scale: 0.5,
+            ..in_object_space(Pattern::Gradient {
+                axis: Vec3::new(1.0, 0.0, 0.0),
+            })
+        };
+        let query = TextureQuery {
+            texture_coords: Some((0.8, 0.1)),
+            position: Some(Vec3::new(1.2, 5.0, 5.0)),
+            normal: None,
+            footprint: 0.0,
+        };
+
+        assert_eq!(gradient(PatternSpace::Uv).value(&query), Some(0.4));
+        assert_eq!(
+            gradient(PatternSpace::Object).value(&query).map(cap_float),
+            Some(0.6)
+        );
+        // without texture coordinates colors show up pink and parameters are left alone
+        let without_uv = at_position(Vec3::new(0.0, 0.0, 0.0));
+        let color: Color = gradient(PatternSpace::Uv).get(&without_uv);
+        let parameter: f32 = gradient(PatternSpace::Uv).get(&without_uv);
+        assert_eq!(color, DEBUG_PINK);
+        assert_eq!(parameter, 1.0);
+    }
+}
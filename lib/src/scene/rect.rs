@@ -1,7 +1,7 @@
 use super::triangle::Triangle;
-use super::Surface;
+use super::{Surface, SurfaceSample};
 
-use crate::ray_tracing::Intersection;
+use crate::ray_tracing::{Aabb, Intersection};
 use crate::vector_math::Vec3;
 
 #[derive(Debug, PartialEq)]
@@ -15,34 +15,58 @@ impl Surface for Rect {
             let [a, b, _, d] = self.as_vertices();
             let intersection_point = a + (d - a) * x + (b - a) * y;
             if (intersection_point - *ray_origin) * *ray_direction > 0.0 {
+                let normal = self.as_triangles()[0].normal();
                 return Some(Intersection {
                     distance: (intersection_point - *ray_origin).magnitude(),
-                    normal: self
-                        .as_triangles()
-                        .iter()
-                        .next()
-                        .map(|triangle| triangle.normal())
-                        .unwrap(),
+                    normal,
+                    shading_normal: normal,
                     texture_coords: Some((x, y)),
+                    tangent: (d - a).normalize(),
+                    bitangent: (b - a).normalize(),
                 });
             }
         }
         None
     }
 
+    fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.as_vertices())
+    }
+
+    fn texture_scale(&self) -> f32 {
+        let [a, b, _, d] = self.as_vertices();
+        1.0 / (d - a).magnitude().min((b - a).magnitude())
+    }
+
     fn approximate_outside(&self, point_on_surface: Vec3) -> Vec3 {
         point_on_surface + (self.as_triangles()[0].normal() * 1e-6)
     }
+
+    fn sample_surface(&self, (x, y): (f32, f32)) -> Option<SurfaceSample> {
+        let [a, b, _, d] = self.as_vertices();
+        Some(SurfaceSample {
+            point: a + (d - a) * x + (b - a) * y,
+            normal: self.as_triangles()[0].normal(),
+            area: (d - a).cross_product(&(b - a)).magnitude(),
+        })
+    }
 }
 
 impl Rect {
     pub fn new(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Rect {
+        Rect::try_new(a, b, c, d).expect("rectangle vertices must lie in one plane")
+    }
+
+    pub fn try_new(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Option<Rect> {
         let half = Triangle::new(a, c, b);
         let another = Triangle::new(c, a, d);
 
-        assert!((half.normal() - another.normal()).magnitude() < f32::EPSILON);
-        Rect {
-            triangles: vec![half, another],
+        if (half.normal() - another.normal()).magnitude() < f32::EPSILON {
+            Some(Rect {
+                triangles: vec![half, another],
+            })
+        } else {
+            None
         }
     }
 
@@ -105,7 +129,10 @@ mod test {
             Some(Intersection {
                 distance: 1.0,
                 normal: Vec3::new(0.0, -0.0, 1.0),
-                texture_coords: Some((0.5, 0.5))
+                shading_normal: Vec3::new(0.0, -0.0, 1.0),
+                texture_coords: Some((0.5, 0.5)),
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                bitangent: Vec3::new(0.0, 1.0, 0.0),
             }),
             intersection
         );
@@ -127,7 +154,10 @@ mod test {
             Some(Intersection {
                 distance: 1.0,
                 normal: Vec3::new(0.0, 0.0, 1.0),
-                texture_coords: Some((0.0, 0.5))
+                shading_normal: Vec3::new(0.0, 0.0, 1.0),
+                texture_coords: Some((0.0, 0.5)),
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                bitangent: Vec3::new(0.0, 1.0, 0.0),
             }),
             intersection
         );
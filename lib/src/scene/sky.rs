@@ -0,0 +1,357 @@
+use std::f32::consts::PI;
+
+use super::light::Light;
+use super::material::{TextureQuery, ValueGenerator};
+use crate::color::Color;
+use crate::common::DEBUG_PINK;
+use crate::vector_math::Vec3;
+
+// the model gives luminance in kcd/m², a clear sky around noon comes out at about 1 with this
+const LUMINANCE_SCALE: f32 = 0.1;
+// directions the sky is integrated over to find out how much light reaches the ground
+const GROUND_SAMPLES: (usize, usize) = (32, 8);
+// illuminance of the sun outside the atmosphere in klx, in the same units as the luminance
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+// wavelengths in µm the red, green and blue of the sunlight are attenuated at
+const SUN_WAVELENGTHS: [f32; 3] = [0.65, 0.55, 0.45];
+
+/// Analytic clear daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model
+/// for Daylight". It is looked up with the texture coordinates of the sky sphere like any
+/// other sky texture, so it lights the scene just the same.
+///
+/// The sun disc itself is left out of the texture, `sun` gives its light instead. Below the
+/// horizon lies a Lambertian ground of `ground_albedo` lit by the sky and the sun.
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    turbidity: f32,
+    // Perez coefficients of the luminance and the two chromaticity coordinates
+    luminance: Perez,
+    chromaticity_x: Perez,
+    chromaticity_y: Perez,
+    ground: Color,
+}
+
+// distribution of one quantity over the sky, scaled so that it matches its value at the zenith
+struct Perez {
+    coefficients: [f32; 5],
+    zenith: f32,
+    // the distribution at the zenith, which it is divided by
+    normalization: f32,
+}
+
+impl Perez {
+    fn new(coefficients: [f32; 5], zenith: f32, sun_zenith_angle: f32) -> Perez {
+        let mut perez = Perez {
+            coefficients,
+            zenith,
+            normalization: 1.0,
+        };
+        perez.normalization = perez.distribution(1.0, sun_zenith_angle);
+        perez
+    }
+
+    fn distribution(&self, cosine_zenith: f32, sun_angle: f32) -> f32 {
+        let [a, b, c, d, e] = self.coefficients;
+        let cosine_sun = sun_angle.cos();
+        (1.0 + a * (b / cosine_zenith.max(0.01)).exp())
+            * (1.0 + c * (d * sun_angle).exp() + e * cosine_sun * cosine_sun)
+    }
+
+    fn value(&self, cosine_zenith: f32, sun_angle: f32) -> f32 {
+        self.zenith * self.distribution(cosine_zenith, sun_angle) / self.normalization
+    }
+}
+
+impl PreethamSky {
+    /// `sun_direction` points towards the sun, `turbidity` is the haziness of the air from 2
+    /// for a very clear sky to 10 for a hazy one.
+    pub fn new(sun_direction: Vec3, turbidity: f32, ground_albedo: Color) -> PreethamSky {
+        let sun_direction = sun_direction.normalize();
+        let turbidity = turbidity.clamp(2.0, 10.0);
+        let t = turbidity;
+        // the model breaks down once the sun sets
+        let sun_zenith_angle = sun_direction.as_coords().1.clamp(0.0, 1.0).acos();
+        let s = sun_zenith_angle;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |[a, b, c, d]: [f32; 4]| ((a * s + b) * s + c) * s + d;
+        let zenith_x = t * t * polynomial([0.00166, -0.00375, 0.00209, 0.0])
+            + t * polynomial([-0.02903, 0.06377, -0.03202, 0.00394])
+            + polynomial([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = t * t * polynomial([0.00275, -0.00610, 0.00317, 0.0])
+            + t * polynomial([-0.04214, 0.08970, -0.04153, 0.00516])
+            + polynomial([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let linear = |coefficients: [(f32, f32); 5]| coefficients.map(|(a, b)| a * t + b);
+        let mut sky = PreethamSky {
+            sun_direction,
+            turbidity,
+            luminance: Perez::new(
+                linear([
+                    (0.1787, -1.4630),
+                    (-0.3554, 0.4275),
+                    (-0.0227, 5.3251),
+                    (0.1206, -2.5771),
+                    (-0.0670, 0.3703),
+                ]),
+                zenith_luminance.max(0.0),
+                s,
+            ),
+            chromaticity_x: Perez::new(
+                linear([
+                    (-0.0193, -0.2592),
+                    (-0.0665, 0.0008),
+                    (-0.0004, 0.2125),
+                    (-0.0641, -0.8989),
+                    (-0.0033, 0.0452),
+                ]),
+                zenith_x,
+                s,
+            ),
+            chromaticity_y: Perez::new(
+                linear([
+                    (-0.0167, -0.2608),
+                    (-0.0950, 0.0092),
+                    (-0.0079, 0.2102),
+                    (-0.0441, -1.6537),
+                    (-0.0109, 0.0529),
+                ]),
+                zenith_y,
+                s,
+            ),
+            ground: Color::BLACK,
+        };
+        // the ground is lit by the whole sky and, while it is up, by the sun
+        let mut irradiance = Color::WHITE * (sky.horizontal_irradiance() / PI);
+        if let Some(sun) = sky.sun() {
+            irradiance += sun.color * (sun.intensity * sun_direction.as_coords().1);
+        }
+        sky.ground = ground_albedo * irradiance;
+        sky
+    }
+
+    /// The sun as a directional light, dimmed and reddened by the air it shines through on its
+    /// way down as in the appendix of the paper. `None` once it has set.
+    pub fn sun(&self) -> Option<Light> {
+        let cosine_zenith = self.sun_direction.as_coords().1;
+        if cosine_zenith <= 0.0 {
+            return None;
+        }
+        // how much more air the light crosses than straight down, after Kasten
+        let zenith_angle = cosine_zenith.acos().to_degrees();
+        let air_mass = 1.0 / (cosine_zenith + 0.15 * (93.885 - zenith_angle).powf(-1.253));
+        // Ångström's coefficient of the haze
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let [r, g, b] = SUN_WAVELENGTHS.map(|wavelength: f32| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosols = beta * wavelength.powf(-1.3);
+            (-(rayleigh + aerosols) * air_mass).exp()
+        });
+
+        // the lights' irradiance is a 1 / π larger than the illuminance of the sky's radiance
+        let mut sun = Light::directional(
+            -self.sun_direction,
+            SOLAR_ILLUMINANCE * LUMINANCE_SCALE / PI,
+        );
+        sun.color = Color::new(r, g, b);
+        Some(sun)
+    }
+
+    // radiance of the sky above the horizon
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let cosine_zenith = direction.as_coords().1;
+        let sun_angle = (*direction * self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.luminance.value(cosine_zenith, sun_angle) * LUMINANCE_SCALE;
+        let x = self.chromaticity_x.value(cosine_zenith, sun_angle);
+        let y = self.chromaticity_y.value(cosine_zenith, sun_angle);
+        if luminance <= 0.0 || y <= 0.0 {
+            return Color::BLACK;
+        }
+        xyz_to_linear_srgb(x / y * luminance, luminance, (1.0 - x - y) / y * luminance)
+    }
+
+    // light arriving at the ground from the whole sky
+    fn horizontal_irradiance(&self) -> f32 {
+        let (columns, rows) = GROUND_SAMPLES;
+        let mut irradiance = 0.0;
+        for row in 0..rows {
+            // heights are spread evenly, so every direction stands for the same solid angle
+            let height = (row as f32 + 0.5) / rows as f32;
+            for column in 0..columns {
+                let u = (column as f32 + 0.5) / columns as f32;
+                let direction = sky_direction((u, (1.0 - height) / 2.0));
+                irradiance += self.sky_radiance(&direction).luminance() * height;
+            }
+        }
+        irradiance * 2.0 * PI / (rows * columns) as f32
+    }
+}
+
+impl ValueGenerator<Color> for PreethamSky {
+    fn get(&self, query: &TextureQuery) -> Color {
+        let Some(texture_coords) = query.texture_coords else {
+            return DEBUG_PINK;
+        };
+        let direction = sky_direction(texture_coords);
+        if direction.as_coords().1 < 0.0 {
+            return self.ground;
+        }
+        self.sky_radiance(&direction)
+    }
+}
+
+fn xyz_to_linear_srgb(x: f32, y: f32, z: f32) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+    .map(|channel| channel.max(0.0))
+}
+
+/// Unit vector towards the part of the sky seen at `texture_coords`, the inverse of the
+/// texture coordinates of a sphere around the origin. `v` goes linearly with the height of
+/// the direction, so every part of a sky texture covers the same solid angle.
+pub(crate) fn sky_direction((u, v): (f32, f32)) -> Vec3 {
+    let height = 1.0 - 2.0 * v;
+    let ring_radius = (1.0 - height * height).max(0.0).sqrt();
+    let angle = 2.0 * PI * u;
+    Vec3::new(ring_radius * angle.cos(), height, ring_radius * angle.sin())
+}
+
+/// Texture coordinates of the part of the sky seen towards the unit vector `direction`, the
+/// inverse of `sky_direction`.
+pub(crate) fn sky_texture_coords(direction: &Vec3) -> (f32, f32) {
+    let (x, height, z) = direction.as_coords();
+    let angle = z.atan2(x);
+    let u = if angle < 0.0 {
+        angle / (2.0 * PI) + 1.0
+    } else {
+        angle / (2.0 * PI)
+    };
+    (u.min(1.0), ((1.0 - height) / 2.0).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use super::*;
+    use crate::common::test::*;
+    use crate::scene::sphere::Sphere;
+    use crate::scene::Surface;
+
+    fn radiance(sky: &PreethamSky, direction: Vec3) -> Color {
+        sky.sky_radiance(&direction.normalize())
+    }
+
+    #[test]
+    fn directions_match_the_sky_texture_coordinates() {
+        let sky = Sphere {
+            origin: Vec3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        for (u, v) in [(0.1, 0.3), (0.6, 0.5), (0.9, 0.8)] {
+            let texture_coords = sky
+                .find_intersection(&Vec3::new(0.0, 0.0, 0.0), &sky_direction((u, v)))
+                .and_then(|intersection| intersection.texture_coords)
+                .unwrap();
+
+            assert_eq!(
+                (cap_float(texture_coords.0), cap_float(texture_coords.1)),
+                (u, v)
+            );
+            let (inverse_u, inverse_v) = sky_texture_coords(&sky_direction((u, v)));
+            assert!((inverse_u - u).abs() < 1e-5 && (inverse_v - v).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn zenith_matches_the_zenith_values() {
+        let sky = PreethamSky::new(Vec3::new(1.0, 1.0, 0.0), 3.0, Color::BLACK);
+
+        let zenith = radiance(&sky, Vec3::new(0.0, 1.0, 0.0));
+
+        assert_eq!(
+            cap_float(zenith.luminance()),
+            cap_float(sky.luminance.zenith * LUMINANCE_SCALE)
+        );
+    }
+
+    #[test]
+    fn sky_is_brightest_around_the_sun_and_blue_away_from_it() {
+        let sky = PreethamSky::new(Vec3::new(1.0, 1.0, 0.0), 3.0, Color::BLACK);
+
+        let near_sun = radiance(&sky, Vec3::new(1.0, 0.9, 0.1));
+        let opposite = radiance(&sky, Vec3::new(-1.0, 1.0, 0.0));
+        let (r, _, b) = opposite.as_channels();
+
+        assert!(near_sun.luminance() > 3.0 * opposite.luminance());
+        assert!(b > r, "{:?}", opposite);
+    }
+
+    #[test]
+    fn sun_is_dimmed_and_reddened_towards_the_horizon() {
+        let sunlight = |sun_direction| {
+            let sun = PreethamSky::new(sun_direction, 3.0, Color::BLACK)
+                .sun()
+                .unwrap();
+            sun.color * sun.intensity
+        };
+
+        let noon = sunlight(Vec3::new(0.0, 1.0, 0.0));
+        let evening = sunlight(Vec3::new(1.0, 0.1, 0.0));
+        let (r, g, b) = evening.as_channels();
+
+        // the sun outshines a clear sky
+        let sky = PreethamSky::new(Vec3::new(0.0, 1.0, 0.0), 3.0, Color::BLACK);
+        assert!(noon.luminance() > 2.0 * sky.horizontal_irradiance() / PI);
+        assert!(evening.luminance() < 0.5 * noon.luminance());
+        assert!(r > g && g > b, "{:?}", evening);
+        assert!(
+            PreethamSky::new(Vec3::new(1.0, -0.1, 0.0), 3.0, Color::BLACK)
+                .sun()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn haze_washes_out_the_blue() {
+        let blueness = |turbidity| {
+            let sky = PreethamSky::new(Vec3::new(1.0, 1.0, 0.0), turbidity, Color::BLACK);
+            let (r, _, b) = radiance(&sky, Vec3::new(-1.0, 1.0, 0.0)).as_channels();
+            b / r
+        };
+
+        assert!(blueness(2.0) > blueness(8.0));
+    }
+
+    #[test]
+    fn ground_reflects_the_sky_and_the_sun_by_its_albedo() {
+        let sun = Vec3::new(0.0, 1.0, 1.0);
+        let below_horizon = TextureQuery::at(Some((0.3, 0.8)));
+
+        let black = PreethamSky::new(sun, 3.0, Color::BLACK).get(&below_horizon);
+        let grey = PreethamSky::new(sun, 3.0, Color::new(0.2, 0.2, 0.2)).get(&below_horizon);
+        let white = PreethamSky::new(sun, 3.0, Color::WHITE).get(&below_horizon);
+
+        assert_eq!(black, Color::BLACK);
+        assert_eq!(
+            cap_float(white.luminance() * 0.2),
+            cap_float(grey.luminance())
+        );
+        // a white ground reflects all of the light of the sky and of the sun coming in at 45°
+        let sky = PreethamSky::new(sun, 3.0, Color::WHITE);
+        let sunlight = sky.sun().unwrap();
+        let sky_share = sky.horizontal_irradiance() / PI;
+        let sun_share = (sunlight.color * sunlight.intensity).luminance() * FRAC_1_SQRT_2;
+        assert_eq!(
+            cap_float(white.luminance()),
+            cap_float(sky_share + sun_share)
+        );
+        // most of it comes from the sun while it is high up
+        assert!(sun_share > sky_share);
+    }
+}
@@ -2,8 +2,9 @@ use core::f32;
 
 use crate::vector_math::Vec3;
 
-use super::Surface;
-use crate::ray_tracing::Intersection;
+use super::{area_to_solid_angle, SolidAngleSample, Surface, SurfaceSample};
+use crate::ray_tracing::{Aabb, Intersection};
+use crate::sampling::uniform_sphere;
 
 #[derive(Debug, PartialEq)]
 pub struct Sphere {
@@ -28,11 +29,44 @@ fn get_texture_coords(origin: &Vec3, point_on_sphere: &Vec3) -> (f32, f32) {
     };
 
     const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
-    let y = (origin_to_point * UP * -1.0 + 1.0) / 2.0; //mapping to 0..1 from 1..-1
+    let y = (-(origin_to_point * UP) + 1.0) / 2.0; //mapping to 0..1 from 1..-1
     (x, y)
 }
 
-impl Sphere {}
+// along the parallel towards growing longitude and down the meridian
+fn get_tangents(origin: &Vec3, point_on_sphere: &Vec3) -> (Vec3, Vec3) {
+    let normal = (*point_on_sphere - *origin).normalize();
+    let (x, _, z) = normal.as_coords();
+    let tangent = if x == 0.0 && z == 0.0 {
+        // every direction is along a parallel at the poles
+        normal.perpendiculars().0
+    } else {
+        Vec3::new(-z, 0.0, x).normalize()
+    };
+    (tangent, normal.cross_product(&tangent))
+}
+
+impl Sphere {
+    // cone of directions the sphere covers seen from `point` outside of it: its axis towards
+    // the center, the cosine of its half angle and the solid angle it spans
+    fn visible_cone(&self, point: &Vec3) -> Option<(Vec3, f32, f32)> {
+        let to_center = self.origin - *point;
+        let distance_squared = to_center * to_center;
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        let cosine = (1.0 - radius_squared / distance_squared).sqrt();
+        // 1 - cosine without losing the precision of small, distant spheres
+        let solid_angle =
+            2.0 * f32::consts::PI * (radius_squared / distance_squared) / (1.0 + cosine);
+        Some((
+            to_center * (1.0 / distance_squared.sqrt()),
+            cosine,
+            solid_angle,
+        ))
+    }
+}
 
 impl Surface for Sphere {
     fn find_intersection(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<Intersection> {
@@ -46,24 +80,44 @@ impl Surface for Sphere {
             if distance_to_intersection_with_ray - delta >= 0.0 {
                 let distance = distance_to_intersection_with_ray - delta;
                 let point_on_sphere = *ray_origin + (ray_direction * distance);
+                let (tangent, bitangent) = get_tangents(&self.origin, &point_on_sphere);
+                let normal = point_on_sphere - self.origin;
                 return Some(Intersection {
                     distance,
-                    normal: point_on_sphere - self.origin,
+                    normal,
+                    shading_normal: normal,
                     texture_coords: Some(get_texture_coords(&self.origin, &point_on_sphere)),
+                    tangent,
+                    bitangent,
                 });
             } else if distance_to_intersection_with_ray + delta >= 0.0 {
                 let distance = distance_to_intersection_with_ray + delta;
                 let point_on_sphere = *ray_origin + (ray_direction * distance);
+                let (tangent, bitangent) = get_tangents(&self.origin, &point_on_sphere);
+                let normal = point_on_sphere - self.origin;
                 return Some(Intersection {
                     distance,
-                    normal: point_on_sphere - self.origin,
+                    normal,
+                    shading_normal: normal,
                     texture_coords: Some(get_texture_coords(&self.origin, &point_on_sphere)),
+                    tangent,
+                    bitangent,
                 });
             }
         }
         Option::None
     }
 
+    fn texture_scale(&self) -> f32 {
+        // from pole to pole
+        1.0 / (std::f32::consts::PI * self.radius)
+    }
+
+    fn aabb(&self) -> Aabb {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.origin - radius, self.origin + radius)
+    }
+
     fn approximate_inside(&self, point_on_surface: Vec3) -> Vec3 {
         let origin_to_point = point_on_surface - self.origin;
 
@@ -77,6 +131,64 @@ impl Surface for Sphere {
         let next_up = f32::from_bits(self.radius.to_bits() + LAST_PLACE_UNIT_ERROR_MARGIN);
         self.origin + (origin_to_point.normalize() * next_up)
     }
+
+    // only the cap facing `point` can be seen from it, directions are spread uniformly over the
+    // cone it spans
+    fn sample_seen_from(&self, point: &Vec3, (u, v): (f32, f32)) -> Option<SolidAngleSample> {
+        let Some((axis, max_cosine, solid_angle)) = self.visible_cone(point) else {
+            return Some(self.sample_surface((u, v))?.seen_from(point));
+        };
+        let cosine = 1.0 - u * (1.0 - max_cosine);
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let angle = 2.0 * f32::consts::PI * v;
+        let (tangent, bitangent) = axis.perpendiculars();
+        let direction =
+            tangent * (sine * angle.cos()) + bitangent * (sine * angle.sin()) + axis * cosine;
+
+        // the nearer of the two points the direction crosses the sphere at
+        let to_center = self.origin - *point;
+        let along = to_center * direction;
+        let off_axis_squared = to_center * to_center - along * along;
+        let distance = along
+            - (self.radius * self.radius - off_axis_squared)
+                .max(0.0)
+                .sqrt();
+        let point_on_sphere = *point + direction * distance;
+        Some(SolidAngleSample {
+            point: point_on_sphere,
+            normal: (point_on_sphere - self.origin) * (1.0 / self.radius),
+            density: 1.0 / solid_angle,
+        })
+    }
+
+    fn density_seen_from(&self, point: &Vec3, direction: &Vec3) -> f32 {
+        let Some((axis, max_cosine, solid_angle)) = self.visible_cone(point) else {
+            // inside the sphere all of it is seen, it is sampled uniformly
+            return self
+                .find_intersection(point, direction)
+                .map_or(0.0, |intersection| {
+                    area_to_solid_angle(
+                        intersection.distance,
+                        intersection.normal.normalize() * direction.normalize(),
+                        4.0 * f32::consts::PI * self.radius * self.radius,
+                    )
+                });
+        };
+        if direction.normalize() * axis >= max_cosine {
+            1.0 / solid_angle
+        } else {
+            0.0
+        }
+    }
+
+    fn sample_surface(&self, sample: (f32, f32)) -> Option<SurfaceSample> {
+        let normal = uniform_sphere(sample);
+        Some(SurfaceSample {
+            point: self.origin + normal * self.radius,
+            normal,
+            area: 4.0 * f32::consts::PI * self.radius * self.radius,
+        })
+    }
 }
 
 const LAST_PLACE_UNIT_ERROR_MARGIN: u32 = 8;
@@ -126,6 +238,54 @@ mod test {
         assert_eq!(distance, 1.0);
     }
 
+    #[test]
+    fn surface_samples_lie_on_sphere() {
+        let sphere = Sphere {
+            origin: Vec3::new(1.0, 2.0, 3.0),
+            radius: 2.0,
+        };
+        let mut random = SmallRng::seed_from_u64(1);
+
+        for _ in 0..100 {
+            let sample = sphere
+                .sample_surface((random.gen::<f32>(), random.gen::<f32>()))
+                .unwrap();
+            assert_eq!(cap_float((sample.point - sphere.origin).magnitude()), 2.0);
+            assert_eq!(
+                cap_float(sample.normal * (sample.point - sphere.origin)),
+                2.0
+            );
+        }
+    }
+
+    #[test]
+    fn samples_seen_from_outside_lie_on_the_visible_cap() {
+        let sphere = Sphere {
+            origin: Vec3::new(0.0, 4.0, 0.0),
+            radius: 2.0,
+        };
+        let point = Vec3::new(0.0, 0.0, 0.0);
+        // a cone of half angle 30 degrees
+        let solid_angle = 2.0 * f32::consts::PI * (1.0 - 0.75f32.sqrt());
+        let mut random = SmallRng::seed_from_u64(2);
+
+        for _ in 0..100 {
+            let sample = sphere
+                .sample_seen_from(&point, (random.gen::<f32>(), random.gen::<f32>()))
+                .unwrap();
+            let direction = (sample.point - point).normalize();
+
+            assert_eq!(cap_float((sample.point - sphere.origin).magnitude()), 2.0);
+            assert!(sample.normal * direction <= 1e-3, "{:?}", sample.normal);
+            assert!((sample.density * solid_angle - 1.0).abs() < 1e-3);
+            assert_eq!(sphere.density_seen_from(&point, &direction), sample.density);
+        }
+        assert_eq!(
+            sphere.density_seen_from(&point, &Vec3::new(1.0, 1.0, 0.0)),
+            0.0
+        );
+    }
+
     const ORIGIN: Vec3 = Vec3::new(0.0, 0.0, 0.0);
 
     #[test]
@@ -135,6 +295,21 @@ mod test {
         assert_eq!((0.0, 0.0), coords);
     }
 
+    #[test]
+    fn tangents_point_where_texture_coords_grow() {
+        let point = Vec3::new(0.6, 0.0, 0.8);
+        let (tangent, bitangent) = get_tangents(&ORIGIN, &point);
+        let (u, v) = get_texture_coords(&ORIGIN, &point);
+
+        let (u_along_tangent, v_along_tangent) =
+            get_texture_coords(&ORIGIN, &(point + tangent * 0.01));
+        let (u_along_bitangent, v_along_bitangent) =
+            get_texture_coords(&ORIGIN, &(point + bitangent * 0.01));
+
+        assert!(u_along_tangent > u && cap_float(v_along_tangent) == cap_float(v));
+        assert!(v_along_bitangent > v && cap_float(u_along_bitangent) == cap_float(u));
+    }
+
     #[test]
     fn texture_coord_even_gradient() {
         let (x, y) = get_texture_coords(&ORIGIN, &Vec3::new(0.7, 0.0, 0.7));
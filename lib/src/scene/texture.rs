@@ -0,0 +1,630 @@
+use std::{fmt, fs, io, path::Path};
+
+use image::error::{ImageFormatHint, UnsupportedErrorKind};
+use image::{DynamicImage, ImageError};
+
+use super::material::ValueGenerator;
+use crate::buffer::{Buffer, Dimensions, Point};
+use crate::color::{srgb_to_linear, Color};
+use crate::common::DEBUG_PINK;
+use crate::vector_math::Vec3;
+
+/// Where a texture is looked up and how much of it the lookup covers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextureQuery {
+    pub texture_coords: Option<(f32, f32)>,
+    /// point on the surface in the scene, for textures that don't need texture coordinates
+    pub position: Option<Vec3>,
+    /// normal of the surface at `position`, before any normal map bends it
+    pub normal: Option<Vec3>,
+    /// width of the area seen through a pixel in texture coordinates, 0 for a point lookup
+    pub footprint: f32,
+}
+
+impl TextureQuery {
+    pub fn at(texture_coords: Option<(f32, f32)>) -> TextureQuery {
+        TextureQuery {
+            texture_coords,
+            position: None,
+            normal: None,
+            footprint: 0.0,
+        }
+    }
+}
+
+pub struct SolidColor(pub Color);
+impl ValueGenerator<Color> for SolidColor {
+    fn get(&self, _: &TextureQuery) -> Color {
+        self.0
+    }
+}
+
+/// Squares of two colors over the texture coordinates, `rows` of them along each side.
+pub struct CheckerBoard {
+    pub light: Color,
+    pub dark: Color,
+    pub rows: u32,
+}
+
+impl CheckerBoard {
+    pub fn new(light: Color, dark: Color) -> CheckerBoard {
+        CheckerBoard {
+            light,
+            dark,
+            rows: 8,
+        }
+    }
+}
+
+impl ValueGenerator<Color> for CheckerBoard {
+    fn get(&self, query: &TextureQuery) -> Color {
+        let light_color = &self.light;
+        let dark_color = &self.dark;
+        let rows = self.rows.max(1) as f32;
+        if let Some((x, y)) = query.texture_coords {
+            let x = f32::min(1.0 - f32::EPSILON, x);
+            let y = f32::min(1.0 - f32::EPSILON, y);
+
+            let x = (x * rows) as u32;
+            let y = (y * rows) as u32;
+
+            let color = if (x + y).is_multiple_of(2) {
+                *light_color
+            } else {
+                *dark_color
+            };
+            // once a pixel covers several squares all that's left to see is their average
+            let blur = (query.footprint * rows - 0.5).clamp(0.0, 1.0);
+            return color * (1.0 - blur) + (*light_color + *dark_color) * (blur / 2.0);
+        }
+        DEBUG_PINK
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        true
+    }
+}
+
+/// Which part of a color a scalar is read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+/// One channel of a color texture as a scalar, for parameter maps packed into the channels of
+/// an image such as the roughness in green and the metalness in blue of glTF.
+pub struct ChannelTexture {
+    pub texture: Box<dyn ValueGenerator<Color> + Send + Sync>,
+    pub channel: Channel,
+}
+
+// surfaces without the texture coordinates the texture needs keep the parameter the channel
+// scales as it is, like procedural patterns do
+impl ValueGenerator<f32> for ChannelTexture {
+    fn get(&self, query: &TextureQuery) -> f32 {
+        if query.texture_coords.is_none() && self.texture.uses_texture_coords() {
+            return 1.0;
+        }
+        let color = self.texture.get(query);
+        let (r, g, b) = color.as_channels();
+        match self.channel {
+            Channel::Red => r,
+            Channel::Green => g,
+            Channel::Blue => b,
+            Channel::Luminance => color.luminance(),
+        }
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        self.texture.uses_texture_coords()
+    }
+}
+
+/// How texels are combined into the color at a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    /// blends the four closest texels of the mip level matching the footprint
+    Bilinear,
+    /// blends between the two mip levels closest to the footprint as well
+    Trilinear,
+}
+
+/// What lies outside of 0..1 texture coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    Clamp,
+}
+
+/// Scale and offset applied to the texture coordinates before the lookup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvTransform {
+    pub scale: (f32, f32),
+    pub offset: (f32, f32),
+}
+
+impl Default for UvTransform {
+    fn default() -> UvTransform {
+        UvTransform {
+            scale: (1.0, 1.0),
+            offset: (0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    /// the file is none of the image formats that can be read
+    UnsupportedFormat(String),
+    /// the file looks like a known format but can not be decoded
+    Malformed(String),
+}
+
+impl From<ImageError> for TextureError {
+    fn from(error: ImageError) -> TextureError {
+        match error {
+            ImageError::Unsupported(error) => match error.kind() {
+                UnsupportedErrorKind::Format(ImageFormatHint::Unknown) => {
+                    TextureError::UnsupportedFormat("unknown image format".to_string())
+                }
+                _ => TextureError::UnsupportedFormat(error.to_string()),
+            },
+            // images are decoded from memory, running out of bytes means the file is cut short
+            error => TextureError::Malformed(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io(error) => write!(f, "{}", error),
+            TextureError::UnsupportedFormat(message) => write!(f, "unsupported image: {}", message),
+            TextureError::Malformed(message) => write!(f, "malformed image: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub struct ImageTexture {
+    // full resolution first, every level half the size of the one before down to a single texel
+    levels: Vec<Buffer>,
+    pub filter: TextureFilter,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub transform: UvTransform,
+}
+
+impl ImageTexture {
+    /// Reads a PNG, JPEG, Radiance HDR or OpenEXR image, the format is told by the contents
+    /// of the file rather than its extension.
+    pub fn load(file_path: impl AsRef<Path>) -> Result<ImageTexture, TextureError> {
+        let bytes = fs::read(file_path).map_err(TextureError::Io)?;
+        ImageTexture::decode(&bytes)
+    }
+
+    /// Like `load` for images that hold data rather than colors, such as normal maps, whose
+    /// values are used as they are stored.
+    pub fn load_linear(file_path: impl AsRef<Path>) -> Result<ImageTexture, TextureError> {
+        let bytes = fs::read(file_path).map_err(TextureError::Io)?;
+        ImageTexture::decode_linear(&bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<ImageTexture, TextureError> {
+        ImageTexture::decode_with(bytes, srgb_to_linear)
+    }
+
+    pub fn decode_linear(bytes: &[u8]) -> Result<ImageTexture, TextureError> {
+        ImageTexture::decode_with(bytes, |value| value)
+    }
+
+    // `to_linear` maps the 0..1 values of integer formats
+    fn decode_with(
+        bytes: &[u8],
+        to_linear: impl Fn(f32) -> f32,
+    ) -> Result<ImageTexture, TextureError> {
+        let image = image::load_from_memory(bytes).map_err(TextureError::from)?;
+        let mut buffer = Buffer::new(Dimensions(image.width(), image.height()), 3);
+
+        match image {
+            // floating point formats hold linear values already
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                for (idx, value) in image.to_rgb32f().into_raw().into_iter().enumerate() {
+                    buffer.set_raw_value(idx, value);
+                }
+            }
+            // everything else is usually sRGB encoded, whatever its channels and bit depth, and
+            // shading has to happen on linear values. Alpha is dropped.
+            _ => {
+                for (idx, value) in image.to_rgb16().into_raw().into_iter().enumerate() {
+                    buffer.set_raw_value(idx, to_linear(value as f32 / u16::MAX as f32));
+                }
+            }
+        }
+
+        Ok(ImageTexture::from_buffer(buffer))
+    }
+
+    /// Texture of linear colors, the mip levels are generated right away.
+    pub fn from_buffer(image: Buffer) -> ImageTexture {
+        ImageTexture {
+            levels: mipmaps(image),
+            filter: TextureFilter::Trilinear,
+            wrap_u: WrapMode::Repeat,
+            wrap_v: WrapMode::Repeat,
+            transform: UvTransform::default(),
+        }
+    }
+
+    // mip level with texels about as large as the footprint, fractional in between two levels
+    fn level_of_detail(&self, footprint: f32) -> f32 {
+        let full_resolution = &self.levels[0];
+        let texels = footprint * *full_resolution.width().max(full_resolution.height()) as f32;
+        if texels <= 1.0 {
+            return 0.0;
+        }
+        texels.log2().min((self.levels.len() - 1) as f32)
+    }
+
+    fn texel(&self, level: &Buffer, x: i64, y: i64) -> Color {
+        level.get(&Point(
+            wrap(x, *level.width(), self.wrap_u),
+            wrap(y, *level.height(), self.wrap_v),
+        ))
+    }
+
+    fn nearest(&self, level: &Buffer, (u, v): (f32, f32)) -> Color {
+        let x = (u * *level.width() as f32).floor() as i64;
+        let y = (v * *level.height() as f32).floor() as i64;
+        self.texel(level, x, y)
+    }
+
+    fn bilinear(&self, level: &Buffer, (u, v): (f32, f32)) -> Color {
+        // texel centers sit half a texel in from the edges
+        let x = u * *level.width() as f32 - 0.5;
+        let y = v * *level.height() as f32 - 0.5;
+        let (left, top) = (x.floor(), y.floor());
+        let (tx, ty) = (x - left, y - top);
+        let (left, top) = (left as i64, top as i64);
+
+        let upper =
+            self.texel(level, left, top) * (1.0 - tx) + self.texel(level, left + 1, top) * tx;
+        let lower = self.texel(level, left, top + 1) * (1.0 - tx)
+            + self.texel(level, left + 1, top + 1) * tx;
+        upper * (1.0 - ty) + lower * ty
+    }
+}
+
+impl ValueGenerator<Color> for ImageTexture {
+    fn get(&self, query: &TextureQuery) -> Color {
+        let Some((u, v)) = query.texture_coords else {
+            return DEBUG_PINK;
+        };
+        let UvTransform {
+            scale: (scale_u, scale_v),
+            offset: (offset_u, offset_v),
+        } = self.transform;
+        let coords = (u * scale_u + offset_u, v * scale_v + offset_v);
+        let footprint = query.footprint * scale_u.abs().max(scale_v.abs());
+
+        match self.filter {
+            TextureFilter::Nearest => self.nearest(&self.levels[0], coords),
+            TextureFilter::Bilinear => {
+                let level = self.level_of_detail(footprint).round() as usize;
+                self.bilinear(&self.levels[level], coords)
+            }
+            TextureFilter::Trilinear => {
+                let level_of_detail = self.level_of_detail(footprint);
+                let finer = level_of_detail.floor() as usize;
+                let coarser = (finer + 1).min(self.levels.len() - 1);
+                let t = level_of_detail - finer as f32;
+                self.bilinear(&self.levels[finer], coords) * (1.0 - t)
+                    + self.bilinear(&self.levels[coarser], coords) * t
+            }
+        }
+    }
+
+    fn uses_texture_coords(&self) -> bool {
+        true
+    }
+}
+
+fn wrap(coordinate: i64, size: u32, mode: WrapMode) -> u32 {
+    let size = size as i64;
+    let wrapped = match mode {
+        WrapMode::Repeat => coordinate.rem_euclid(size),
+        WrapMode::MirroredRepeat => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size {
+                period
+            } else {
+                2 * size - 1 - period
+            }
+        }
+        WrapMode::Clamp => coordinate.clamp(0, size - 1),
+    };
+    wrapped as u32
+}
+
+// box filtered levels, odd sizes round down and the last row or column is folded into the one
+// before it
+fn mipmaps(image: Buffer) -> Vec<Buffer> {
+    let mut levels = vec![image];
+    loop {
+        let previous = levels
+            .last()
+            .expect("there is always the full resolution level");
+        let (width, height) = (*previous.width(), *previous.height());
+        if width <= 1 && height <= 1 {
+            return levels;
+        }
+
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut next = Buffer::new(Dimensions(next_width, next_height), 3);
+        for y in 0..next_height {
+            let rows = (y * 2)..if y + 1 == next_height {
+                height
+            } else {
+                y * 2 + 2
+            };
+            for x in 0..next_width {
+                let columns = (x * 2)..if x + 1 == next_width {
+                    width
+                } else {
+                    x * 2 + 2
+                };
+                let mut sum = Color::BLACK;
+                let mut count = 0;
+                for source_y in rows.clone() {
+                    for source_x in columns.clone() {
+                        sum += previous.get(&Point(source_x, source_y));
+                        count += 1;
+                    }
+                }
+                next.set(&Point(x, y), &(sum * (1.0 / count as f32)));
+            }
+        }
+        levels.push(next);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test::*;
+
+    fn texture(width: u32, height: u32, texels: &[f32]) -> ImageTexture {
+        let mut buffer = Buffer::new(Dimensions(width, height), 3);
+        for (idx, value) in texels.iter().enumerate() {
+            let point = Point(idx as u32 % width, idx as u32 / width);
+            buffer.set(&point, &Color::new(*value, *value, *value));
+        }
+        ImageTexture::from_buffer(buffer)
+    }
+
+    fn brightness(texture: &ImageTexture, texture_coords: (f32, f32), footprint: f32) -> f32 {
+        let (r, _, _) = texture
+            .get(&TextureQuery {
+                texture_coords: Some(texture_coords),
+                footprint,
+                ..TextureQuery::default()
+            })
+            .as_channels();
+        cap_float(r)
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!(wrap(5, 4, WrapMode::Repeat), 1);
+        assert_eq!(wrap(-1, 4, WrapMode::Repeat), 3);
+        assert_eq!(wrap(5, 4, WrapMode::MirroredRepeat), 2);
+        assert_eq!(wrap(-1, 4, WrapMode::MirroredRepeat), 0);
+        assert_eq!(wrap(5, 4, WrapMode::Clamp), 3);
+        assert_eq!(wrap(-1, 4, WrapMode::Clamp), 0);
+    }
+
+    #[test]
+    fn bilinear_filtering_blends_neighbouring_texels() {
+        let mut texture = texture(2, 1, &[0.0, 1.0]);
+        texture.wrap_u = WrapMode::Clamp;
+
+        assert_eq!(brightness(&texture, (0.25, 0.5), 0.0), 0.0);
+        assert_eq!(brightness(&texture, (0.5, 0.5), 0.0), 0.5);
+        assert_eq!(brightness(&texture, (0.75, 0.5), 0.0), 1.0);
+
+        texture.filter = TextureFilter::Nearest;
+        assert_eq!(brightness(&texture, (0.45, 0.5), 0.0), 0.0);
+    }
+
+    #[test]
+    fn mipmaps_average_down_to_a_single_texel() {
+        let texture = texture(
+            4,
+            3,
+            &[0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0],
+        );
+
+        let sizes: Vec<(u32, u32)> = texture
+            .levels
+            .iter()
+            .map(|level| (*level.width(), *level.height()))
+            .collect();
+
+        assert_eq!(sizes, vec![(4, 3), (2, 1), (1, 1)]);
+        let (r, _, _) = texture.levels[2].get(&Point(0, 0)).as_channels();
+        assert_eq!(cap_float(r), 0.5);
+    }
+
+    #[test]
+    fn large_footprints_look_up_coarser_levels() {
+        let texture = texture(4, 4, &[0.0, 1.0, 0.0, 1.0].repeat(4));
+
+        // a texel wide footprint still sees the stripes, the whole texture only their average
+        assert_eq!(brightness(&texture, (0.375, 0.5), 0.25), 1.0);
+        assert_eq!(brightness(&texture, (0.375, 0.5), 1.0), 0.5);
+        let halfway = brightness(&texture, (0.375, 0.5), 0.35);
+        assert!(halfway > 0.5 && halfway < 1.0, "{}", halfway);
+    }
+
+    #[test]
+    fn uv_transform_tiles_the_texture() {
+        let mut texture = texture(2, 1, &[0.0, 1.0]);
+        texture.filter = TextureFilter::Nearest;
+        texture.transform = UvTransform {
+            scale: (2.0, 1.0),
+            offset: (0.0, 0.0),
+        };
+
+        assert_eq!(brightness(&texture, (0.1, 0.5), 0.0), 0.0);
+        assert_eq!(brightness(&texture, (0.3, 0.5), 0.0), 1.0);
+        assert_eq!(brightness(&texture, (0.6, 0.5), 0.0), 0.0);
+    }
+
+    #[test]
+    fn checker_board_blurs_to_its_average_from_afar() {
+        let checker_board = CheckerBoard::new(Color::WHITE, Color::BLACK);
+        let query = |footprint| TextureQuery {
+            texture_coords: Some((0.01, 0.01)),
+            footprint,
+            ..TextureQuery::default()
+        };
+
+        assert_eq!(checker_board.get(&query(0.0)), Color::WHITE);
+        assert_eq!(checker_board.get(&query(1.0)), Color::new(0.5, 0.5, 0.5));
+    }
+
+    fn encode(image: DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    fn full_resolution(texture: &ImageTexture) -> Vec<Color> {
+        let level = &texture.levels[0];
+        (0..*level.width())
+            .map(|x| level.get(&Point(x, 0)))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_every_png_color_type_to_linear_rgb() {
+        let grey =
+            DynamicImage::ImageLuma8(image::GrayImage::from_raw(2, 1, vec![0, 255]).unwrap());
+        let rgba = DynamicImage::ImageRgba8(
+            image::RgbaImage::from_raw(2, 1, vec![0, 0, 0, 255, 255, 255, 255, 0]).unwrap(),
+        );
+        let deep = DynamicImage::ImageRgb16(
+            image::ImageBuffer::from_raw(2, 1, vec![0, 0, 0, u16::MAX, u16::MAX, u16::MAX])
+                .unwrap(),
+        );
+
+        for image in [grey, rgba, deep] {
+            let texture = ImageTexture::decode(&encode(image, image::ImageFormat::Png)).unwrap();
+
+            assert_eq!(full_resolution(&texture), vec![Color::BLACK, Color::WHITE]);
+        }
+    }
+
+    #[test]
+    fn srgb_values_are_linearized() {
+        let grey = DynamicImage::ImageLuma8(image::GrayImage::from_raw(1, 1, vec![128]).unwrap());
+
+        let texture = ImageTexture::decode(&encode(grey, image::ImageFormat::Png)).unwrap();
+
+        let (r, _, _) = full_resolution(&texture)[0].as_channels();
+        assert_eq!(cap_float(r), 0.2);
+    }
+
+    #[test]
+    fn data_images_are_not_linearized() {
+        let grey = DynamicImage::ImageLuma8(image::GrayImage::from_raw(1, 1, vec![51]).unwrap());
+
+        let texture = ImageTexture::decode_linear(&encode(grey, image::ImageFormat::Png)).unwrap();
+
+        assert_eq!(full_resolution(&texture), vec![Color::new(0.2, 0.2, 0.2)]);
+    }
+
+    #[test]
+    fn high_dynamic_range_formats_keep_their_linear_values() {
+        let texels = vec![4.0, 0.5, 0.25, 0.0, 0.0, 0.0];
+        for format in [image::ImageFormat::Hdr, image::ImageFormat::OpenExr] {
+            let image = DynamicImage::ImageRgb32F(
+                image::Rgb32FImage::from_raw(2, 1, texels.clone()).unwrap(),
+            );
+
+            let texture = ImageTexture::decode(&encode(image, format)).unwrap();
+
+            assert_eq!(
+                full_resolution(&texture),
+                vec![Color::new(4.0, 0.5, 0.25), Color::BLACK],
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn reports_unreadable_images() {
+        let png = encode(
+            DynamicImage::ImageLuma8(image::GrayImage::from_raw(2, 1, vec![0, 255]).unwrap()),
+            image::ImageFormat::Png,
+        );
+
+        assert!(matches!(
+            ImageTexture::decode(b"not an image"),
+            Err(TextureError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            ImageTexture::decode(&png[..png.len() / 2]),
+            Err(TextureError::Malformed(_))
+        ));
+        assert!(matches!(
+            ImageTexture::load("does_not_exist.png"),
+            Err(TextureError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn channel_texture_reads_one_channel() {
+        let channel = |channel| {
+            ChannelTexture {
+                texture: Box::new(SolidColor(Color::new(0.2, 0.4, 0.8))),
+                channel,
+            }
+            .get(&TextureQuery::at(None))
+        };
+
+        assert_eq!(channel(Channel::Red), 0.2);
+        assert_eq!(channel(Channel::Green), 0.4);
+        assert_eq!(channel(Channel::Blue), 0.8);
+        assert_eq!(
+            cap_float(channel(Channel::Luminance)),
+            cap_float(Color::new(0.2, 0.4, 0.8).luminance())
+        );
+    }
+
+    #[test]
+    fn channel_texture_leaves_surfaces_without_texture_coords_alone() {
+        let roughness = ChannelTexture {
+            texture: Box::new(CheckerBoard::new(Color::BLACK, Color::BLACK)),
+            channel: Channel::Green,
+        };
+
+        assert_eq!(roughness.get(&TextureQuery::at(None)), 1.0);
+        assert_eq!(roughness.get(&TextureQuery::at(Some((0.5, 0.5)))), 0.0);
+    }
+}
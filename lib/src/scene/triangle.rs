@@ -1,28 +1,32 @@
+use std::collections::HashMap;
+
 use super::Surface;
 
-use crate::ray_tracing::Intersection;
+use crate::ray_tracing::{Aabb, Intersection};
 use crate::vector_math::Vec3;
 
+// faces meeting at a larger angle than this in degrees leave a hard edge between them
+const CREASE_ANGLE: f32 = 60.0;
+
 #[derive(Debug, PartialEq)]
 pub struct Triangle {
     vertices: Vec<Vec3>,
     normal: Vec3,
+    texture_coords: Option<[(f32, f32); 3]>,
+    vertex_normals: Option<[Vec3; 3]>,
 }
 impl Surface for Triangle {
     fn find_intersection(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<Intersection> {
-        let [a, b, c] = self.as_vertices();
-        if let Some((u, v)) = self.find_barycentric_intersection(ray_origin, ray_direction) {
-            let point_on_triangle = a + (b - a) * u + (c - a) * v;
-            let ray_origin_to_intersection = point_on_triangle - *ray_origin;
-            if ray_origin_to_intersection * *ray_direction > 0.0 {
-                return Some(Intersection {
-                    distance: (point_on_triangle - *ray_origin).magnitude(),
-                    normal: self.normal,
-                    texture_coords: None,
-                });
-            }
-        }
-        None
+        Triangle::find_intersection(self, ray_origin, ray_direction)
+    }
+
+    fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.vertices)
+    }
+
+    fn texture_scale(&self) -> f32 {
+        self.texture_area()
+            .map_or(0.0, |texture_area| (texture_area / self.area()).sqrt())
     }
 }
 
@@ -31,13 +35,60 @@ impl Triangle {
         Triangle {
             vertices: vec![v1, v2, v3],
             normal: (v2 - v1).cross_product(&(v3 - v2)).normalize(),
+            texture_coords: None,
+            vertex_normals: None,
         }
     }
 
+    /// Texture coordinates at the three vertices, interpolated in between.
+    pub fn with_texture_coords(mut self, texture_coords: [(f32, f32); 3]) -> Triangle {
+        self.texture_coords = Some(texture_coords);
+        self
+    }
+
+    /// Normals at the three vertices as a model file gives them.
+    pub fn with_vertex_normals(mut self, vertex_normals: [Vec3; 3]) -> Triangle {
+        self.vertex_normals = Some(vertex_normals.map(Vec3::normalize));
+        self
+    }
+
     pub fn normal(&self) -> Vec3 {
         self.normal
     }
 
+    pub fn vertex_normals(&self) -> Option<[Vec3; 3]> {
+        self.vertex_normals
+    }
+
+    pub fn area(&self) -> f32 {
+        let [a, b, c] = self.as_vertices();
+        (b - a).cross_product(&(c - a)).magnitude() / 2.0
+    }
+
+    /// Area the triangle covers in texture coordinates, if it has any.
+    pub fn texture_area(&self) -> Option<f32> {
+        let [(u1, v1), (u2, v2), (u3, v3)] = self.texture_coords?;
+        Some(((u2 - u1) * (v3 - v1) - (u3 - u1) * (v2 - v1)).abs() / 2.0)
+    }
+
+    // directions along the surface the texture coordinates grow in, any frame along the surface
+    // will do without them
+    fn tangents(&self) -> (Vec3, Vec3) {
+        let [a, b, c] = self.as_vertices();
+        if let Some([(u1, v1), (u2, v2), (u3, v3)]) = self.texture_coords {
+            let (edge_1, edge_2) = (b - a, c - a);
+            let (du_1, dv_1, du_2, dv_2) = (u2 - u1, v2 - v1, u3 - u1, v3 - v1);
+            let determinant = du_1 * dv_2 - du_2 * dv_1;
+            if determinant.abs() > f32::EPSILON {
+                let tangent = (edge_1 * dv_2 - edge_2 * dv_1) * (1.0 / determinant);
+                let bitangent = (edge_2 * du_1 - edge_1 * du_2) * (1.0 / determinant);
+                return (tangent.normalize(), bitangent.normalize());
+            }
+        }
+        let tangent = (b - a).normalize();
+        (tangent, self.normal.cross_product(&tangent))
+    }
+
     pub fn find_barycentric_intersection(
         &self,
         ray_origin: &Vec3,
@@ -65,7 +116,7 @@ impl Triangle {
         let inv_det = 1.0 / det;
         let s = *ray_origin - a;
         let u = inv_det * (s * ray_cross_e2);
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -87,16 +138,54 @@ impl Triangle {
             let point_on_triangle = a + (b - a) * u + (c - a) * v;
             let ray_origin_to_intersection = point_on_triangle - *ray_origin;
             if ray_origin_to_intersection * *ray_direction > 0.0 {
+                let (tangent, bitangent) = self.tangents();
                 return Some(Intersection {
                     distance: (point_on_triangle - *ray_origin).magnitude(),
                     normal: self.normal,
-                    texture_coords: None,
+                    shading_normal: self.shading_normal(u, v),
+                    texture_coords: self.texture_coords.map(|[(u1, v1), (u2, v2), (u3, v3)]| {
+                        let w = 1.0 - u - v;
+                        (u1 * w + u2 * u + u3 * v, v1 * w + v2 * u + v3 * v)
+                    }),
+                    tangent,
+                    bitangent,
                 });
             }
         }
         None
     }
 
+    // vertex normals blended at the barycentric coordinates `u` and `v`, turned to the side the
+    // face normal points to in case the file winds its vertices the other way
+    fn shading_normal(&self, u: f32, v: f32) -> Vec3 {
+        let Some([n1, n2, n3]) = self.vertex_normals else {
+            return self.normal;
+        };
+        let normal = n1 * (1.0 - u - v) + n2 * u + n3 * v;
+        if normal.magnitude() < f32::EPSILON {
+            self.normal
+        } else if normal * self.normal < 0.0 {
+            -normal.normalize()
+        } else {
+            normal.normalize()
+        }
+    }
+
+    // angle the edges enclose at each of the vertices
+    fn corner_angles(&self) -> [f32; 3] {
+        let [a, b, c] = self.as_vertices();
+        let angle = |corner: Vec3, first: Vec3, second: Vec3| {
+            let (first, second) = (first - corner, second - corner);
+            let cosine = first * second / (first.magnitude() * second.magnitude());
+            if cosine.is_finite() {
+                cosine.clamp(-1.0, 1.0).acos()
+            } else {
+                0.0
+            }
+        };
+        [angle(a, b, c), angle(b, c, a), angle(c, a, b)]
+    }
+
     pub fn as_vertices(&self) -> [Vec3; 3] {
         match self.vertices[..] {
             [a, b, c] => [a, b, c],
@@ -105,6 +194,58 @@ impl Triangle {
     }
 }
 
+/// Gives the triangles without vertex normals the average of the face normals around each of
+/// their vertices, weighted by the angle the faces enclose there, so that meshes without normals
+/// of their own are shaded smoothly as well. Vertices at the same position are shared no matter
+/// which triangles they come from, but only faces within `CREASE_ANGLE` of a triangle are
+/// averaged into its normals so that hard edges stay sharp.
+pub fn generate_vertex_normals(triangles: &mut [Triangle]) {
+    // adding zero turns -0 into 0, both are the same position
+    let key = |vertex: Vec3| {
+        let (x, y, z) = vertex.as_coords();
+        [
+            (x + 0.0).to_bits(),
+            (y + 0.0).to_bits(),
+            (z + 0.0).to_bits(),
+        ]
+    };
+    // face normals around every vertex with the angles their faces enclose there
+    let mut faces: HashMap<[u32; 3], Vec<(Vec3, f32)>> = HashMap::new();
+    for triangle in triangles.iter() {
+        for (vertex, angle) in triangle
+            .as_vertices()
+            .into_iter()
+            .zip(triangle.corner_angles())
+        {
+            faces
+                .entry(key(vertex))
+                .or_default()
+                .push((triangle.normal, angle));
+        }
+    }
+
+    let min_cosine = CREASE_ANGLE.to_radians().cos();
+    for triangle in triangles
+        .iter_mut()
+        .filter(|triangle| triangle.vertex_normals.is_none())
+    {
+        let vertex_normals = triangle.as_vertices().map(|vertex| {
+            let normal = faces[&key(vertex)]
+                .iter()
+                .filter(|(face_normal, _)| *face_normal * triangle.normal >= min_cosine)
+                .fold(Vec3::new(0.0, 0.0, 0.0), |sum, (face_normal, angle)| {
+                    sum + *face_normal * *angle
+                });
+            if normal.magnitude() < f32::EPSILON {
+                triangle.normal
+            } else {
+                normal.normalize()
+            }
+        });
+        triangle.vertex_normals = Some(vertex_normals);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -138,7 +279,10 @@ mod test {
             Some(Intersection {
                 distance: 1.0,
                 normal: Vec3::new(0.0, 0.0, 1.0),
-                texture_coords: None
+                shading_normal: Vec3::new(0.0, 0.0, 1.0),
+                texture_coords: None,
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                bitangent: Vec3::new(0.0, 1.0, 0.0),
             }),
             intersection
         );
@@ -159,7 +303,10 @@ mod test {
             Some(Intersection {
                 distance: 2.0,
                 normal: Vec3::new(0.0, 0.0, 1.0),
-                texture_coords: None
+                shading_normal: Vec3::new(0.0, 0.0, 1.0),
+                texture_coords: None,
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                bitangent: Vec3::new(0.0, 1.0, 0.0),
             }),
             intersection
         );
@@ -192,4 +339,121 @@ mod test {
 
         assert_eq!(None, intersection);
     }
+    #[test]
+    fn shading_normal_is_interpolated_between_the_vertex_normals() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        )
+        .with_vertex_normals([
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ]);
+
+        let intersection = triangle
+            .find_intersection(&Vec3::new(0.5, 0.5, 1.0), &Vec3::new(0.0, 0.0, -1.0))
+            .unwrap();
+
+        // halfway between the normals of the second and the third vertex
+        let expected = Vec3::new(1.0, 1.0, 2.0).normalize();
+        assert!((intersection.shading_normal - expected).magnitude() < 1e-5);
+        assert_eq!(intersection.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn shading_normal_stays_on_the_side_of_the_face() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        )
+        .with_vertex_normals([Vec3::new(0.0, 0.0, -1.0); 3]);
+
+        let intersection = triangle
+            .find_intersection(&Vec3::new(0.2, 0.2, 1.0), &Vec3::new(0.0, 0.0, -1.0))
+            .unwrap();
+
+        assert_eq!(intersection.shading_normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn generated_normals_are_weighted_by_the_angles_at_the_vertices() {
+        use std::f32::consts::FRAC_PI_2;
+
+        // a face tilted by 45 degrees folded against two faces with normal z along the y axis
+        let tilted = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let mut triangles = [
+            Triangle::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            Triangle::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(-1.0, 0.0, 1.0),
+            ),
+            Triangle::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(2.0, 1.0, 0.0),
+            )
+            .with_vertex_normals([Vec3::new(0.0, 1.0, 0.0); 3]),
+        ];
+
+        generate_vertex_normals(&mut triangles);
+
+        let [origin, far_corner, shared] = triangles[0].vertex_normals().unwrap();
+        // both folded faces enclose a right angle at the origin
+        let expected = (Vec3::new(0.0, 0.0, 1.0) + tilted).normalize();
+        assert!((origin - expected).magnitude() < 1e-5);
+        // at (0, 1, 0) the faces with normal z enclose a right angle together, the tilted one
+        // a little more than half of one. The last face counts even though it keeps its own
+        // normals.
+        let tilted_angle = (1.0 / 3.0f32.sqrt()).acos();
+        let expected = (Vec3::new(0.0, 0.0, FRAC_PI_2) + tilted * tilted_angle).normalize();
+        assert!((shared - expected).magnitude() < 1e-5);
+        assert!((far_corner - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        assert_eq!(
+            triangles[2].vertex_normals(),
+            Some([Vec3::new(0.0, 1.0, 0.0); 3])
+        );
+    }
+
+    #[test]
+    fn generated_normals_keep_the_edges_of_a_cube_sharp() {
+        // corner `idx` has its x, y and z coordinates in its lowest three bits
+        let corner = |idx: usize| {
+            Vec3::new(
+                (idx & 1) as f32,
+                (idx >> 1 & 1) as f32,
+                (idx >> 2 & 1) as f32,
+            )
+        };
+        // every side wound counterclockwise seen from outside
+        let sides = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let mut triangles: Vec<Triangle> = sides
+            .iter()
+            .map(|side| side.map(corner))
+            .flat_map(|[a, b, c, d]| [Triangle::new(a, b, c), Triangle::new(a, c, d)])
+            .collect();
+
+        generate_vertex_normals(&mut triangles);
+
+        for triangle in &triangles {
+            let outwards = triangle.normal;
+            let center = Vec3::new(0.5, 0.5, 0.5);
+            assert!(outwards * (triangle.as_vertices()[0] - center) > 0.0);
+            assert_eq!(triangle.vertex_normals(), Some([outwards; 3]));
+        }
+    }
 }
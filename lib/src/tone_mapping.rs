@@ -0,0 +1,166 @@
+use crate::buffer::{Buffer, Point};
+use crate::color::{linear_to_srgb, Color};
+
+/// Curve compressing the unbounded scene radiance into the 0..1 range of the display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    /// anything above 1.0 is simply cut off
+    Clamp,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Hable's filmic curve, `white_point` is the radiance that ends up as pure white. Build it
+    /// with `ToneMapping::uncharted2` to have the white point checked.
+    Uncharted2 {
+        white_point: f32,
+    },
+}
+
+impl ToneMapping {
+    /// `None` unless `white_point` is a positive number, every pixel would come out as NaN
+    /// otherwise.
+    pub fn uncharted2(white_point: f32) -> Option<ToneMapping> {
+        (white_point.is_finite() && white_point > 0.0)
+            .then_some(ToneMapping::Uncharted2 { white_point })
+    }
+
+    pub fn map(&self, value: f32) -> f32 {
+        let mapped = match *self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (1.0 + value),
+            ToneMapping::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+            ToneMapping::Uncharted2 { white_point } => {
+                uncharted2_curve(value) / uncharted2_curve(white_point)
+            }
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+fn uncharted2_curve(value: f32) -> f32 {
+    const SHOULDER_STRENGTH: f32 = 0.15;
+    const LINEAR_STRENGTH: f32 = 0.5;
+    const LINEAR_ANGLE: f32 = 0.1;
+    const TOE_STRENGTH: f32 = 0.2;
+    const TOE_NUMERATOR: f32 = 0.02;
+    const TOE_DENOMINATOR: f32 = 0.3;
+    ((value * (SHOULDER_STRENGTH * value + LINEAR_ANGLE * LINEAR_STRENGTH)
+        + TOE_STRENGTH * TOE_NUMERATOR)
+        / (value * (SHOULDER_STRENGTH * value + LINEAR_STRENGTH) + TOE_STRENGTH * TOE_DENOMINATOR))
+        - TOE_NUMERATOR / TOE_DENOMINATOR
+}
+
+/// Output transform applied to the rendered buffer before it is written out:
+/// exposure, tone mapping and sRGB encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessing {
+    /// in stops, every +1 doubles the brightness
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for PostProcessing {
+    fn default() -> PostProcessing {
+        PostProcessing {
+            exposure: 0.0,
+            tone_mapping: ToneMapping::Clamp,
+        }
+    }
+}
+
+impl PostProcessing {
+    /// Display referred color with channels in 0..1, still linear.
+    pub fn apply(&self, color: Color) -> Color {
+        let exposure_scale = 2.0_f32.powf(self.exposure);
+        color.map(|channel| self.tone_mapping.map(channel * exposure_scale))
+    }
+
+    /// sRGB encoded 8-bit color ready to be shown.
+    pub fn to_srgb_bytes(&self, color: Color) -> (u8, u8, u8) {
+        self.apply(color).map(linear_to_srgb).to_bytes()
+    }
+
+    /// 8-bit sRGB rows of the whole buffer, ready to be handed to an image encoder.
+    pub fn encode(&self, buffer: &Buffer) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity((buffer.width() * buffer.height() * 3) as usize);
+        for y in 0..*buffer.height() {
+            for x in 0..*buffer.width() {
+                let (r, g, b) = self.to_srgb_bytes(buffer.get(&Point(x, y)));
+                bytes.extend([r, g, b]);
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::Dimensions;
+
+    const OPERATORS: [ToneMapping; 4] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::Aces,
+        ToneMapping::Uncharted2 { white_point: 11.2 },
+    ];
+
+    #[test]
+    fn operators_are_monotonic_and_stay_in_display_range() {
+        for operator in OPERATORS {
+            let mut previous = operator.map(0.0);
+            assert!(previous.abs() < 1e-2, "{:?}", operator);
+            for step in 1..200 {
+                let value = operator.map(step as f32 * 0.1);
+                assert!(value >= previous && value <= 1.0, "{:?}", operator);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn uncharted2_maps_white_point_to_white() {
+        let operator = ToneMapping::uncharted2(4.0).unwrap();
+
+        assert!((operator.map(4.0) - 1.0).abs() < 1e-5);
+        assert!(operator.map(2.0) < 1.0);
+    }
+
+    #[test]
+    fn uncharted2_needs_a_positive_white_point() {
+        for white_point in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(
+                ToneMapping::uncharted2(white_point),
+                None,
+                "{}",
+                white_point
+            );
+        }
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let post_processing = PostProcessing {
+            exposure: 1.0,
+            tone_mapping: ToneMapping::Clamp,
+        };
+
+        assert_eq!(
+            post_processing.apply(Color::new(0.25, 0.1, 2.0)),
+            Color::new(0.5, 0.2, 1.0)
+        );
+    }
+
+    #[test]
+    fn encodes_buffer_as_srgb() {
+        let mut buffer = Buffer::new(Dimensions(2, 1), 3);
+        buffer.set(&Point(0, 0), &Color::new(0.214, 1.0, 0.0));
+        buffer.set(&Point(1, 0), &Color::new(5.0, 0.0, 0.0));
+
+        let bytes = PostProcessing::default().encode(&buffer);
+
+        assert_eq!(bytes, vec![127, 255, 0, 255, 0, 0]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, Mul, Neg, Sub};
+use std::ops::{Add, Index, Mul, Neg, Sub};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec3 {
@@ -51,6 +51,13 @@ impl Neg for Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(&self, axis: usize) -> &f32 {
+        &self.values[axis]
+    }
+}
+
 impl Vec3 {
     pub const fn new(x: f32, y: f32, z: f32) -> Vec3 {
         Vec3 { values: [x, y, z] }
@@ -76,22 +83,42 @@ impl Vec3 {
     }
 
     pub fn reflection(self, normal: &Vec3) -> Vec3 {
-        let normal = normal.clone().normalize();
+        let normal = normal.normalize();
         let normalized_self = self.normalize();
         normal * (2.0 * (normalized_self * normal)) - normalized_self
     }
 
+    /// Direction of the ray bent through the boundary between two media, `normal` facing the
+    /// incoming ray. `None` on total internal reflection.
     pub fn refraction(
         self,
         normal: &Vec3,
         current_refraction_index: f32,
         next_refraction_index: f32,
-    ) -> Vec3 {
+    ) -> Option<Vec3> {
         let normal = normal.normalize();
         let normalized_self = self.normalize();
         let r = current_refraction_index / next_refraction_index;
         let c = -normal * normalized_self;
-        (self * r + normal * (r * c - f32::sqrt(1.0 - r.powi(2) * (1.0 - c.powi(2))))).normalize()
+        let k = 1.0 - r.powi(2) * (1.0 - c.powi(2));
+        if k < 0.0 {
+            return None;
+        }
+        Some((normalized_self * r + normal * (r * c - k.sqrt())).normalize())
+    }
+
+    pub fn component_min(self, rhs: &Vec3) -> Vec3 {
+        let [x1, y1, z1] = self.values;
+        let [x2, y2, z2] = rhs.values;
+
+        Vec3::new(x1.min(x2), y1.min(y2), z1.min(z2))
+    }
+
+    pub fn component_max(self, rhs: &Vec3) -> Vec3 {
+        let [x1, y1, z1] = self.values;
+        let [x2, y2, z2] = rhs.values;
+
+        Vec3::new(x1.max(x2), y1.max(y2), z1.max(z2))
     }
 
     pub fn cross_product(self, rhs: &Vec3) -> Vec3 {
@@ -100,6 +127,19 @@ impl Vec3 {
 
         Vec3::new(y1 * z2 - z1 * y2, z1 * x2 - x1 * z2, x1 * y2 - y1 * x2)
     }
+
+    /// Two unit vectors that together with the normalized `self` form an orthonormal basis.
+    pub fn perpendiculars(self) -> (Vec3, Vec3) {
+        let normal = self.normalize();
+        let helper = if normal[0].abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = normal.cross_product(&helper).normalize();
+        let bitangent = normal.cross_product(&tangent);
+        (tangent, bitangent)
+    }
 }
 
 #[cfg(test)]
@@ -135,6 +175,19 @@ mod test {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn perpendiculars_form_orthonormal_basis() {
+        for vector in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.2, -3.0, 0.5)] {
+            let (tangent, bitangent) = vector.perpendiculars();
+
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-6);
+            assert!((bitangent.magnitude() - 1.0).abs() < 1e-6);
+            assert!((tangent * vector).abs() < 1e-6);
+            assert!((bitangent * vector).abs() < 1e-6);
+            assert!((tangent * bitangent).abs() < 1e-6);
+        }
+    }
+
     #[test]
     fn magnitude() {
         let result = Vec3::new(-10.0, 0.0, 0.0).magnitude();
@@ -159,15 +212,34 @@ mod test {
     fn refraction() {
         let refracted_vector =
             Vec3::new(1.0, -1.0, 0.0).refraction(&Vec3::new(0.0, 1.0, 0.0), 1.0, 1.1);
-        let [x, y, z] = refracted_vector.values;
+        let [x, y, z] = refracted_vector.unwrap().values;
         assert_eq!(
             Vec3 {
                 values: [cap_float(x), cap_float(y), cap_float(z)]
             },
-            Vec3::new(0.7, -0.8, 0.0)
+            // sin 45° / 1.1 = 0.64
+            Vec3::new(0.6, -0.8, 0.0)
         );
     }
 
+    #[test]
+    fn refraction_past_critical_angle_is_total_internal_reflection() {
+        // leaving glass at 60 degrees, the critical angle is about 42
+        let refracted_vector =
+            Vec3::new(3f32.sqrt(), -1.0, 0.0).refraction(&Vec3::new(0.0, 1.0, 0.0), 1.5, 1.0);
+
+        assert_eq!(refracted_vector, None);
+    }
+
+    #[test]
+    fn component_min_and_max() {
+        let v1 = Vec3::new(1.0, -2.0, 3.0);
+        let v2 = Vec3::new(-1.0, 2.0, 3.5);
+
+        assert_eq!(v1.component_min(&v2), Vec3::new(-1.0, -2.0, 3.0));
+        assert_eq!(v1.component_max(&v2), Vec3::new(1.0, 2.0, 3.5));
+    }
+
     #[test]
     fn cross_product() {
         let cross_product = Vec3::new(1.0, 0.0, 0.0).cross_product(&Vec3::new(0.0, 1.0, 0.0));
@@ -4,14 +4,26 @@ use video_rs::encode::{Encoder, Settings};
 use video_rs::time::Time;
 
 use std::path::Path;
+use std::sync::Arc;
 
 use lib::buffer::{Buffer, Dimensions, Point};
+use lib::camera::Camera;
+use lib::integrator::Whitted;
+use lib::sampling::{Filter, SamplePattern};
+use lib::scene::Scene;
+use lib::tone_mapping::{PostProcessing, ToneMapping};
 use lib::vector_math::Vec3;
+use lib::RenderSettings;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1024;
 
 fn main() {
     video_rs::init().unwrap();
 
-    let settings = Settings::preset_h264_yuv420p(lib::WIDTH as usize, lib::HEIGHT as usize, false);
+    let scene = Arc::new(Scene::load("scene.toml").expect("failed to load scene"));
+
+    let settings = Settings::preset_h264_yuv420p(WIDTH as usize, HEIGHT as usize, false);
     let mut encoder =
         Encoder::new(Path::new("output.mp4"), settings).expect("failed to create encoder");
 
@@ -26,7 +38,8 @@ fn main() {
             f32::sin(angle.to_radians()) * 12.0,
         );
         let camera_position = Vec3::new(x, 1.0, z);
-        let frame = generate_frame(look_at + camera_position, -camera_position);
+        let camera = Camera::new(look_at + camera_position, look_at, WIDTH, HEIGHT);
+        let frame = generate_frame(&scene, &camera);
 
         encoder
             .encode(&frame, position)
@@ -39,14 +52,15 @@ fn main() {
     encoder.finish().expect("failed to finish encoder");
 }
 
-fn generate_frame(camera_position: Vec3, camera_direction: Vec3) -> Array3<u8> {
-    let mut buffer = Buffer::new(Dimensions(lib::WIDTH, lib::HEIGHT), lib::CHANNELS);
-    let mut frame = Array3::zeros((lib::HEIGHT as usize, lib::WIDTH as usize, 3));
+fn generate_frame(scene: &Arc<Scene>, camera: &Camera) -> Array3<u8> {
+    let mut buffer = Buffer::new(Dimensions(WIDTH, HEIGHT), lib::CHANNELS);
+    let mut frame = Array3::zeros((HEIGHT as usize, WIDTH as usize, 3));
 
-    lib::draw(&mut buffer, camera_position, camera_direction);
-    for x in 0..lib::WIDTH {
-        for y in 0..lib::HEIGHT {
-            let rgb = buffer.get(&Point(x, y)).as_bites();
+    lib::draw(&mut buffer, scene, camera, &render_settings());
+    let post_processing = post_processing();
+    for x in 0..WIDTH {
+        for y in 0..HEIGHT {
+            let rgb = post_processing.to_srgb_bytes(buffer.get(&Point(x, y)));
             let (x, y) = (x as usize, y as usize);
             frame[[y, x, 0]] = rgb.0;
             frame[[y, x, 1]] = rgb.1;
@@ -55,3 +69,22 @@ fn generate_frame(camera_position: Vec3, camera_direction: Vec3) -> Array3<u8> {
     }
     frame
 }
+
+fn render_settings() -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 4,
+        sample_pattern: SamplePattern::Sobol,
+        filter: Filter::Gaussian {
+            radius: 1.5,
+            alpha: 2.0,
+        },
+        integrator: Arc::new(Whitted::default()),
+    }
+}
+
+fn post_processing() -> PostProcessing {
+    PostProcessing {
+        exposure: 0.0,
+        tone_mapping: ToneMapping::Aces,
+    }
+}